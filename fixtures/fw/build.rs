@@ -0,0 +1,5 @@
+fn main() {
+    // `memory.x` for cortex-m-rt
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rustc-link-search={}", dir);
+}
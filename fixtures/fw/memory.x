@@ -0,0 +1,3 @@
+MEMORY { FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+RAM : ORIGIN = 0x20000000, LENGTH = 64K }
+SECTIONS { .note.gnu.build-id : { KEEP(*(.note.gnu.build-id)) } > FLASH } INSERT AFTER .rodata;
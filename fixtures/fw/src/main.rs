@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+use cortex_m_rt::entry;
+
+#[defmt::global_logger]
+struct Logger;
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(bytes: &[u8]) {
+        for b in bytes {
+            core::ptr::write_volatile(0xE000_0000 as *mut u8, *b);
+        }
+    }
+}
+defmt::timestamp!("{=u32:us}", unsafe {
+    core::ptr::read_volatile(0xE000_1004 as *const u32)
+});
+
+mod net {
+    pub fn poll(x: u32) {
+        defmt::debug!("poll {}", x);
+        defmt::trace!("tick");
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    defmt::info!("boot");
+    defmt::println!("build-id: {=str}", "abc");
+    let mut i = 0u32;
+    loop {
+        defmt::info!("temp={} vbat={}", 21.5f32, 3.7f32);
+        defmt::warn!("low battery {=u8}", 3u8);
+        defmt::error!("fault code {=u16:x}", 0xbeef);
+        defmt::info!("buf {=[u8]}", &[1u8, 2, 3, 0xff][..]);
+        net::poll(i);
+        i += 1;
+    }
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
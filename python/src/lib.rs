@@ -0,0 +1,239 @@
+//! Python bindings: the frames of a capture and the reads of a timestamped one.
+//!
+//! ```python
+//! import defmt_listener
+//!
+//! frames = defmt_listener.decode("capture.bin", elf="app.elf")
+//! for frame in frames:
+//!     print(frame.timestamp, frame.level, frame.message)
+//! print(frames.corrupted)
+//! ```
+
+use defmt_decoder::{DecodeError, Encoding, Locations, Table};
+use listener::{capture, elf, fetch, frames::FrameDecoder, itm::ItmPacket, Error};
+use pyo3::{
+    exceptions::{PyOSError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use std::{collections::VecDeque, io::BufRead, mem, path::PathBuf};
+
+/// A decoded defmt frame.
+#[pyclass(frozen, get_all, module = "defmt_listener")]
+struct Frame {
+    /// Index of the log site in the defmt table
+    index: u64,
+    /// `None` for `println`
+    level: Option<String>,
+    /// As formatted by the firmware, `None` without a timestamp
+    timestamp: Option<String>,
+    message: String,
+    file: Option<String>,
+    line: Option<u32>,
+    module: Option<String>,
+    /// Seconds since the Unix epoch at which the host received the frame, for timestamped captures
+    host_time: Option<f64>,
+    /// Encoded bytes of the frame
+    size: usize,
+}
+
+#[pymethods]
+impl Frame {
+    fn __repr__(&self) -> String {
+        format!(
+            "Frame(index={}, level={}, timestamp={}, message={:?})",
+            self.index,
+            repr(&self.level),
+            repr(&self.timestamp),
+            self.message
+        )
+    }
+}
+
+fn repr(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("{:?}", value),
+        None => "None".to_string(),
+    }
+}
+
+/// The frames of `capture` on stimulus `port`, raw or timestamped, decoded with `elf`, by default
+/// the ELF embedded in the capture. The capture is read as the frames are iterated.
+#[pyfunction]
+#[pyo3(signature = (capture, elf=None, port=0))]
+fn decode(capture: PathBuf, elf: Option<PathBuf>, port: u8) -> PyResult<Frames> {
+    let opened = capture::open_replay(&capture, 0.0, None).map_err(error)?;
+    let bytes = match elf {
+        Some(elf) => fetch::read_elf(&elf).map_err(error)?,
+        None => opened
+            .header
+            .as_ref()
+            .and_then(|header| header.elf(port))
+            .map(|elf| elf.to_vec())
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "{} embeds no ELF for port {}, pass `elf=`",
+                    capture.display(),
+                    port
+                ))
+            })?,
+    };
+    let table = elf::parse_table(&bytes).map_err(error)?;
+    let locs = table
+        .get_locations(&bytes)
+        .map_err(|err| PyValueError::new_err(format!("{:#}", err)))?;
+
+    let input = match opened.timestamped {
+        true => Input::Records(capture::open_records(&capture).map_err(error)?.0),
+        false => Input::Raw(capture::open(&capture).map_err(error)?),
+    };
+    let mut itm_packet = ItmPacket::new();
+    itm_packet.quiet = true;
+
+    Ok(Frames {
+        input,
+        decoder: Decoder {
+            table,
+            locs,
+            port,
+            itm_packet,
+            pending: Vec::new(),
+            decoded: VecDeque::new(),
+        },
+    })
+}
+
+/// The frames of a capture, decoded as they're iterated.
+#[pyclass(unsendable, module = "defmt_listener")]
+struct Frames {
+    input: Input,
+    decoder: Decoder,
+}
+
+enum Input {
+    /// Of a timestamped capture
+    Records(capture::Records),
+    Raw(Box<dyn BufRead>),
+}
+
+#[pymethods]
+impl Frames {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<Frame>> {
+        while self.decoder.decoded.is_empty() {
+            match &mut self.input {
+                Input::Records(records) => match records.record()? {
+                    Some((micros, bytes)) => {
+                        self.decoder.received(bytes, Some(micros as f64 / 1e6))
+                    }
+                    None => break,
+                },
+                Input::Raw(source) => {
+                    let chunk = source.fill_buf()?;
+                    if chunk.is_empty() {
+                        break;
+                    }
+                    let len = chunk.len();
+                    self.decoder.received(chunk, None);
+                    source.consume(len);
+                }
+            }
+        }
+        Ok(self.decoder.decoded.pop_front())
+    }
+
+    /// Regions of a timestamped capture whose checksums didn't match, as `(start, end)` offsets in
+    /// the capture after decompression. Their frames are skipped; complete once iterated to the end.
+    #[getter]
+    fn corrupted(&self) -> Vec<(u64, u64)> {
+        match &self.input {
+            Input::Records(records) => (records.corrupted().into_iter())
+                .map(|region| (region.start, region.end))
+                .collect(),
+            Input::Raw(_) => Vec::new(),
+        }
+    }
+}
+
+/// The reads of the timestamped `capture`, as (seconds since the Unix epoch, bytes), to replay them
+/// with their original timing.
+#[pyfunction]
+fn records(py: Python<'_>, capture: PathBuf) -> PyResult<Vec<(f64, Py<PyBytes>)>> {
+    let (mut records, _) = capture::open_records(&capture).map_err(error)?;
+    let mut reads = Vec::new();
+    while let Some((micros, bytes)) = records.record()? {
+        reads.push((micros as f64 / 1e6, PyBytes::new(py, bytes).unbind()));
+    }
+    Ok(reads)
+}
+
+/// The frames decoded and not yet iterated.
+struct Decoder {
+    table: Table,
+    locs: Locations,
+    port: u8,
+    itm_packet: ItmPacket,
+    /// Bytes of an incomplete frame; the frame decoder borrows the table so it lives for a read
+    pending: Vec<u8>,
+    decoded: VecDeque<Frame>,
+}
+
+impl Decoder {
+    fn received(&mut self, bytes: &[u8], host_time: Option<f64>) {
+        let mut frames = FrameDecoder::new(&self.table);
+        frames.received(&mem::take(&mut self.pending));
+        for packet in self.itm_packet.packets(bytes) {
+            if packet.port != self.port {
+                continue;
+            }
+            frames.received(packet.payload());
+
+            loop {
+                match frames.decode() {
+                    Ok((frame, size)) => {
+                        let loc = self.locs.get(&frame.index());
+                        self.decoded.push_back(Frame {
+                            index: frame.index(),
+                            level: frame.level().map(|level| level.as_str().to_string()),
+                            timestamp: frame.display_timestamp().map(|ts| ts.to_string()),
+                            message: frame.display_message().to_string(),
+                            file: loc.map(|loc| loc.file.display().to_string()),
+                            line: loc.map(|loc| loc.line as u32),
+                            module: loc.map(|loc| loc.module.clone()),
+                            host_time,
+                            size,
+                        })
+                    }
+                    Err(DecodeError::UnexpectedEof) => break,
+                    // malformed rzCOBS frames are skipped by `decode`
+                    Err(DecodeError::Malformed) => {
+                        if matches!(self.table.encoding(), Encoding::Raw) && frames.resync() == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending = frames.into_pending();
+    }
+}
+
+/// I/O errors as `OSError`, the others as `ValueError`.
+fn error(err: Error) -> PyErr {
+    match err {
+        Error::Transport(err) => PyOSError::new_err(err.to_string()),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
+
+#[pymodule]
+fn defmt_listener(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Frame>()?;
+    module.add_class::<Frames>()?;
+    module.add_function(wrap_pyfunction!(decode, module)?)?;
+    module.add_function(wrap_pyfunction!(records, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! `--alarm`, limits on the numeric values extracted from the messages as by `--field`.
+
+use std::fmt;
+
+use crate::telemetry::{Extractor, Field};
+
+const OPERATORS: [(&str, Op); 6] = [
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("==", Op::Eq),
+    ("!=", Op::Ne),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// A condition on a value, e.g. `vbat<3.3`, tripping the alarm while it holds.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    name: String,
+    op: Op,
+    limit: f64,
+    /// As given
+    text: String,
+}
+
+pub fn parse_alarm(text: &str) -> Result<Rule, String> {
+    let invalid = || {
+        format!(
+            "invalid alarm `{}`, expected `<name><op><limit>` with `<`, `<=`, `>`, `>=`, `==` or `!=`, e.g. `vbat<3.3`",
+            text
+        )
+    };
+    let (at, operator, op) = OPERATORS
+        .iter()
+        .filter_map(|&(operator, op)| Some((text.find(operator)?, operator, op)))
+        .min_by_key(|&(at, operator, _)| (at, usize::MAX - operator.len()))
+        .ok_or_else(invalid)?;
+    let name = text[..at].trim();
+    let limit = text[at + operator.len()..].trim();
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let limit = limit
+        .parse()
+        .map_err(|_| format!("invalid limit `{}` of alarm `{}`", limit, text))?;
+    Ok(Rule {
+        name: name.to_string(),
+        op,
+        limit,
+        text: text.to_string(),
+    })
+}
+
+impl Rule {
+    fn holds(&self, value: f64) -> bool {
+        match self.op {
+            Op::Lt => value < self.limit,
+            Op::Le => value <= self.limit,
+            Op::Gt => value > self.limit,
+            Op::Ge => value >= self.limit,
+            Op::Eq => value == self.limit,
+            Op::Ne => value != self.limit,
+        }
+    }
+}
+
+/// A rule starting or ceasing to hold for a value.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// The rule as given
+    pub rule: String,
+    pub name: String,
+    pub value: f64,
+    /// Whether the rule holds now, `false` once the alarm cleared
+    pub tripped: bool,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}={})", self.rule, self.name, self.value)
+    }
+}
+
+/// The alarms of a session, each reported once when its value crosses the limit and again once it
+/// clears.
+pub struct Alarms {
+    rules: Vec<Rule>,
+    extractor: Extractor,
+    /// Whether each rule holds for the last value
+    tripped: Vec<bool>,
+    /// Times an alarm tripped
+    count: usize,
+}
+
+impl Alarms {
+    pub fn new(rules: Vec<Rule>, fields: Vec<Field>) -> Self {
+        Alarms {
+            tripped: vec![false; rules.len()],
+            rules,
+            extractor: Extractor::new(fields),
+            count: 0,
+        }
+    }
+
+    /// Checks the values of `message`, returning the alarms it trips or clears.
+    pub fn check(&mut self, message: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (name, value) in self.extractor.extract(message) {
+            for (i, rule) in self.rules.iter().enumerate() {
+                if rule.name != name {
+                    continue;
+                }
+                let holds = rule.holds(value);
+                if holds != self.tripped[i] {
+                    self.count += usize::from(holds);
+                    events.push(Event {
+                        rule: rule.text.clone(),
+                        name: name.to_string(),
+                        value,
+                        tripped: holds,
+                    });
+                }
+                self.tripped[i] = holds;
+            }
+        }
+        events
+    }
+
+    /// Times an alarm tripped in the session.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> (String, Op, f64) {
+        let rule = parse_alarm(text).unwrap();
+        (rule.name, rule.op, rule.limit)
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(rule("vbat<3.3"), ("vbat".into(), Op::Lt, 3.3));
+        assert_eq!(rule("vbat>3.3"), ("vbat".into(), Op::Gt, 3.3));
+        assert_eq!(rule("temp==20"), ("temp".into(), Op::Eq, 20.0));
+        assert_eq!(rule("temp!=20"), ("temp".into(), Op::Ne, 20.0));
+        assert_eq!(rule(" temp > -5 "), ("temp".into(), Op::Gt, -5.0));
+    }
+
+    #[test]
+    fn the_longer_operator_wins_at_the_same_position() {
+        assert_eq!(rule("vbat<=3.3"), ("vbat".into(), Op::Le, 3.3));
+        assert_eq!(rule("vbat>=3.3"), ("vbat".into(), Op::Ge, 3.3));
+    }
+
+    #[test]
+    fn the_first_operator_wins() {
+        // `<` before the `==` in its limit, which is then invalid
+        assert!(parse_alarm("a<b==1").is_err());
+        assert_eq!(rule("a<=-1"), ("a".into(), Op::Le, -1.0));
+    }
+
+    #[test]
+    fn invalid_alarms() {
+        for text in ["vbat", "<3.3", "vbat<", "vbat<low", "=3.3", ""] {
+            assert!(parse_alarm(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn holds() {
+        let rule = parse_alarm("vbat<=3.3").unwrap();
+        assert!(rule.holds(3.3) && rule.holds(3.0) && !rule.holds(3.4));
+        let rule = parse_alarm("vbat<3.3").unwrap();
+        assert!(!rule.holds(3.3) && rule.holds(3.0));
+    }
+
+    #[test]
+    fn alarms_trip_and_clear_once() {
+        let mut alarms = Alarms::new(vec![parse_alarm("vbat<3.3").unwrap()], Vec::new());
+        let tripped = |events: Vec<Event>| events.iter().map(|e| e.tripped).collect::<Vec<_>>();
+        assert!(alarms.check("vbat=3.5").is_empty());
+        assert_eq!(tripped(alarms.check("vbat=3.1 temp=20")), [true]);
+        assert!(alarms.check("vbat=3.0").is_empty());
+        assert!(alarms.check("temp=21").is_empty());
+        assert_eq!(tripped(alarms.check("vbat=3.4")), [false]);
+        assert_eq!(tripped(alarms.check("vbat=2.9")), [true]);
+        assert_eq!(alarms.count(), 2);
+    }
+}
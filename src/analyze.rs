@@ -0,0 +1,115 @@
+use std::{collections::BTreeMap, env, path::PathBuf};
+
+use crate::{
+    bandwidth::{self, Site},
+    corrupted_warning,
+    elf::{self, DefmtSection},
+    fetch, frames,
+    replay::{self, Clock},
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnalyzeArgs {
+    /// Path or `http(s)://` URL of the ELF file
+    #[arg(long)]
+    elf: PathBuf,
+    /// ITM stimulus port carrying the defmt data
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Frequency of the device timestamp in Hz, for timestamps without a unit
+    #[arg(long)]
+    tick_rate: Option<f64>,
+    /// Length of the timeline buckets in seconds
+    #[arg(long, default_value_t = 1.0, value_parser = parse_interval)]
+    interval: f64,
+    /// Threads decoding the capture, by default one per CPU
+    #[arg(long, short)]
+    jobs: Option<usize>,
+    /// Capture, e.g. written with `--capture`
+    capture: PathBuf,
+}
+
+fn parse_interval(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(interval) if interval > 0.0 && interval.is_finite() => Ok(interval),
+        _ => Err(format!(
+            "invalid interval `{}`, expected a number of seconds above 0, e.g. `0.5`",
+            text
+        )),
+    }
+}
+
+pub fn run(args: AnalyzeArgs) -> anyhow::Result<()> {
+    let bytes = fetch::read_elf(&args.elf)?;
+    let table = elf::parse_table(&bytes)?;
+    let locs = table.get_locations(&bytes)?;
+    let section = DefmtSection::parse(&bytes)?;
+    let current_dir = env::current_dir()?;
+
+    let mut sites = BTreeMap::<u64, Site>::new();
+    let mut timeline = BTreeMap::<u64, Site>::new();
+    let mut clock = Clock::default();
+
+    let stats = frames::decode_capture_parallel(
+        &args.capture,
+        args.port,
+        &table,
+        frames::jobs(args.jobs),
+        |frame, size| {
+            let time = frame
+                .display_timestamp()
+                .and_then(|ts| replay::parse_timestamp(&ts.to_string(), args.tick_rate));
+            (frame.index(), size, time)
+        },
+        |(index, size, time)| {
+            let site = sites.entry(index).or_default();
+            site.frames += 1;
+            site.bytes += size;
+
+            // the clock follows the frames in capture order
+            if let Some(time) = time.map(|time| clock.advance(time)) {
+                let start = clock.start().unwrap_or(time);
+                let bucket = ((time - start) / args.interval) as u64;
+                let slot = timeline.entry(bucket).or_default();
+                slot.frames += 1;
+                slot.bytes += size;
+            }
+        },
+    )?;
+    let (total, malformed) = (stats.bytes, stats.malformed);
+    for region in &stats.corrupted {
+        println!("{}", corrupted_warning(region));
+    }
+
+    let frames: usize = sites.values().map(|site| site.frames).sum();
+    let elapsed = clock.elapsed().filter(|&elapsed| elapsed > 0.0);
+    match elapsed {
+        Some(elapsed) => println!(
+            "{} bytes, {} frames ({} malformed) over {:.3}s of device time",
+            total, frames, malformed, elapsed
+        ),
+        None => println!(
+            "{} bytes, {} frames ({} malformed), no device time to compute rates",
+            total, frames, malformed
+        ),
+    }
+    println!();
+
+    let labels = bandwidth::labels(&section, Some(&locs), &current_dir);
+    bandwidth::print_sites(&sites, elapsed, &labels);
+
+    if !timeline.is_empty() {
+        println!();
+        println!("timeline ({}s buckets):", args.interval);
+        for (bucket, slot) in timeline {
+            println!(
+                "{:>10.1}s {:>7} frames {:>8} bytes",
+                bucket as f64 * args.interval,
+                slot.frames,
+                slot.bytes
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use defmt_decoder::Locations;
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::elf::{self, DefmtSection};
+
+/// Number of log sites in the periodic `--bandwidth-interval` report
+const TOP_SITES: usize = 5;
+
+/// Frames and bytes on the wire of a log site.
+#[derive(Default)]
+pub struct Site {
+    pub frames: usize,
+    pub bytes: usize,
+}
+
+/// Description of a log site for the reports.
+pub struct Label {
+    level: String,
+    format: String,
+    location: Option<String>,
+}
+
+/// Collects the labels of the log sites in the ELF.
+pub fn labels(
+    section: &DefmtSection,
+    locs: Option<&Locations>,
+    current_dir: &Path,
+) -> BTreeMap<u64, Label> {
+    section
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.is_log_site())
+        .map(|symbol| {
+            let label = Label {
+                level: symbol.kind().to_uppercase(),
+                format: symbol.data.clone(),
+                location: locs
+                    .and_then(|locs| locs.get(&symbol.index))
+                    .map(|loc| elf::display_location(loc, current_dir)),
+            };
+            (symbol.index, label)
+        })
+        .collect()
+}
+
+/// Prints the `sites` sorted by bytes, with rates over `elapsed` seconds if known.
+pub fn print_sites(
+    sites: &BTreeMap<u64, Site>,
+    elapsed: Option<f64>,
+    labels: &BTreeMap<u64, Label>,
+) {
+    let mut sites = sites.iter().collect::<Vec<_>>();
+    sites.sort_by_key(|(_, site)| Reverse(site.bytes));
+
+    let rate =
+        |count: usize| elapsed.map_or("-".to_string(), |e| format!("{:.2}", count as f64 / e));
+
+    println!(" frames    bytes  frames/s    bytes/s  index  level     format");
+    for (index, site) in sites {
+        let label = labels.get(index);
+        let columns = format!(
+            "{:>7} {:>8} {:>9} {:>10}  {:#06x} {:<9} ",
+            site.frames,
+            site.bytes,
+            rate(site.frames),
+            rate(site.bytes),
+            index,
+            label.map_or("?", |label| label.level.as_str()),
+        );
+        println!(
+            "{}{}",
+            columns,
+            label.map_or("<unknown index>", |label| label.format.as_str())
+        );
+        if let Some(location) = label.and_then(|label| label.location.as_ref()) {
+            println!("{:width$}└─ {}", "", location, width = columns.len());
+        }
+    }
+}
+
+/// Bytes on the wire per log site of a live session, for `--bandwidth` and SIGUSR1.
+pub struct Bandwidth {
+    sites: BTreeMap<u64, Site>,
+    /// Sites since the last periodic report
+    recent: BTreeMap<u64, Site>,
+    start: Instant,
+    last_report: Instant,
+    pub labels: BTreeMap<u64, Label>,
+}
+
+impl Bandwidth {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Bandwidth {
+            sites: BTreeMap::new(),
+            recent: BTreeMap::new(),
+            start: now,
+            last_report: now,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, index: u64, size: usize) {
+        for sites in [&mut self.sites, &mut self.recent] {
+            let site = sites.entry(index).or_default();
+            site.frames += 1;
+            site.bytes += size;
+        }
+    }
+
+    /// Prints the top sites since the last report once `interval` has passed.
+    pub fn report_recent(&mut self, interval: Duration) {
+        let elapsed = self.last_report.elapsed();
+        if elapsed < interval {
+            return;
+        }
+
+        let mut recent = std::mem::take(&mut self.recent)
+            .into_iter()
+            .collect::<Vec<_>>();
+        recent.sort_by_key(|(_, site)| Reverse(site.bytes));
+        let total: usize = recent.iter().map(|(_, site)| site.bytes).sum();
+        let secs = elapsed.as_secs_f64();
+
+        println!(
+            "(HOST) bandwidth over the last {:.1}s: {:.0} B/s",
+            secs,
+            total as f64 / secs
+        );
+        for (index, site) in recent.iter().take(TOP_SITES) {
+            println!(
+                "    {:>8.0} B/s  {:#06x} {}",
+                site.bytes as f64 / secs,
+                index,
+                self.labels
+                    .get(index)
+                    .map_or("<unknown index>", |label| label.format.as_str())
+            );
+        }
+        self.last_report = Instant::now();
+    }
+
+    /// Prints the sites using the most bytes so far.
+    pub fn print_top(&self) {
+        let mut sites = self.sites.iter().collect::<Vec<_>>();
+        sites.sort_by_key(|(_, site)| Reverse(site.bytes));
+
+        println!("(HOST) top log sites by bytes");
+        for (index, site) in sites.iter().take(TOP_SITES) {
+            println!(
+                "    {:>7} frames {:>8} bytes  {:#06x} {}",
+                site.frames,
+                site.bytes,
+                index,
+                self.labels
+                    .get(index)
+                    .map_or("<unknown index>", |label| label.format.as_str())
+            );
+        }
+    }
+
+    /// Prints the `top` sites by frames and by bytes so far, to pick the ones to mute.
+    pub fn print_talkers(&self, top: usize) {
+        let frames: usize = self.sites.values().map(|site| site.frames).sum();
+        let bytes: usize = self.sites.values().map(|site| site.bytes).sum();
+        let share = |count: usize, total: usize| count as f64 * 100.0 / total.max(1) as f64;
+
+        let mut sites = self.sites.iter().collect::<Vec<_>>();
+        for (by, key) in [("frames", 0), ("bytes", 1)] {
+            match key {
+                0 => sites.sort_by_key(|(_, site)| Reverse((site.frames, site.bytes))),
+                _ => sites.sort_by_key(|(_, site)| Reverse((site.bytes, site.frames))),
+            }
+            println!(
+                "(HOST) top log sites by {}, of {} frames and {} bytes",
+                by, frames, bytes
+            );
+            for (index, site) in sites.iter().take(top) {
+                let label = self.labels.get(index);
+                println!(
+                    "    {:>7} frames {:>5.1}% {:>8} bytes {:>5.1}%  {:#06x} {:<9} {}",
+                    site.frames,
+                    share(site.frames, frames),
+                    site.bytes,
+                    share(site.bytes, bytes),
+                    index,
+                    label.map_or("?", |label| label.level.as_str()),
+                    label.map_or("<unknown index>", |label| label.format.as_str())
+                );
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        let total: usize = self.sites.values().map(|site| site.bytes).sum();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        println!(
+            "(HOST) bandwidth report: {} bytes of frames in {:.1}s",
+            total, elapsed
+        );
+        print_sites(&self.sites, Some(elapsed), &self.labels);
+    }
+}
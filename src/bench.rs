@@ -0,0 +1,50 @@
+use std::{path::PathBuf, time::Instant};
+
+use crate::{capture, elf, fetch, frames};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// Path or `http(s)://` URL of the ELF file
+    #[arg(long)]
+    elf: PathBuf,
+    /// ITM stimulus port carrying the defmt data
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Decode the capture this many times, for steadier numbers with small captures
+    #[arg(long, default_value_t = 1)]
+    repeat: usize,
+    /// Capture, e.g. written with `--capture`
+    capture: PathBuf,
+}
+
+pub fn run(args: BenchArgs) -> anyhow::Result<()> {
+    let bytes = fetch::read_elf(&args.elf)?;
+    let table = elf::parse_table(&bytes)?;
+    // read ahead, so the disk isn't measured
+    let capture = capture::read(&args.capture)?;
+
+    let (mut frames, mut malformed, mut formatted) = (0, 0, 0);
+    let start = Instant::now();
+    for _ in 0..args.repeat {
+        let stats = frames::decode_stream(&capture[..], args.port, &table, |frame, _| {
+            // format like the listener does, that's part of the cost
+            formatted += frame.display_message().to_string().len();
+            frames += 1;
+        })?;
+        malformed += stats.malformed;
+    }
+    let secs = start.elapsed().as_secs_f64();
+
+    let total = capture.len() * args.repeat;
+    println!(
+        "{} bytes, {} frames ({} malformed, {} bytes of text) in {:.3}s",
+        total, frames, malformed, formatted, secs
+    );
+    println!(
+        "{:.2} MB/s, {:.0} frames/s",
+        total as f64 / secs / 1e6,
+        frames as f64 / secs
+    );
+
+    Ok(())
+}
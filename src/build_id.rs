@@ -0,0 +1,96 @@
+use object::Object;
+use std::collections::BTreeSet;
+
+use crate::elf::DefmtSection;
+
+/// Messages starting with this prefix carry the build ID of the running firmware, e.g.
+/// `defmt::println!("build-id: {=[u8]:x}", BUILD_ID)`.
+pub const BUILD_ID_PREFIX: &str = "build-id:";
+
+pub enum Verdict {
+    Match,
+    Mismatch {
+        firmware: String,
+        elf: String,
+    },
+    /// The ELF has no `.note.gnu.build-id` to compare against
+    Unknown {
+        firmware: String,
+    },
+}
+
+/// Verifies the build ID reported by the firmware against the loaded ELF.
+#[derive(Debug)]
+pub struct BuildIdCheck {
+    elf: Option<Vec<u8>>,
+    /// Table indices of the log sites reporting a build ID
+    indices: BTreeSet<u64>,
+}
+
+impl BuildIdCheck {
+    pub fn new(bytes: &[u8], section: &DefmtSection) -> anyhow::Result<Self> {
+        let elf = object::File::parse(bytes)?.build_id()?.map(<[u8]>::to_vec);
+        let indices = section
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.is_log_site() && symbol.data.starts_with(BUILD_ID_PREFIX))
+            .map(|symbol| symbol.index)
+            .collect();
+
+        Ok(BuildIdCheck { elf, indices })
+    }
+
+    pub fn is_build_id(&self, index: u64) -> bool {
+        self.indices.contains(&index)
+    }
+
+    /// Compares the build ID in `message`, which must belong to a build ID log site.
+    pub fn verify(&self, message: &str) -> Verdict {
+        let reported = message
+            .strip_prefix(BUILD_ID_PREFIX)
+            .unwrap_or(message)
+            .trim();
+        let firmware = parse_id(reported);
+
+        match &self.elf {
+            None => Verdict::Unknown {
+                firmware: reported.to_string(),
+            },
+            Some(elf) if firmware.as_ref() == Some(elf) => Verdict::Match,
+            Some(elf) => Verdict::Mismatch {
+                firmware: reported.to_string(),
+                elf: to_hex(elf),
+            },
+        }
+    }
+}
+
+/// Parses either a byte list as formatted by defmt (`[de, ad, 0xbe]`) or a plain hex string.
+fn parse_id(text: &str) -> Option<Vec<u8>> {
+    match text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        Some(list) => list
+            .split(',')
+            .map(|byte| {
+                let byte = byte.trim();
+                u8::from_str_radix(byte.trim_start_matches("0x"), 16).ok()
+            })
+            .collect(),
+        None => {
+            let digits = text.trim_start_matches("0x");
+            if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+                return None;
+            }
+            (0..digits.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+                .collect()
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
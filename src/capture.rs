@@ -0,0 +1,1135 @@
+//! The `--capture` file, split into numbered parts by `--split-every` and `--split-size`, or into
+//! one per boot of the firmware by `--split-on-reboot`.
+//!
+//! A raw capture holds the received bytes as they are. A timestamped capture starts with `MAGIC`
+//! and `VERSION`, followed since version 2 by a `Header`: its `Metadata` as JSON and the embedded
+//! ELF files compressed with gzip, each preceded by its length as a little-endian `u32`. Then comes
+//! one record per read: the host time in microseconds since the Unix epoch as a little-endian
+//! `u64`, the number of bytes as a little-endian `u32` and the bytes, since version 3 followed by
+//! the CRC-32 of the record as a little-endian `u32`. A replay skips the records whose checksum
+//! doesn't match, up to the next one that does.
+//!
+//! `--capture-index` writes a sidecar file next to an uncompressed capture, `capture.bin.idx`, with
+//! one `IndexEntry` per line as JSON: the points decoding can start at, to seek to `--from`.
+
+use chrono::{format::Item, format::StrftimeItems, Local, SecondsFormat};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use object::Object;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Cursor, ErrorKind, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{compress, elf, fetch, Error};
+
+/// Expands a capture path template: strftime fields like `%Y-%m-%d` with the local time, `{target}`
+/// with the name of the ELF and `{hash}` with a hash of its contents.
+pub fn expand_path(
+    template: &Path,
+    elf: &Path,
+    elf_bytes: Option<&[u8]>,
+) -> crate::Result<PathBuf> {
+    let template = template.to_string_lossy();
+    if StrftimeItems::new(&template).any(|item| item == Item::Error) {
+        return Err(Error::Sink(format!(
+            "Invalid strftime field in `{}`",
+            template
+        )));
+    }
+
+    let mut path = Local::now().format(&template).to_string();
+    if path.contains("{target}") {
+        let target = elf.file_stem().unwrap_or_default().to_string_lossy();
+        path = path.replace("{target}", &target);
+    }
+    if let Some(bytes) = elf_bytes {
+        path = path.replace("{hash}", &elf_hash(bytes));
+    }
+    Ok(PathBuf::from(path))
+}
+
+/// 32-bit FNV-1a of the ELF in hex, stable across builds unlike the std hasher.
+pub fn elf_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0x811c9dc5u32, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    format!("{:08x}", hash)
+}
+
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, unit) =
+        text.split_at(text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len()));
+    let secs = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => f64::NAN,
+    };
+    match number.parse::<f64>().map(|number| number * secs) {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(format!(
+            "invalid duration `{}`, expected e.g. `90s`, `30m` or `1h`",
+            text
+        )),
+    }
+}
+
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let (number, unit) =
+        text.split_at(text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len()));
+    let scale = match unit {
+        "" | "B" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => 0,
+    };
+    match number
+        .parse::<u64>()
+        .map(|number| number.saturating_mul(scale))
+    {
+        Ok(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(format!(
+            "invalid size `{}`, expected e.g. `64K`, `500M` or `1G`",
+            text
+        )),
+    }
+}
+
+const MAGIC: &[u8] = b"DEFMTCAP";
+const VERSION: u8 = 3;
+/// The version without the checksums of the records
+const VERSION_WITHOUT_CRC: u8 = 2;
+/// The first version, without a `Header`
+const VERSION_WITHOUT_HEADER: u8 = 1;
+/// Host time and length of a record
+const RECORD_HEADER: usize = 12;
+const CRC: usize = 4;
+/// Longest record of a checksummed capture, a longer length is corrupted
+const MAX_RECORD: usize = 16 << 20;
+/// Bytes of a capture between the entries of its index, at least
+const INDEX_INTERVAL: u64 = 1 << 20;
+/// Longest sleep of a paced replay before the listener gets to check for Ctrl-C and keys
+const MAX_PACE_SLEEP: Duration = Duration::from_millis(100);
+
+/// How `--capture` stores the received bytes.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// The bytes as received, like the trace file of OpenOCD
+    Raw,
+    /// The bytes with the host time they arrived at, for a replay with the original timing
+    Timestamped,
+}
+
+/// What a timestamped capture was recorded with.
+#[derive(Serialize, Deserialize)]
+pub struct Metadata {
+    /// Local time the capture was started at, in RFC 3339
+    pub created: String,
+    pub targets: Vec<TargetInfo>,
+}
+
+/// The ELF decoding a stimulus port of a capture.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TargetInfo {
+    pub port: u8,
+    /// File name of the ELF
+    pub elf: String,
+    /// As by `elf_hash`
+    pub hash: String,
+    /// `.note.gnu.build-id` of the ELF in hex
+    pub build_id: Option<String>,
+    pub encoding: String,
+    /// Whether the ELF is in the capture
+    pub embedded: bool,
+}
+
+pub struct Header {
+    pub metadata: Metadata,
+    /// The ELF files of the targets with `embedded` set, in order
+    pub elfs: Vec<Vec<u8>>,
+}
+
+impl Header {
+    /// Describes the (port, ELF) `targets`, embedding the ELF files if `embed` is set.
+    pub fn new(targets: &[(u8, &Path)], embed: bool) -> crate::Result<Self> {
+        let mut infos = Vec::new();
+        let mut elfs = Vec::new();
+        for &(port, path) in targets {
+            let bytes = fetch::read_elf(path)?;
+            let table = elf::parse_table(&bytes)?;
+            let build_id = object::File::parse(&*bytes)
+                .and_then(|elf| elf.build_id())
+                .map_err(|err| Error::ElfParse(err.to_string()))?
+                .map(|id| id.iter().map(|byte| format!("{:02x}", byte)).collect());
+            infos.push(TargetInfo {
+                port,
+                elf: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                hash: elf_hash(&bytes),
+                build_id,
+                encoding: format!("{:?}", table.encoding()).to_lowercase(),
+                embedded: embed,
+            });
+            if embed {
+                elfs.push(bytes);
+            }
+        }
+
+        Ok(Header {
+            metadata: Metadata {
+                created: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+                targets: infos,
+            },
+            elfs,
+        })
+    }
+
+    /// The embedded ELF of stimulus `port`.
+    pub fn elf(&self, port: u8) -> Option<&[u8]> {
+        let embedded = self.metadata.targets.iter().filter(|t| t.embedded);
+        embedded
+            .zip(&self.elfs)
+            .find(|(target, _)| target.port == port)
+            .map(|(_, elf)| &elf[..])
+    }
+
+    /// Writes the embedded ELF files to the cache, once per hash, returning (port, path) of each.
+    pub fn extract_elfs(&self) -> crate::Result<Vec<(u8, PathBuf)>> {
+        let embedded = self.metadata.targets.iter().filter(|t| t.embedded);
+        let mut paths = Vec::new();
+        for (target, elf) in embedded.zip(&self.elfs) {
+            let dir = fetch::cache_dir()?.join("embedded").join(&target.hash);
+            let file = dir.join(&target.elf);
+            if !file.exists() {
+                fs::create_dir_all(&dir)?;
+                fs::write(&file, elf)?;
+            }
+            paths.push((target.port, file));
+        }
+        Ok(paths)
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let metadata = serde_json::to_vec(&self.metadata)?;
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&metadata);
+        for elf in &self.elfs {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(elf)?;
+            let compressed = encoder.finish()?;
+            bytes.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&compressed);
+        }
+        Ok(bytes)
+    }
+
+    fn read(source: &mut dyn Read) -> io::Result<Self> {
+        let metadata: Metadata = serde_json::from_slice(&read_block(source)?)?;
+        let mut elfs = Vec::new();
+        for _ in metadata.targets.iter().filter(|target| target.embedded) {
+            let mut elf = Vec::new();
+            GzDecoder::new(&read_block(source)?[..]).read_to_end(&mut elf)?;
+            elfs.push(elf);
+        }
+        Ok(Header { metadata, elfs })
+    }
+}
+
+/// Reads a length-prefixed block of the header.
+fn read_block(source: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    source.read_exact(&mut len)?;
+    let mut block = vec![0; u32::from_le_bytes(len) as usize];
+    source.read_exact(&mut block)?;
+    Ok(block)
+}
+
+/// When to start the next part of a split capture.
+#[derive(Clone, Copy)]
+pub struct Split {
+    pub every: Option<Duration>,
+    /// Received bytes, before compression
+    pub size: Option<u64>,
+    /// Parts numbered by boot instead, each started by `Capture::reboot`
+    pub reboot: bool,
+}
+
+/// A point of a capture decoding can start at, between two frames and ITM packets.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexEntry {
+    /// In the file, at the start of a record
+    pub offset: u64,
+    /// Host time in microseconds since the Unix epoch of the record before
+    pub host_time: u64,
+    /// Latest device timestamp of the frames since the entry before, as printed
+    pub timestamp: Option<String>,
+}
+
+/// The sidecar index of the capture being written.
+struct Index {
+    file: fs::File,
+    /// Offset of the last entry, or of the end of the capture when the index was opened
+    last: u64,
+}
+
+/// The `--capture-index` file of the capture at `path`.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+/// The index of the capture at `path`, `None` without one. An entry cut off while writing ends it.
+pub fn read_index(path: &Path) -> crate::Result<Option<Vec<IndexEntry>>> {
+    let index = index_path(path);
+    if compress::compressed(path) || !index.exists() {
+        return Ok(None);
+    }
+    let size = fs::metadata(path)?.len();
+    let entries = fs::read_to_string(&index)?
+        .lines()
+        .map_while(|line| serde_json::from_str::<IndexEntry>(line).ok())
+        .collect::<Vec<_>>();
+    match entries.last() {
+        Some(last) if last.offset > size => Err(Error::Framing(format!(
+            "{} is not the index of {}, it goes past its end",
+            index.display(),
+            path.display()
+        ))),
+        _ => Ok(Some(entries)),
+    }
+}
+
+pub struct Capture {
+    path: PathBuf,
+    format: CaptureFormat,
+    /// Written at the start of each timestamped part
+    header: Vec<u8>,
+    split: Option<Split>,
+    writer: compress::Writer,
+    /// Whether the records end with a checksum, see `start`
+    checked: bool,
+    /// Number of the current part, or boot with `Split::reboot`
+    part: usize,
+    started: Instant,
+    written: u64,
+    index: Option<Index>,
+    /// In the current part, with an index
+    offset: u64,
+    /// Host time of the last record
+    last_micros: u64,
+}
+
+impl Capture {
+    /// Appends to `path`, or with `split` writes `name.0001.ext`, `name.0002.ext` and so on,
+    /// starting after the parts already there, or `name.boot0001.ext` and so on by boot. Missing
+    /// directories are created.
+    pub fn new(
+        path: &Path,
+        format: CaptureFormat,
+        header: Option<&Header>,
+        split: Option<Split>,
+    ) -> crate::Result<Self> {
+        let header = match header {
+            Some(header) => header.to_bytes().map_err(sink)?,
+            None => Vec::new(),
+        };
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(sink)?;
+        }
+        let part = match split {
+            Some(split) if split.reboot => 1,
+            Some(_) => (1..).find(|&part| !part_path(path, part).exists()).unwrap(),
+            None => 0,
+        };
+        let (writer, checked) = start(&split_path(path, split, part), format, &header)?;
+
+        Ok(Capture {
+            path: path.to_owned(),
+            format,
+            header,
+            split,
+            writer,
+            checked,
+            part,
+            started: Instant::now(),
+            written: 0,
+            index: None,
+            offset: 0,
+            last_micros: 0,
+        })
+    }
+
+    /// Writes an index next to each part from now on, see `mark`. Compressed captures can't be
+    /// indexed, the offsets in them can't be seeked to.
+    pub fn start_index(&mut self) -> crate::Result<()> {
+        if compress::compressed(&self.path) {
+            return Err(Error::Sink(format!(
+                "{} is compressed, it can't be indexed",
+                self.path.display()
+            )));
+        }
+        self.open_index()
+    }
+
+    /// Opens the index of the current part, and finds the end of it.
+    fn open_index(&mut self) -> crate::Result<()> {
+        let path = split_path(&self.path, self.split, self.part);
+        self.writer.flush().map_err(sink)?;
+        self.offset = fs::metadata(&path).map_err(sink)?.len();
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_path(&path))
+            .map_err(sink)?;
+        self.index = Some(Index {
+            file,
+            last: self.offset,
+        });
+        Ok(())
+    }
+
+    /// Adds the current offset to the index as a point decoding can start at, e.g. after a read
+    /// whose frames were all completed, at most every `INDEX_INTERVAL` bytes. `timestamp` is the
+    /// latest of the frames since the last entry. Returns whether an entry was added.
+    pub fn mark(&mut self, timestamp: Option<&str>) -> crate::Result<bool> {
+        let Some(index) = &mut self.index else {
+            return Ok(false);
+        };
+        if self.offset < index.last + INDEX_INTERVAL {
+            return Ok(false);
+        }
+        let entry = IndexEntry {
+            offset: self.offset,
+            host_time: self.last_micros,
+            timestamp: timestamp.map(str::to_string),
+        };
+        let line = serde_json::to_string(&entry).map_err(|err| Error::Sink(err.to_string()))?;
+        writeln!(index.file, "{}", line).map_err(sink)?;
+        index.last = self.offset;
+        Ok(true)
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finish()
+    }
+
+    /// Continues in the next part of a split capture, or reopens the path of another one, e.g. after
+    /// it was moved away by logrotate. Returns the path written to from now on.
+    pub fn rotate(&mut self) -> crate::Result<PathBuf> {
+        // the part of a boot is only reopened
+        if self.split.is_some_and(|split| !split.reboot) {
+            self.part += 1;
+        }
+        let path = split_path(&self.path, self.split, self.part);
+        // NOTE flushed for `start` to read the format of a file that is still there
+        self.writer.flush().map_err(sink)?;
+        let (next, checked) = start(&path, self.format, &self.header)?;
+        self.checked = checked;
+        std::mem::replace(&mut self.writer, next)
+            .finish()
+            .map_err(sink)?;
+        self.started = Instant::now();
+        self.written = 0;
+        if self.index.is_some() {
+            self.open_index()?;
+        }
+        Ok(path)
+    }
+
+    /// Continues in the part of `boot` with `--split-on-reboot`. Returns the path written to from now
+    /// on, if any.
+    pub fn reboot(&mut self, boot: usize) -> crate::Result<Option<PathBuf>> {
+        if !self.split.is_some_and(|split| split.reboot) {
+            return Ok(None);
+        }
+        self.part = boot;
+        self.rotate().map(Some)
+    }
+
+    /// Writes `bytes` as received at `micros` since the Unix epoch, the time is only kept by
+    /// timestamped captures.
+    pub fn write_record(&mut self, micros: u64, bytes: &[u8]) -> io::Result<()> {
+        if self.format == CaptureFormat::Timestamped {
+            let mut header = [0; RECORD_HEADER];
+            header[..8].copy_from_slice(&micros.to_le_bytes());
+            header[8..].copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+            self.writer.write_all(&header)?;
+            self.offset += RECORD_HEADER as u64;
+            // NOTE a record has to be complete
+            self.writer.write_all(bytes)?;
+            if self.checked {
+                let mut crc = crc32fast::Hasher::new();
+                crc.update(&header);
+                crc.update(bytes);
+                self.writer.write_all(&crc.finalize().to_le_bytes())?;
+                self.offset += CRC as u64;
+            }
+        } else {
+            self.writer.write_all(bytes)?;
+        }
+        self.written += bytes.len() as u64;
+        self.offset += bytes.len() as u64;
+        self.last_micros = micros;
+        Ok(())
+    }
+
+    fn due(&self) -> bool {
+        self.split.is_some_and(|split| {
+            split
+                .every
+                .is_some_and(|every| self.started.elapsed() >= every)
+                || split.size.is_some_and(|size| self.written >= size)
+        })
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // NOTE parts are split between reads, so an ITM packet or frame may straddle two parts
+        if self.due() {
+            self.rotate().map_err(io::Error::other)?;
+        }
+
+        let micros = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        self.write_record(micros, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Opens the capture at `path` for appending, writing the start of a new timestamped capture.
+/// Returns whether its records end with a checksum, those of an older version don't.
+fn start(
+    path: &Path,
+    format: CaptureFormat,
+    header: &[u8],
+) -> crate::Result<(compress::Writer, bool)> {
+    let head = match path.exists() {
+        true => read_head(&mut compress::open(path)?)?,
+        false => Vec::new(),
+    };
+    let existing = detect(&head);
+    let new = || -> io::Result<compress::Writer> {
+        let mut writer = compress::Writer::append(path)?;
+        if format == CaptureFormat::Timestamped {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+            writer.write_all(header)?;
+        }
+        Ok(writer)
+    };
+    match (existing, format) {
+        (Some(existing), format) if existing != format => {
+            let name = match existing {
+                CaptureFormat::Raw => "raw",
+                CaptureFormat::Timestamped => "timestamped",
+            };
+            Err(Error::Sink(format!(
+                "{} is a {} capture, pass `--capture-format {}` to append to it",
+                path.display(),
+                name,
+                name
+            )))
+        }
+        // the start of a timestamped capture is only written to a new one
+        (None, format) => Ok((new().map_err(sink)?, format == CaptureFormat::Timestamped)),
+        (_, format) => Ok((
+            compress::Writer::append(path).map_err(sink)?,
+            format == CaptureFormat::Timestamped && head[MAGIC.len()] >= VERSION,
+        )),
+    }
+}
+
+fn sink(err: io::Error) -> Error {
+    Error::Sink(err.to_string())
+}
+
+/// Reads the magic and version from the start of a capture, or less if it's shorter.
+fn read_head(source: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    source.take(MAGIC.len() as u64 + 1).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// The format of a capture starting with `head`, `None` if it's empty.
+fn detect(head: &[u8]) -> Option<CaptureFormat> {
+    match head {
+        [] => None,
+        [magic @ .., _] if magic == MAGIC => Some(CaptureFormat::Timestamped),
+        _ => Some(CaptureFormat::Raw),
+    }
+}
+
+/// Opens a capture of either format, reading the received bytes.
+pub fn open(path: &Path) -> crate::Result<Box<dyn BufRead>> {
+    let opened = open_replay(path, 0.0, None)?;
+    Ok(Box::new(BufReader::new(opened.source)))
+}
+
+/// A capture opened by `open_replay`.
+pub struct Opened {
+    /// The received bytes
+    pub source: Box<dyn Read>,
+    pub timestamped: bool,
+    /// Of timestamped captures since version 2
+    pub header: Option<Header>,
+    /// Of the file, compressed
+    pub size: u64,
+    /// Bytes of the file read so far, compressed, which runs ahead of `source` by a buffer
+    pub read: Arc<AtomicU64>,
+    /// Host time in microseconds since the Unix epoch of the record of the last read of `source`,
+    /// `0` for raw captures
+    pub host_time: Arc<AtomicU64>,
+    /// Cleared to read the records without delay until it's set again, e.g. those before `--from`
+    pub paced: Arc<AtomicBool>,
+    /// Regions of a timestamped capture whose checksums didn't match, skipped by `source`, as
+    /// offsets in the capture after decompression
+    pub corrupted: Arc<Mutex<Vec<Range<u64>>>>,
+}
+
+/// Reads all received bytes of a capture of either format.
+pub fn read(path: &Path) -> crate::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    open(path)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Opens a capture to replay, delaying the bytes of a timestamped capture until their arrival time,
+/// scaled by `speed`. Gaps are shortened to `skip_gaps` seconds.
+pub fn open_replay(path: &Path, speed: f64, skip_gaps: Option<f64>) -> crate::Result<Opened> {
+    open_replay_from(path, speed, skip_gaps, None)
+}
+
+/// Like `open_replay`, starting at `offset` of an uncompressed capture, e.g. of an `IndexEntry`.
+pub fn open_replay_at(
+    path: &Path,
+    speed: f64,
+    skip_gaps: Option<f64>,
+    offset: u64,
+) -> crate::Result<Opened> {
+    open_replay_from(path, speed, skip_gaps, Some(offset))
+}
+
+fn open_replay_from(
+    path: &Path,
+    speed: f64,
+    skip_gaps: Option<f64>,
+    offset: Option<u64>,
+) -> crate::Result<Opened> {
+    let read = Arc::default();
+    let mut source = compress::open_counted(path, Arc::clone(&read))?;
+    let size = fs::metadata(path)?.len();
+    let head = read_head(&mut source)?;
+    let format = detect(&head);
+    let (header, start) = match format {
+        Some(CaptureFormat::Timestamped) => read_header(path, head[MAGIC.len()], &mut source)?,
+        _ => (None, 0),
+    };
+    if let Some(offset) = offset {
+        source = compress::open_at(path, offset, Arc::clone(&read))?;
+    }
+
+    match format {
+        Some(CaptureFormat::Timestamped) => {
+            let mut records = Records::new(source, head[MAGIC.len()], speed, skip_gaps);
+            records.offset = offset.unwrap_or(start);
+            Ok(Opened {
+                host_time: Arc::clone(&records.time),
+                paced: Arc::clone(&records.paced),
+                corrupted: Arc::clone(&records.corrupted),
+                source: Box::new(records),
+                timestamped: true,
+                header,
+                size,
+                read,
+            })
+        }
+        _ => Ok(Opened {
+            source: match offset {
+                Some(_) => Box::new(source),
+                None => Box::new(Cursor::new(head).chain(source)),
+            },
+            timestamped: false,
+            header: None,
+            size,
+            read,
+            host_time: Arc::default(),
+            paced: Arc::new(AtomicBool::new(true)),
+            corrupted: Arc::default(),
+        }),
+    }
+}
+
+/// Opens a timestamped capture to read it record by record.
+pub fn open_records(path: &Path) -> crate::Result<(Records, Option<Header>)> {
+    let mut source = compress::open(path)?;
+    let head = read_head(&mut source)?;
+    if detect(&head) != Some(CaptureFormat::Timestamped) {
+        return Err(Error::Framing(format!(
+            "{} is not a timestamped capture, it has no host times",
+            path.display()
+        )));
+    }
+    let (header, start) = read_header(path, head[MAGIC.len()], &mut source)?;
+    let mut records = Records::new(source, head[MAGIC.len()], 0.0, None);
+    records.offset = start;
+    Ok((records, header))
+}
+
+/// The header following `version`, `None` for captures without one, and the offset of the records
+/// after it.
+fn read_header(
+    path: &Path,
+    version: u8,
+    source: &mut dyn Read,
+) -> crate::Result<(Option<Header>, u64)> {
+    let start = MAGIC.len() as u64 + 1;
+    match version {
+        VERSION_WITHOUT_CRC | VERSION => {
+            let mut limited = source.take(u64::MAX);
+            let header = Header::read(&mut limited).map_err(|err| {
+                Error::Framing(format!("Invalid header of {}: {}", path.display(), err))
+            })?;
+            Ok((Some(header), start + (u64::MAX - limited.limit())))
+        }
+        VERSION_WITHOUT_HEADER => Ok((None, start)),
+        version => Err(Error::Framing(format!(
+            "{} is a capture of format version {}, up to {} is supported",
+            path.display(),
+            version,
+            VERSION
+        ))),
+    }
+}
+
+/// The bytes of the records of a timestamped capture.
+pub struct Records {
+    source: Box<dyn BufRead>,
+    /// `0` doesn't pace
+    speed: f64,
+    skip_gaps: Option<f64>,
+    /// Host time in the capture and now of the record the replay is aligned to
+    origin: Option<(u64, Instant)>,
+    /// Host time of the last record
+    last: u64,
+    /// `last`, for `Opened::host_time`
+    time: Arc<AtomicU64>,
+    paced: Arc<AtomicBool>,
+    /// Whether the records end with a checksum
+    checked: bool,
+    /// Offset of `ahead` in the capture
+    offset: u64,
+    /// Bytes read while looking for the next record with a matching checksum
+    ahead: Vec<u8>,
+    corrupted: Arc<Mutex<Vec<Range<u64>>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Records {
+    fn new(source: Box<dyn BufRead>, version: u8, speed: f64, skip_gaps: Option<f64>) -> Self {
+        Records {
+            source,
+            speed,
+            skip_gaps,
+            origin: None,
+            last: 0,
+            time: Arc::default(),
+            paced: Arc::new(AtomicBool::new(true)),
+            checked: version >= VERSION,
+            offset: 0,
+            ahead: Vec::new(),
+            corrupted: Arc::default(),
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Regions whose checksums didn't match, skipped so far; see `Opened::corrupted`.
+    pub fn corrupted(&self) -> Vec<Range<u64>> {
+        self.corrupted.lock().unwrap().clone()
+    }
+
+    /// The next record: its host time in microseconds since the Unix epoch and its bytes.
+    pub fn record(&mut self) -> io::Result<Option<(u64, &[u8])>> {
+        match self.next()? {
+            true => {
+                self.pos = self.chunk.len();
+                Ok(Some((self.last, &self.chunk)))
+            }
+            false => Ok(None),
+        }
+    }
+
+    /// Reads the next record, `false` at the end of the capture.
+    fn next(&mut self) -> io::Result<bool> {
+        let time = match self.checked {
+            true => self.read_checked()?,
+            false => self.read_unchecked()?,
+        };
+        let Some(time) = time else {
+            return Ok(false);
+        };
+        self.pos = 0;
+
+        if self.speed > 0.0 && self.paced.load(Ordering::Relaxed) {
+            let origin = self.origin.get_or_insert((time, Instant::now()));
+            if let Some(max) = self.skip_gaps {
+                let gap = time.saturating_sub(self.last);
+                if gap as f64 / 1e6 > max {
+                    origin.0 += gap - (max * 1e6) as u64;
+                }
+            }
+        }
+        self.last = time;
+        self.time.store(time, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Reads a record into `chunk`, returning its host time.
+    fn read_unchecked(&mut self) -> io::Result<Option<u64>> {
+        let mut header = [0; RECORD_HEADER];
+        match self.source.read_exact(&mut header) {
+            Ok(()) => {}
+            // NOTE a capture cut off while writing ends with a partial record
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let (time, len) = record_header(&header);
+        self.chunk.clear();
+        (&mut self.source)
+            .take(len as u64)
+            .read_to_end(&mut self.chunk)?;
+        Ok(Some(time))
+    }
+
+    /// Like `read_unchecked` for the next record whose length is in range and whose checksum
+    /// matches. Past corrupted bytes it's looked for a byte at a time, checksumming only the
+    /// candidates followed by another plausible record header or the end of the capture.
+    fn read_checked(&mut self) -> io::Result<Option<u64>> {
+        // bytes skipped, and whether the first record looked cut off rather than corrupted
+        let (mut skip, mut cut_off) = (0, false);
+        loop {
+            if !self.fill(skip + RECORD_HEADER)? {
+                break;
+            }
+            let (time, len) = record_header(&self.ahead[skip..skip + RECORD_HEADER]);
+            let plausible = len as usize <= MAX_RECORD;
+            let end = skip + RECORD_HEADER + len as usize;
+            if plausible && self.fill(end + CRC)? {
+                let crc =
+                    u32::from_le_bytes(self.ahead[end..end + CRC].try_into().expect("4 bytes"));
+                if (skip == 0 || self.starts_record(end + CRC)?)
+                    && crc32fast::hash(&self.ahead[skip..end]) == crc
+                {
+                    self.chunk.clear();
+                    self.chunk
+                        .extend_from_slice(&self.ahead[skip + RECORD_HEADER..end]);
+                    self.ahead.drain(..end + CRC);
+                    self.skipped(skip);
+                    self.offset += (end + CRC - skip) as u64;
+                    return Ok(Some(time));
+                }
+            } else if plausible && skip == 0 {
+                cut_off = true;
+            }
+            skip += 1;
+        }
+
+        // NOTE a capture cut off while writing ends with a partial record
+        if !cut_off && skip > 0 {
+            self.skipped(self.ahead.len());
+        }
+        self.ahead.clear();
+        Ok(None)
+    }
+
+    /// Whether a record header with a length in range starts at `at` of `ahead`, or the capture ends
+    /// before one would.
+    fn starts_record(&mut self, at: usize) -> io::Result<bool> {
+        match self.fill(at + RECORD_HEADER)? {
+            true => {
+                let (_, len) = record_header(&self.ahead[at..at + RECORD_HEADER]);
+                Ok(len as usize <= MAX_RECORD)
+            }
+            false => Ok(true),
+        }
+    }
+
+    /// Reads ahead until `len` bytes are, `false` if the capture ends before.
+    fn fill(&mut self, len: usize) -> io::Result<bool> {
+        if let Some(missing) = len.checked_sub(self.ahead.len()) {
+            (&mut self.source)
+                .take(missing as u64)
+                .read_to_end(&mut self.ahead)?;
+        }
+        Ok(self.ahead.len() >= len)
+    }
+
+    /// Records the next `len` bytes as corrupted.
+    fn skipped(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        self.corrupted
+            .lock()
+            .unwrap()
+            .push(self.offset..self.offset + len as u64);
+        self.offset += len as u64;
+    }
+
+    /// When the current record is due.
+    fn due(&self) -> Option<Instant> {
+        let (time, now) = self.origin?;
+        let elapsed = self.last.saturating_sub(time) as f64 / 1e6;
+        Some(now + Duration::from_secs_f64(elapsed / self.speed))
+    }
+}
+
+/// The host time and length in a record header.
+fn record_header(header: &[u8]) -> (u64, u32) {
+    let (time, len) = header.split_at(8);
+    (
+        u64::from_le_bytes(time.try_into().expect("8 bytes")),
+        u32::from_le_bytes(len.try_into().expect("4 bytes")),
+    )
+}
+
+impl Read for Records {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if !self.next()? {
+                return Ok(0);
+            }
+        }
+
+        // return now and then while waiting, so the listener stays responsive
+        if let Some(delay) = self
+            .due()
+            .and_then(|due| due.checked_duration_since(Instant::now()))
+        {
+            thread::sleep(delay.min(MAX_PACE_SLEEP));
+            if delay > MAX_PACE_SLEEP {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The file of `part` of a capture at `path`.
+fn split_path(path: &Path, split: Option<Split>, part: usize) -> PathBuf {
+    match split {
+        Some(split) if split.reboot => boot_path(path, part),
+        Some(_) => part_path(path, part),
+        None => path.to_owned(),
+    }
+}
+
+/// `dir/name.ext` as `dir/name.0001.ext`, keeping all extensions, e.g. `.bin.gz`.
+fn part_path(path: &Path, part: usize) -> PathBuf {
+    numbered(path, &format!("{:04}", part))
+}
+
+/// `dir/name.ext` as `dir/name.boot0001.ext`, for the files split by `--split-on-reboot`.
+pub fn boot_path(path: &Path, boot: usize) -> PathBuf {
+    numbered(path, &format!("boot{:04}", boot))
+}
+
+/// `dir/name.ext` as `dir/name.<label>.ext`, e.g. for the files of each of several listeners.
+pub fn labeled_path(path: &Path, label: &str) -> PathBuf {
+    numbered(path, label)
+}
+
+fn numbered(path: &Path, number: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match name.split_once('.') {
+        Some((stem, extensions)) => format!("{}.{}.{}", stem, number, extensions),
+        None => format!("{}.{}", name, number),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A record as read: its host time and bytes
+    type Record = (u64, Vec<u8>);
+
+    /// A path in the temporary directory unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("defmt-listener-{}-{}", std::process::id(), name))
+    }
+
+    /// Writes a timestamped capture of `records` to `path`.
+    fn write_capture(path: &Path, records: &[(u64, &[u8])]) {
+        let header = Header {
+            metadata: Metadata {
+                created: "2024-01-01T00:00:00+00:00".to_string(),
+                targets: Vec::new(),
+            },
+            elfs: Vec::new(),
+        };
+        let _ = fs::remove_file(path);
+        let mut capture =
+            Capture::new(path, CaptureFormat::Timestamped, Some(&header), None).unwrap();
+        for &(micros, bytes) in records {
+            capture.write_record(micros, bytes).unwrap();
+        }
+        capture.finish().unwrap();
+    }
+
+    /// The records of the capture at `path` and the regions skipped as corrupted.
+    fn read_capture(path: &Path) -> (Vec<Record>, Vec<Range<u64>>) {
+        let (mut records, _) = open_records(path).unwrap();
+        let mut read = Vec::new();
+        while let Some((micros, bytes)) = records.record().unwrap() {
+            read.push((micros, bytes.to_vec()));
+        }
+        (read, records.corrupted())
+    }
+
+    #[test]
+    fn records_after_a_long_pause_are_kept() {
+        let path = temp_path("pause.bin");
+        // a session appended days after the last one
+        let day = 24 * 3600 * 1_000_000;
+        write_capture(&path, &[(day, b"one"), (4 * day, b"two"), (day, b"three")]);
+        let (records, corrupted) = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            records,
+            [
+                (day, b"one".to_vec()),
+                (4 * day, b"two".to_vec()),
+                (day, b"three".to_vec())
+            ]
+        );
+        assert!(corrupted.is_empty());
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let path = temp_path("round-trip.bin");
+        let long = vec![0xa5; 70_000];
+        write_capture(&path, &[(1, b"abc"), (2, b""), (3, &long)]);
+        let (records, corrupted) = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records, [(1, b"abc".to_vec()), (2, Vec::new()), (3, long)]);
+        assert!(corrupted.is_empty());
+    }
+
+    #[test]
+    fn truncated_record_ends_the_capture() {
+        let path = temp_path("truncated.bin");
+        write_capture(&path, &[(1, b"first"), (2, b"second")]);
+        // cut off while writing the checksum of the last record
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 2]).unwrap();
+        let (records, corrupted) = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records, [(1, b"first".to_vec())]);
+        assert!(corrupted.is_empty());
+    }
+
+    #[test]
+    fn corrupted_record_is_skipped() {
+        let path = temp_path("corrupted.bin");
+        write_capture(&path, &[(1, b"first"), (2, b"second"), (3, b"third")]);
+        let mut bytes = fs::read(&path).unwrap();
+        let second = bytes
+            .windows(6)
+            .position(|bytes| bytes == b"second")
+            .unwrap();
+        bytes[second] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        let (records, corrupted) = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records, [(1, b"first".to_vec()), (3, b"third".to_vec())]);
+        // the whole second record, from its header to its checksum
+        let start = (second - RECORD_HEADER) as u64;
+        let end = start + (RECORD_HEADER + 6 + CRC) as u64;
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!(corrupted[0], start..end);
+    }
+
+    #[test]
+    fn garbage_length_is_resynced() {
+        let path = temp_path("garbage.bin");
+        write_capture(&path, &[(1, b"first"), (2, b"second")]);
+        let mut bytes = fs::read(&path).unwrap();
+        let first = bytes
+            .windows(5)
+            .position(|bytes| bytes == b"first")
+            .unwrap();
+        // the length of the first record out of range
+        bytes[first - 1] = 0xff;
+        fs::write(&path, &bytes).unwrap();
+        let (records, corrupted) = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(records, [(2, b"second".to_vec())]);
+        assert_eq!(corrupted.len(), 1);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        for text in ["", "0", "-1s", "1w", "s", "1 h", "infs"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert_eq!(parse_size("99999999999G"), Ok(u64::MAX));
+        for text in ["", "0", "0K", "1.5M", "-1", "1T", "1k", "M"] {
+            assert!(parse_size(text).is_err(), "{}", text);
+        }
+    }
+}
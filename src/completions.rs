@@ -0,0 +1,18 @@
+use clap_complete::Shell;
+use std::io::{self, Write};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompletionsArgs {
+    /// Shell to complete in
+    shell: Shell,
+}
+
+/// Writes the completion script of `command` to stdout.
+pub fn run(args: CompletionsArgs, command: &mut clap::Command) -> anyhow::Result<()> {
+    let name = command.get_name().to_string();
+    // NOTE generated to a buffer, `generate` panics if stdout is closed
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, command, name, &mut script);
+    io::stdout().write_all(&script)?;
+    Ok(())
+}
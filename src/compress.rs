@@ -0,0 +1,128 @@
+//! Transparent gzip and zstd compression of files picked by their `.gz` or `.zst` extension.
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+#[derive(Clone, Copy)]
+enum Format {
+    Plain,
+    Gzip,
+    Zstd,
+}
+
+impl Format {
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Format::Gzip,
+            Some("zst") => Format::Zstd,
+            _ => Format::Plain,
+        }
+    }
+}
+
+/// A file being written, compressed as its extension says.
+pub enum Writer {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Writer {
+    /// Appends to the file at `path`; compressed files get a new gzip member or zstd frame, which
+    /// decompress as one.
+    pub fn append(path: &Path) -> io::Result<Self> {
+        let file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+        Ok(match Format::of(path) {
+            Format::Plain => Writer::Plain(file),
+            Format::Gzip => Writer::Gzip(GzEncoder::new(file, Compression::default())),
+            Format::Zstd => Writer::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Writes the end of the compressed stream, without which the file can't be read completely.
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Writer::Plain(file) => file,
+            Writer::Gzip(encoder) => encoder.finish()?,
+            Writer::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Plain(file) => file.write(buf),
+            Writer::Gzip(encoder) => encoder.write(buf),
+            Writer::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Plain(file) => file.flush(),
+            Writer::Gzip(encoder) => encoder.flush(),
+            Writer::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Opens the file at `path` for reading, decompressing it as its extension says.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    open_counted(path, Arc::default())
+}
+
+/// Like `open`, adding the bytes read from the file, before decompression, to `read`.
+pub fn open_counted(path: &Path, read: Arc<AtomicU64>) -> io::Result<Box<dyn BufRead>> {
+    let file = BufReader::new(Counted {
+        file: File::open(path)?,
+        read,
+    });
+    Ok(match Format::of(path) {
+        Format::Plain => Box::new(file),
+        Format::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Format::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(file)?)),
+    })
+}
+
+/// Like `open_counted`, starting at `offset` of an uncompressed file, which `read` starts at.
+pub fn open_at(path: &Path, offset: u64, read: Arc<AtomicU64>) -> io::Result<Box<dyn BufRead>> {
+    if compressed(path) {
+        return Err(io::Error::other(format!(
+            "{} is compressed, it can't be read from an offset",
+            path.display()
+        )));
+    }
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    read.store(offset, Ordering::Relaxed);
+    Ok(Box::new(BufReader::new(Counted { file, read })))
+}
+
+/// Whether the file at `path` is compressed, by its extension.
+pub fn compressed(path: &Path) -> bool {
+    !matches!(Format::of(path), Format::Plain)
+}
+
+/// A file counting the bytes read from it, e.g. for the progress of a replay.
+struct Counted {
+    file: File,
+    read: Arc<AtomicU64>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
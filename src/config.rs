@@ -0,0 +1,386 @@
+//! `--config`, a TOML file setting up the console and the sinks, each with its own filter.
+//!
+//! ```toml
+//! level_map = ["trace=debug", "app::net:warn=error"]
+//! extract = ['vbat:f32=/vbat=(\d+\.\d+)/']
+//!
+//! [console]
+//! level = "info"
+//! color = true
+//!
+//! [[sink]]
+//! kind = "file"
+//! path = "trace.log"
+//! level = "trace"
+//!
+//! [[sink]]
+//! kind = "webhook"
+//! url = "https://hooks.slack.com/services/..."
+//! format = "slack"
+//! level = "error"
+//!
+//! [[sink]]
+//! kind = "journald"
+//! level = "info"
+//! severity = ["error=crit", "app::net:warn=err"]
+//! ```
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+use crate::{
+    filter::Filter,
+    level_map::{self, Rule},
+    sinks::{self, SinkFormat, SinkKind, SinkSpec},
+    syslog::{self, Protocol},
+    telemetry::{self, Field},
+    webhook::WebhookFormat,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    console: Console,
+    #[serde(default, rename = "sink")]
+    sinks: Vec<Sink>,
+    /// `--map-level` rules
+    #[serde(default)]
+    level_map: Vec<String>,
+    /// `--extract` definitions
+    #[serde(default)]
+    extract: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Console {
+    level: Option<String>,
+    #[serde(default)]
+    module: Vec<String>,
+    grep: Option<String>,
+    color: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sink {
+    /// `stderr`, `file`, `tcp`, `webhook`, `syslog` or `journald`
+    kind: String,
+    /// Of the file, or of the socket of syslog or journald if it's not the usual one
+    path: Option<String>,
+    address: Option<String>,
+    url: Option<String>,
+    /// `text` or `json`, for a webhook `json`, `slack` or `discord`
+    format: Option<String>,
+    level: Option<String>,
+    #[serde(default)]
+    module: Vec<String>,
+    grep: Option<String>,
+    /// The syslog severity of the levels, e.g. `error=crit` or `app::net:warn=err`
+    #[serde(default)]
+    severity: Vec<String>,
+}
+
+pub struct Config {
+    /// Of the console, `None` if the file doesn't set one
+    pub filter: Option<Filter>,
+    /// Whether the console is colored, by default if it's a terminal
+    pub color: Option<bool>,
+    pub sinks: Vec<SinkSpec>,
+    pub level_map: Vec<Rule>,
+    pub extracts: Vec<Field>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read --config {}: {}", path.display(), err))?;
+        let file: File = toml::from_str(&text)
+            .map_err(|err| anyhow!("Invalid --config {}: {}", path.display(), err))?;
+
+        let console = &file.console;
+        let filter = match (&console.level, console.module.is_empty(), &console.grep) {
+            (None, true, None) => None,
+            _ => Some(
+                filter(&console.level, &console.module, &console.grep).map_err(|err| {
+                    anyhow!("Invalid [console] in --config {}: {}", path.display(), err)
+                })?,
+            ),
+        };
+        let sinks = file
+            .sinks
+            .iter()
+            .enumerate()
+            .map(|(i, sink)| {
+                sink_spec(sink).map_err(|err| {
+                    anyhow!(
+                        "Invalid sink {} in --config {}: {}",
+                        i + 1,
+                        path.display(),
+                        err
+                    )
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let level_map = file
+            .level_map
+            .iter()
+            .map(|rule| level_map::parse_rule(rule))
+            .collect::<Result<_, _>>()
+            .map_err(|err| anyhow!("Invalid level_map in --config {}: {}", path.display(), err))?;
+        let extracts = file
+            .extract
+            .iter()
+            .map(|definition| telemetry::parse_extract(definition))
+            .collect::<Result<_, _>>()
+            .map_err(|err| anyhow!("Invalid extract in --config {}: {}", path.display(), err))?;
+
+        Ok(Config {
+            filter,
+            color: console.color,
+            sinks,
+            level_map,
+            extracts,
+        })
+    }
+}
+
+fn filter(
+    level: &Option<String>,
+    modules: &[String],
+    grep: &Option<String>,
+) -> anyhow::Result<Filter> {
+    Ok(Filter {
+        level: level.as_deref().map(parse_level).transpose()?,
+        modules: modules.to_vec(),
+        grep: grep.as_deref().map(Regex::new).transpose()?,
+    })
+}
+
+fn parse_level(level: &str) -> anyhow::Result<log::Level> {
+    level
+        .parse()
+        .map_err(|_| anyhow!("invalid level `{}`", level))
+}
+
+fn sink_spec(sink: &Sink) -> anyhow::Result<SinkSpec> {
+    let required = |value: &Option<String>, key: &str| {
+        value
+            .clone()
+            .ok_or_else(|| anyhow!("`{}` is required for a {} sink", key, sink.kind))
+    };
+
+    if !sink.severity.is_empty() && !matches!(sink.kind.as_str(), "syslog" | "journald") {
+        return Err(anyhow!(
+            "`severity` is only for the syslog and journald sinks"
+        ));
+    }
+
+    let kind = match sink.kind.as_str() {
+        "stderr" => SinkKind::Stderr,
+        "file" => SinkKind::File(required(&sink.path, "path")?.into()),
+        "tcp" => SinkKind::Tcp(required(&sink.address, "address")?),
+        "webhook" => {
+            // the level is the webhook's, which takes every frame for the context of the alerts
+            let format = match &sink.format {
+                Some(format) => WebhookFormat::from_str(format, true)
+                    .map_err(|_| anyhow!("invalid webhook format `{}`", format))?,
+                None => WebhookFormat::Json,
+            };
+            let level = sink.level.as_deref().map(parse_level).transpose()?;
+            return Ok(SinkSpec {
+                kind: SinkKind::Webhook {
+                    url: required(&sink.url, "url")?,
+                    format,
+                    level: level.unwrap_or(log::Level::Error),
+                },
+                format: SinkFormat::Text,
+                filter: filter(&None, &sink.module, &sink.grep)?,
+            });
+        }
+        "syslog" | "journald" => {
+            let protocol = match sink.kind.as_str() {
+                "syslog" => Protocol::Syslog,
+                _ => Protocol::Journald,
+            };
+            let rules = sink
+                .severity
+                .iter()
+                .map(|rule| syslog::parse_rule(rule))
+                .collect::<Result<_, _>>()
+                .map_err(|err| anyhow!("invalid severity: {}", err))?;
+            sinks::syslog_kind(protocol, sink.path.as_deref(), rules)
+        }
+        kind => return Err(anyhow!("unknown kind `{}`", kind)),
+    };
+    let format = match &sink.format {
+        Some(format) => SinkFormat::from_str(format, true)
+            .map_err(|_| anyhow!("invalid format `{}`, expected `text` or `json`", format))?,
+        None => SinkFormat::Text,
+    };
+    Ok(SinkSpec {
+        kind,
+        format,
+        filter: filter(&sink.level, &sink.module, &sink.grep)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A path in the temporary directory unique to the test.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("defmt-listener-{}-{}", std::process::id(), name))
+    }
+
+    /// Loads `text` as the --config file `name`.
+    fn load(name: &str, text: &str) -> anyhow::Result<Config> {
+        let path = temp_path(name);
+        fs::write(&path, text).unwrap();
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    /// The error of loading the sink `sink`.
+    fn sink_error(name: &str, sink: &str) -> String {
+        let text = format!("[[sink]]\n{}", sink);
+        match load(name, &text) {
+            Ok(_) => panic!("{} loaded", sink),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn the_example_loads() {
+        // the example of the module documentation
+        let example = include_str!("config.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("//!"))
+            .skip_while(|line| *line != " ```toml")
+            .skip(1)
+            .take_while(|line| *line != " ```")
+            .map(|line| line.trim_start())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config = load("example.toml", &example).unwrap();
+
+        let filter = config.filter.unwrap();
+        assert_eq!(filter.level, Some(log::Level::Info));
+        assert_eq!(config.color, Some(true));
+        assert_eq!((config.level_map.len(), config.extracts.len()), (2, 1));
+
+        let [file, webhook, journald] = &config.sinks[..] else {
+            panic!("{} sinks", config.sinks.len());
+        };
+        assert!(matches!(&file.kind, SinkKind::File(path) if path == Path::new("trace.log")));
+        assert_eq!(file.format, SinkFormat::Text);
+        assert_eq!(file.filter.level, Some(log::Level::Trace));
+        assert!(matches!(
+            &webhook.kind,
+            SinkKind::Webhook { url, format: WebhookFormat::Slack, level: log::Level::Error }
+                if url == "https://hooks.slack.com/services/..."
+        ));
+        // the webhook takes every frame
+        assert_eq!(webhook.filter.level, None);
+        assert!(matches!(
+            &journald.kind,
+            SinkKind::Syslog { protocol: Protocol::Journald, path, rules }
+                if path == Path::new(syslog::JOURNALD_PATH) && rules.len() == 2
+        ));
+        assert_eq!(journald.filter.level, Some(log::Level::Info));
+    }
+
+    #[test]
+    fn an_empty_file_sets_nothing() {
+        let config = load("empty.toml", "").unwrap();
+        assert!(config.filter.is_none() && config.color.is_none() && config.sinks.is_empty());
+    }
+
+    #[test]
+    fn invalid_sinks() {
+        let err = sink_error("kind.toml", "kind = \"udp\"");
+        assert!(
+            err.contains("Invalid sink 1") && err.contains("unknown kind `udp`"),
+            "{}",
+            err
+        );
+        let err = sink_error("path.toml", "kind = \"file\"");
+        assert!(
+            err.contains("`path` is required for a file sink"),
+            "{}",
+            err
+        );
+        let err = sink_error("url.toml", "kind = \"webhook\"");
+        assert!(
+            err.contains("`url` is required for a webhook sink"),
+            "{}",
+            err
+        );
+        let err = sink_error("address.toml", "kind = \"tcp\"");
+        assert!(
+            err.contains("`address` is required for a tcp sink"),
+            "{}",
+            err
+        );
+
+        let err = sink_error("format.toml", "kind = \"stderr\"\nformat = \"xml\"");
+        assert!(err.contains("invalid format `xml`"), "{}", err);
+        let sink = "kind = \"webhook\"\nurl = \"http://localhost\"\nformat = \"teams\"";
+        let err = sink_error("webhook-format.toml", sink);
+        assert!(err.contains("invalid webhook format `teams`"), "{}", err);
+
+        let sink = "kind = \"syslog\"\nseverity = [\"error=fatal\"]";
+        let err = sink_error("severity.toml", sink);
+        assert!(err.contains("invalid severity `fatal`"), "{}", err);
+        let sink = "kind = \"file\"\npath = \"a.log\"\nseverity = [\"error=crit\"]";
+        let err = sink_error("file-severity.toml", sink);
+        assert!(
+            err.contains("`severity` is only for the syslog and journald sinks"),
+            "{}",
+            err
+        );
+
+        let err = sink_error("level.toml", "kind = \"stderr\"\nlevel = \"fatal\"");
+        assert!(err.contains("invalid level `fatal`"), "{}", err);
+        let err = sink_error("key.toml", "kind = \"stderr\"\nlevels = \"info\"");
+        assert!(
+            err.contains("Invalid --config") && err.contains("levels"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn invalid_console_and_rules() {
+        let err = load("console.toml", "[console]\nlevel = \"loud\"")
+            .err()
+            .unwrap();
+        let err = err.to_string();
+        assert!(
+            err.contains("Invalid [console]") && err.contains("`loud`"),
+            "{}",
+            err
+        );
+        let err = load("regex.toml", "[console]\ngrep = \"(\"").err().unwrap();
+        assert!(err.to_string().contains("Invalid [console]"), "{}", err);
+        let err = load("rules.toml", "level_map = [\"trace\"]").err().unwrap();
+        assert!(err.to_string().contains("Invalid level_map"), "{}", err);
+        let err = load("extract.toml", "extract = [\"vbat\"]").err().unwrap();
+        assert!(err.to_string().contains("Invalid extract"), "{}", err);
+
+        let err = Config::load(&temp_path("missing.toml")).err().unwrap();
+        assert!(
+            err.to_string().contains("Failed to read --config"),
+            "{}",
+            err
+        );
+    }
+}
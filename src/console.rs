@@ -0,0 +1,185 @@
+//! The console as a sink, printing the frames like the logger of defmt-decoder as set by `--json`,
+//! `--level-style`, `--pretty-args` and `--max-line-width`.
+
+use colored::Colorize;
+use defmt_decoder::Frame;
+use serde_json::json;
+use std::fmt::Write as _;
+
+use crate::{
+    alarm::Event,
+    filter,
+    level::{self, LevelStyle},
+    level_map, pretty, render_timestamp, sanitize,
+    sinks::{Record, Sink},
+    wrap,
+    wrap::Overflow,
+    Args, LocationInfo,
+};
+
+pub struct Console {
+    json: bool,
+    quiet: bool,
+    level_style: LevelStyle,
+    pretty_args: bool,
+    max_line_width: Option<usize>,
+    line_overflow: Overflow,
+    /// The device timestamp and the logger target of the last frame, reused between the frames
+    timestamp: String,
+    target: String,
+}
+
+impl Console {
+    pub fn new(args: &Args) -> Self {
+        Console {
+            json: args.json,
+            quiet: args.quiet,
+            level_style: args.level_style,
+            pretty_args: args.pretty_args,
+            max_line_width: args.max_line_width,
+            line_overflow: args.line_overflow,
+            timestamp: String::new(),
+            target: String::new(),
+        }
+    }
+
+    /// The message of `frame` as set by `--pretty-args` and `--max-line-width`, its lines after
+    /// the first indented to line up with it.
+    fn render_message(&self, frame: &Frame, prefix: &str, timestamp: Option<&str>) -> String {
+        let mut message = sanitize::message(frame);
+        if self.pretty_args {
+            message = pretty::render(&message);
+        }
+
+        let indent = line_prefix_width(frame, prefix, timestamp, self.level_style);
+        match self.max_line_width {
+            Some(width) => wrap::fit(&message, indent, width, self.line_overflow),
+            None => wrap::indent(&message, indent),
+        }
+    }
+}
+
+impl Sink for Console {
+    fn frame(&mut self, record: &Record) {
+        let (frame, location) = (record.frame, record.location);
+        print!("{}", record.prefix);
+        let timestamp = render_timestamp(frame, &mut self.timestamp)
+            .is_some()
+            .then_some(self.timestamp.as_str());
+        let message = (self.pretty_args || self.max_line_width.is_some() || sanitize::active())
+            .then(|| self.render_message(frame, record.prefix, timestamp));
+        match (self.level_style, message) {
+            (_, message) if self.json && record.label.is_some() => {
+                let message = message.unwrap_or_else(|| sanitize::message(frame));
+                let label = record.label.expect("checked");
+                print_json(frame, &message, location, label, timestamp);
+            }
+            (LevelStyle::Word, None) if !level_map::active() => forward_to_logger(frame, location),
+            (LevelStyle::Word, message) => {
+                let message = message.unwrap_or_else(|| sanitize::message(frame));
+                log_message(frame, &message, location, timestamp, &mut self.target)
+            }
+            (style, message) => {
+                let message = message.unwrap_or_else(|| sanitize::message(frame));
+                level::print(frame, &message, &location, style);
+            }
+        }
+    }
+
+    /// Prints the alarms after the label and the host time of the frame, unless `--quiet` or
+    /// `--json`.
+    fn alarm(&mut self, event: &Event, record: &Record) {
+        if self.quiet || self.json {
+            return;
+        }
+        match event.tripped {
+            true => {
+                let alarm = format!(
+                    "(HOST) ALARM {}: {}",
+                    event,
+                    sanitize::message(record.frame)
+                );
+                println!("{}{}", record.prefix, alarm.red().bold());
+            }
+            false => println!("{}(HOST) alarm {} cleared", record.prefix, event),
+        }
+    }
+}
+
+fn forward_to_logger(frame: &Frame, location_info: LocationInfo) {
+    let (file, line, mod_path) = location_info;
+    defmt_decoder::log::log_defmt(frame, file, line, mod_path);
+}
+
+/// Prints `frame` as the JSON logger does, with the `--name` label as `source`.
+fn print_json(
+    frame: &Frame,
+    message: &str,
+    location: LocationInfo,
+    label: &str,
+    timestamp: Option<&str>,
+) {
+    let (file, line, module) = location;
+    // the crate, the modules and the function, as split by the logger
+    let module_path = module.and_then(|module| {
+        let mut path = module.split("::").collect::<Vec<_>>();
+        let function = path.pop().filter(|_| !path.is_empty())?;
+        let crate_name = path.remove(0);
+        Some(json!({ "crate_name": crate_name, "modules": path, "function": function }))
+    });
+    println!(
+        "{}",
+        json!({
+            "data": message,
+            "host_timestamp": chrono::Utc::now().timestamp_nanos(),
+            "level": level_map::level(frame, module).map(|level| filter::host_level(level).as_str()),
+            "location": { "file": file, "line": line, "module_path": module_path },
+            "target_timestamp": timestamp.unwrap_or_default(),
+            "source": label,
+        })
+    );
+}
+
+/// Logs `frame` like `forward_to_logger`, printing `message` instead of its own, with its target
+/// rendered into `target`.
+fn log_message(
+    frame: &Frame,
+    message: &str,
+    location_info: LocationInfo,
+    timestamp: Option<&str>,
+    target: &mut String,
+) {
+    let (file, line, mod_path) = location_info;
+    let payload = serde_json::json!({
+        "timestamp": timestamp.unwrap_or_default(),
+        "level": level_map::level(frame, mod_path).map(|level| filter::host_level(level).as_str()),
+    });
+    // NOTE the same target as `log_defmt`, which tells the logger it is a defmt frame
+    target.clear();
+    let _ = write!(target, "defmt@{}", payload);
+
+    log::logger().log(
+        &log::Record::builder()
+            .args(format_args!("{}", message))
+            .target(target)
+            .module_path(mod_path)
+            .file(file)
+            .line(line)
+            .build(),
+    );
+}
+
+/// Columns printed before the message: the `prefix`, the device timestamp and the level.
+fn line_prefix_width(
+    frame: &Frame,
+    prefix: &str,
+    timestamp: Option<&str>,
+    style: LevelStyle,
+) -> usize {
+    let timestamp = timestamp.map_or(0, |timestamp| timestamp.chars().count() + 1);
+    let level = match frame.level() {
+        Some(_) => style.width() + 1,
+        None => 0,
+    };
+    wrap::columns(prefix) + timestamp + level
+}
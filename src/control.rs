@@ -0,0 +1,150 @@
+//! `--control`, a small HTTP API to manage a headless listener.
+//!
+//! Requests are served from a thread and handed to the session, which polls them like key presses:
+//! `POST /pause`, `POST /resume`, `POST /filter` with the filter options as the body, `POST /stats`,
+//! `POST /rotate` and `POST /shutdown`. Responses are JSON, `{"result": ...}` or `{"error": ...}`.
+
+use anyhow::anyhow;
+use serde_json::json;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{shutdown, stats};
+
+/// How long a request may take, the session only polls while it isn't busy decoding
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BODY: usize = 64 * 1024;
+
+/// A request to be handled by the session.
+pub enum Request {
+    Pause,
+    Resume,
+    /// The filter options, as typed in interactive mode
+    Filter(String),
+    Rotate,
+}
+
+pub type Reply = Sender<Result<String, String>>;
+
+pub struct Control {
+    receiver: Receiver<(Request, Reply)>,
+}
+
+impl Control {
+    /// Serves the API on `address`, e.g. `127.0.0.1:8080`.
+    pub fn spawn(address: &str) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address)
+            .map_err(|err| anyhow!("Failed to listen on --control {}: {}", address, err))?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for connection in listener.incoming().flatten() {
+                let _ = serve(connection, &sender);
+            }
+        });
+        Ok(Control { receiver })
+    }
+
+    /// The next pending request, and where to send its result.
+    pub fn poll(&self) -> Option<(Request, Reply)> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+fn serve(mut connection: TcpStream, sender: &Sender<(Request, Reply)>) -> anyhow::Result<()> {
+    connection.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(connection.try_clone()?);
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let (method, path) = (
+        words.next().unwrap_or_default(),
+        words.next().unwrap_or_default(),
+    );
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or_default();
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return respond(&mut connection, 413, Err("body too large".to_string()));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body).trim().to_string();
+
+    let request = match (method, path) {
+        ("POST", "/pause") => Request::Pause,
+        ("POST", "/resume") => Request::Resume,
+        ("POST" | "PUT", "/filter") => Request::Filter(body),
+        ("POST", "/rotate") => Request::Rotate,
+        ("POST", "/stats") => {
+            stats::request();
+            return respond(&mut connection, 200, Ok("statistics printed".to_string()));
+        }
+        ("POST", "/shutdown") => {
+            shutdown::request();
+            return respond(&mut connection, 200, Ok("shutting down".to_string()));
+        }
+        (_, "/pause" | "/resume" | "/filter" | "/rotate" | "/stats" | "/shutdown") => {
+            return respond(&mut connection, 405, Err("use POST".to_string()));
+        }
+        _ => return respond(&mut connection, 404, Err("not found".to_string())),
+    };
+
+    let (reply, result) = mpsc::channel();
+    sender.send((request, reply))?;
+    match result.recv_timeout(TIMEOUT) {
+        Ok(Ok(result)) => respond(&mut connection, 200, Ok(result)),
+        Ok(Err(err)) => respond(&mut connection, 409, Err(err)),
+        Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => respond(
+            &mut connection,
+            503,
+            Err("the listener didn't handle the request in time".to_string()),
+        ),
+    }
+}
+
+fn respond(
+    connection: &mut TcpStream,
+    status: u16,
+    result: Result<String, String>,
+) -> anyhow::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    };
+    let body = match result {
+        Ok(result) => json!({ "result": result }),
+        Err(err) => json!({ "error": err }),
+    }
+    .to_string();
+    write!(
+        connection,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! `--dbus`, a service on the session bus for desktop tooling.
+//!
+//! `io.github.kusstas.DefmtListener` at `/io/github/kusstas/DefmtListener` emits a `Frame` signal
+//! per printed frame and has the `SetFilter`, `Pause` and `Resume` methods of `--control`.
+
+use anyhow::anyhow;
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::Duration,
+};
+use zbus::{blocking::Connection, fdo, SignalContext};
+
+use crate::{
+    control::{Reply, Request},
+    level_map, sanitize,
+    sinks::{Record, Sink},
+};
+
+const NAME: &str = "io.github.kusstas.DefmtListener";
+const PATH: &str = "/io/github/kusstas/DefmtListener";
+/// How long a method call may take, the session only polls while it isn't busy decoding
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Dbus {
+    connection: Connection,
+    receiver: Receiver<(Request, Reply)>,
+}
+
+impl Dbus {
+    /// Takes the name on the session bus, its methods are served from a thread.
+    pub fn spawn() -> anyhow::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let connection = zbus::blocking::connection::Builder::session()
+            .and_then(|builder| builder.name(NAME))
+            .and_then(|builder| builder.serve_at(PATH, Service { sender }))
+            .and_then(|builder| builder.build())
+            .map_err(|err| {
+                anyhow!(
+                    "Failed to register {} on the D-Bus session bus: {}",
+                    NAME,
+                    err
+                )
+            })?;
+        Ok(Dbus {
+            connection,
+            receiver,
+        })
+    }
+
+    /// The next pending method call, and where to send its result.
+    pub fn poll(&self) -> Option<(Request, Reply)> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// The sink emitting the `Frame` signal.
+    pub fn signals(&self) -> Signals {
+        Signals {
+            connection: self.connection.clone(),
+        }
+    }
+}
+
+/// Emits the `Frame` signal per frame shown on the console.
+pub struct Signals {
+    connection: Connection,
+}
+
+impl Sink for Signals {
+    /// Emits the signal with empty strings and 0 for what the frame doesn't have, the target being
+    /// the name of the ELF.
+    fn frame(&mut self, record: &Record) {
+        let (frame, (file, line, module)) = (record.frame, record.location);
+        let Ok(context) = SignalContext::new(self.connection.inner(), PATH) else {
+            return;
+        };
+        let _ = zbus::block_on(Service::frame(
+            &context,
+            level_map::level(frame, module)
+                .map(|level| level.as_str())
+                .unwrap_or_default(),
+            &sanitize::message(frame),
+            file.unwrap_or_default(),
+            line.unwrap_or_default(),
+            module.unwrap_or_default(),
+            &frame
+                .display_timestamp()
+                .map(|timestamp| timestamp.to_string())
+                .unwrap_or_default(),
+            record.target,
+        ));
+    }
+}
+
+struct Service {
+    sender: Sender<(Request, Reply)>,
+}
+
+impl Service {
+    /// Hands the request to the session and waits for its result.
+    fn request(&self, request: Request) -> fdo::Result<String> {
+        let (reply, result) = mpsc::channel();
+        self.sender
+            .send((request, reply))
+            .map_err(|_| fdo::Error::Failed("the session has ended".to_string()))?;
+        match result.recv_timeout(TIMEOUT) {
+            Ok(result) => result.map_err(fdo::Error::Failed),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => Err(
+                fdo::Error::Failed("the listener didn't handle the request in time".to_string()),
+            ),
+        }
+    }
+}
+
+#[zbus::interface(name = "io.github.kusstas.DefmtListener")]
+impl Service {
+    /// Replaces the filter with options as typed in interactive mode, empty clears it.
+    fn set_filter(&self, filter: String) -> fdo::Result<String> {
+        self.request(Request::Filter(filter))
+    }
+
+    fn pause(&self) -> fdo::Result<String> {
+        self.request(Request::Pause)
+    }
+
+    fn resume(&self) -> fdo::Result<String> {
+        self.request(Request::Resume)
+    }
+
+    #[zbus(signal)]
+    #[allow(clippy::too_many_arguments)]
+    async fn frame(
+        context: &SignalContext<'_>,
+        level: &str,
+        message: &str,
+        file: &str,
+        line: u32,
+        module: &str,
+        device_timestamp: &str,
+        target: &str,
+    ) -> zbus::Result<()>;
+}
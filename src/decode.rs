@@ -0,0 +1,208 @@
+use anyhow::anyhow;
+use defmt_decoder::Frame;
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    capture, corrupted_warning, elf, fetch, frames, level_map, location_info, render_locations,
+    sanitize,
+    window::{self, Bound, Window},
+    LocationInfo,
+};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeFormat {
+    /// One JSON object per frame
+    Jsonl,
+    /// As printed by the listener
+    Text,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DecodeArgs {
+    /// Path or `http(s)://` URL of the ELF file, by default the one embedded in the capture
+    #[arg(long)]
+    elf: Option<PathBuf>,
+    /// ITM stimulus port carrying the defmt data
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Output format, by default JSON Lines if `--out` ends in `.jsonl`, text otherwise
+    #[arg(long, value_enum)]
+    format: Option<DecodeFormat>,
+    /// File to write the frames to instead of stdout
+    #[arg(long, short)]
+    out: Option<PathBuf>,
+    /// Threads decoding the capture, by default one per CPU
+    #[arg(long, short)]
+    jobs: Option<usize>,
+    /// Decode from this time on: a host time like `2024-05-01T12:00:00Z` of a timestamped capture,
+    /// or a device timestamp like `12.5`, in ticks for timestamps in ticks without `--tick-rate`
+    #[arg(long, value_name = "TIME", value_parser = window::parse_bound)]
+    from: Option<Bound>,
+    /// Decode up to this time, like `--from`
+    #[arg(long, value_name = "TIME", value_parser = window::parse_bound)]
+    to: Option<Bound>,
+    /// Device ticks per second, to compare `--from` and `--to` in seconds to timestamps in ticks
+    #[arg(long)]
+    tick_rate: Option<f64>,
+    /// Capture, e.g. written with `--capture`
+    capture: PathBuf,
+}
+
+pub fn run(args: DecodeArgs) -> anyhow::Result<()> {
+    let elf = match args.elf {
+        Some(elf) => elf,
+        None => embedded_elf(&args)?,
+    };
+    let bytes = fetch::read_elf(&elf)?;
+    let table = elf::parse_table(&bytes)?;
+    let locs = table.get_locations(&bytes)?;
+    let locs = match table.indices().all(|idx| locs.contains_key(&(idx as u64))) {
+        true => Some(locs),
+        false => None,
+    };
+    let current_dir = env::current_dir()?;
+    let locations = locs.map(|locs| render_locations(&locs, &current_dir));
+
+    let format =
+        args.format
+            .unwrap_or_else(|| match args.out.as_ref().and_then(|out| out.extension()) {
+                Some(extension) if extension == "jsonl" => DecodeFormat::Jsonl,
+                _ => DecodeFormat::Text,
+            });
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let render = |frame: &Frame| {
+        let location = location_info(&locations, frame);
+        match format {
+            DecodeFormat::Jsonl => json(frame, location).to_string(),
+            DecodeFormat::Text => text(frame, location),
+        }
+    };
+    let mut window = Window::new(args.from.as_ref(), args.to.as_ref(), args.tick_rate);
+    let mut frames = 0;
+    // NOTE the first error writing the output, the decoding can't be stopped from the callback
+    let mut result = Ok(());
+    let mut write = |line: String| {
+        if result.is_err() {
+            return;
+        }
+        frames += 1;
+        result = writeln!(out, "{}", line);
+    };
+
+    let windowed = window.is_some();
+    let stats = match &mut window {
+        // the host times are only known when decoding in order
+        Some(window) if window.needs_host_time() => {
+            if !capture::open_replay(&args.capture, 0.0, None)?.timestamped {
+                return Err(anyhow!(
+                    "--from and --to with a host time need a timestamped capture, {} is raw",
+                    args.capture.display()
+                ));
+            }
+            frames::decode_capture_timed(&args.capture, args.port, &table, |frame, _, host| {
+                let timestamp = frame.display_timestamp().map(|ts| ts.to_string());
+                let host = host.map(|micros| micros as f64 / 1e6);
+                if window.frame(host, timestamp.as_deref()) {
+                    write(render(frame));
+                }
+            })?
+        }
+        window => frames::decode_capture_parallel(
+            &args.capture,
+            args.port,
+            &table,
+            frames::jobs(args.jobs),
+            |frame, _| {
+                let timestamp =
+                    windowed.then(|| frame.display_timestamp().map(|ts| ts.to_string()));
+                (timestamp, render(frame))
+            },
+            |(timestamp, line)| {
+                let inside = window
+                    .as_mut()
+                    .is_none_or(|window| window.frame(None, timestamp.flatten().as_deref()));
+                if inside {
+                    write(line);
+                }
+            },
+        )?,
+    };
+    result?;
+    out.flush()?;
+
+    // NOTE the frames may be written to stdout
+    for region in &stats.corrupted {
+        match args.out {
+            Some(_) => println!("{}", corrupted_warning(region)),
+            None => eprintln!("{}", corrupted_warning(region)),
+        }
+    }
+    if let Some(path) = &args.out {
+        println!(
+            "{} frames ({} malformed) from {} bytes written to {}",
+            frames,
+            stats.malformed,
+            stats.bytes,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// `frame` as a JSON object, as written with `--format jsonl`.
+pub fn json(frame: &Frame, location: LocationInfo) -> Value {
+    let (file, line, module) = location;
+    json!({
+        "index": frame.index(),
+        "timestamp": frame.display_timestamp().map(|ts| ts.to_string()),
+        "level": level_map::level(frame, module).map(|level| level.as_str()),
+        "message": sanitize::message(frame),
+        "file": file,
+        "line": line,
+        "module": module,
+    })
+}
+
+/// `frame` as printed by the listener, followed by its location on a second line.
+pub fn text(frame: &Frame, location: LocationInfo) -> String {
+    let mut text = frame
+        .display_timestamp()
+        .map(|ts| format!("{} ", ts))
+        .unwrap_or_default();
+    if let Some(level) = level_map::level(frame, location.2) {
+        text += &format!("{:<5} ", level.as_str().to_uppercase());
+    }
+    text += &sanitize::message(frame);
+    if let (Some(file), Some(line), Some(module)) = location {
+        text += &format!("\n└─ {} @ {}:{}", module, file, line);
+    }
+    text
+}
+
+/// The ELF of `args.port` embedded in the capture.
+fn embedded_elf(args: &DecodeArgs) -> anyhow::Result<PathBuf> {
+    let header = capture::open_replay(&args.capture, 0.0, None)?.header;
+    header
+        .map(|header| header.extract_elfs())
+        .transpose()?
+        .into_iter()
+        .flatten()
+        .find(|&(port, _)| port == args.port)
+        .map(|(_, path)| path)
+        .ok_or_else(|| {
+            anyhow!(
+                "--elf is required, the capture {} doesn't embed the ELF of port {}",
+                args.capture.display(),
+                args.port
+            )
+        })
+}
@@ -0,0 +1,195 @@
+use anyhow::anyhow;
+use regex::Regex;
+use similar::{Algorithm, DiffTag};
+use std::path::{Path, PathBuf};
+
+use crate::{capture, elf, fetch, frames, replay};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DiffArgs {
+    /// ELF file to decode captures with, path or `http(s)://` URL
+    #[arg(long)]
+    elf: Option<PathBuf>,
+    /// ELF file to decode the second capture with, if different from `--elf`
+    #[arg(long)]
+    elf_b: Option<PathBuf>,
+    /// ITM stimulus port carrying the defmt data
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Number of matching messages shown around each difference
+    #[arg(long, default_value_t = 3)]
+    context: usize,
+    /// Capture or decoded log of the first session
+    a: PathBuf,
+    /// Capture or decoded log of the second session
+    b: PathBuf,
+}
+
+/// A message of a session, without its timestamp.
+struct Entry {
+    text: String,
+    /// The text with numbers masked, identifying the log site in both sessions
+    site: String,
+}
+
+enum Line {
+    Same(usize),
+    /// Same log site, different arguments
+    Changed(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+pub fn run(args: DiffArgs) -> anyhow::Result<()> {
+    let a = read_session(&args.a, args.elf.as_ref(), args.port)?;
+    let b = read_session(
+        &args.b,
+        args.elf_b.as_ref().or(args.elf.as_ref()),
+        args.port,
+    )?;
+
+    let sites = |entries: &[Entry]| entries.iter().map(|e| e.site.clone()).collect::<Vec<_>>();
+    let ops = similar::capture_diff_slices(Algorithm::Myers, &sites(&a), &sites(&b));
+
+    let mut lines = Vec::new();
+    for op in ops {
+        let (tag, old, new) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => {
+                lines.extend(old.zip(new).map(|(i, j)| match a[i].text == b[j].text {
+                    true => Line::Same(i),
+                    false => Line::Changed(i, j),
+                }))
+            }
+            DiffTag::Delete => lines.extend(old.map(Line::Removed)),
+            DiffTag::Insert => lines.extend(new.map(Line::Added)),
+            DiffTag::Replace => {
+                lines.extend(old.map(Line::Removed));
+                lines.extend(new.map(Line::Added));
+            }
+        }
+    }
+
+    // show the differences with `context` lines around them
+    let differs = |line: &Line| !matches!(line, Line::Same(..));
+    let mut last_shown = None;
+    for (n, line) in lines.iter().enumerate() {
+        let start = n.saturating_sub(args.context);
+        let near = lines[start..lines.len().min(n + args.context + 1)]
+            .iter()
+            .any(differs);
+        if !near {
+            continue;
+        }
+
+        if last_shown.is_none_or(|last| last + 1 != n) {
+            let (i, j) = position(&lines[..n]);
+            println!(
+                "@@ {}:{} {}:{} @@",
+                args.a.display(),
+                i + 1,
+                args.b.display(),
+                j + 1
+            );
+        }
+        match line {
+            Line::Same(i) => println!("  {}", a[*i].text),
+            Line::Changed(i, j) => {
+                println!("~ {}", a[*i].text);
+                println!("~ {}", b[*j].text);
+            }
+            Line::Removed(i) => println!("- {}", a[*i].text),
+            Line::Added(j) => println!("+ {}", b[*j].text),
+        }
+        last_shown = Some(n);
+    }
+
+    let count = |f: fn(&Line) -> bool| lines.iter().filter(|line| f(line)).count();
+    println!(
+        "{} messages in {}, {} in {}: {} identical, {} with different arguments, {} removed, {} added",
+        a.len(),
+        args.a.display(),
+        b.len(),
+        args.b.display(),
+        count(|line| matches!(line, Line::Same(..))),
+        count(|line| matches!(line, Line::Changed(..))),
+        count(|line| matches!(line, Line::Removed(..))),
+        count(|line| matches!(line, Line::Added(..))),
+    );
+
+    Ok(())
+}
+
+/// Returns the indices into both sessions following `lines`.
+fn position(lines: &[Line]) -> (usize, usize) {
+    lines.iter().fold((0, 0), |(i, j), line| match line {
+        Line::Same(..) | Line::Changed(..) => (i + 1, j + 1),
+        Line::Removed(_) => (i + 1, j),
+        Line::Added(_) => (i, j + 1),
+    })
+}
+
+fn read_session(path: &Path, elf: Option<&PathBuf>, port: u8) -> anyhow::Result<Vec<Entry>> {
+    let bytes = capture::read(path)?;
+    let numbers = Regex::new(r"[0-9]+")?;
+    let entry = |text: String| Entry {
+        site: numbers.replace_all(&text, "#").into_owned(),
+        text,
+    };
+
+    // decoded logs are text, captures contain ITM headers and frame delimiters
+    if let Ok(text) = std::str::from_utf8(&bytes) {
+        if !text
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x1b'))
+        {
+            return Ok(read_log(text).into_iter().map(entry).collect());
+        }
+    }
+
+    let elf =
+        elf.ok_or_else(|| anyhow!("{} is a capture, pass --elf to decode it", path.display()))?;
+    let elf = fetch::read_elf(elf)?;
+    let table = elf::parse_table(&elf)?;
+
+    let mut entries = Vec::new();
+    frames::decode_capture(path, port, &table, |frame, _| {
+        let message = frame.display_message().to_string();
+        let text = match frame.level() {
+            Some(level) => format!("{} {}", level.as_str().to_uppercase(), message),
+            None => message,
+        };
+        entries.push(entry(normalize(&text)));
+    })?;
+
+    Ok(entries)
+}
+
+/// Extracts the messages of a log printed by defmt-listener, dropping timestamps, locations and
+/// the output of the listener itself.
+fn read_log(text: &str) -> Vec<String> {
+    let ansi = Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex");
+
+    text.lines()
+        .map(|line| ansi.replace_all(line, ""))
+        .filter(|line| !line.trim().is_empty())
+        .filter(|line| !line.starts_with("└─") && !line.starts_with("(HOST)"))
+        .map(|line| {
+            let line = normalize(&line);
+            match line.split_once(' ') {
+                Some((first, rest)) if is_timestamp(first) => rest.to_string(),
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+fn is_timestamp(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_digit())
+        && replay::parse_timestamp(token, Some(1.0)).is_some()
+}
+
+/// Collapses runs of whitespace, e.g. in the padded level column.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
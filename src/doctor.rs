@@ -0,0 +1,322 @@
+use anyhow::anyhow;
+use defmt_decoder::{DecodeError, Table};
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read},
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    elf, fetch,
+    frames::FrameDecoder,
+    framing::{self, Framing},
+    itm::ItmPacket,
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct DoctorArgs {
+    /// Address serving the ITM stream, e.g. `127.0.0.1:50003`
+    #[arg(long, env = "DEFMT_LISTENER_LISTEN")]
+    listen: SocketAddr,
+    /// Path or `http(s)://` URL of the ELF file
+    #[arg(long)]
+    elf: PathBuf,
+    #[arg(long, env = "DEFMT_LISTENER_PORT", default_value_t = 0)]
+    port: u8,
+    /// Seconds to wait for ITM data
+    #[arg(long, default_value_t = 5.0)]
+    wait: f64,
+}
+
+/// Outcome of a check, printed as it's done.
+struct Checks {
+    failed: usize,
+}
+
+impl Checks {
+    fn ok(&self, what: &str, detail: impl AsRef<str>) {
+        println!("ok    {:<11} {}", what, detail.as_ref());
+    }
+
+    fn warn(&self, what: &str, detail: impl AsRef<str>) {
+        println!("warn  {:<11} {}", what, detail.as_ref());
+    }
+
+    fn fail(&mut self, what: &str, detail: impl AsRef<str>) {
+        println!("FAIL  {:<11} {}", what, detail.as_ref());
+        self.failed += 1;
+    }
+}
+
+/// What arrived during the wait.
+#[derive(Default)]
+struct Traffic {
+    bytes: usize,
+    /// Stimulus packets by port
+    packets: BTreeMap<u8, usize>,
+    overflows: usize,
+    invalid_headers: usize,
+    frames: usize,
+    malformed: usize,
+    /// The first bytes, to detect another framing
+    sample: Vec<u8>,
+}
+
+pub fn run(args: DoctorArgs) -> anyhow::Result<()> {
+    let mut checks = Checks { failed: 0 };
+
+    let table = check_elf(&args, &mut checks);
+
+    let mut stream = match TcpStream::connect_timeout(&args.listen, Duration::from_secs(3)) {
+        Ok(stream) => {
+            checks.ok("connection", format!("connected to {}", args.listen));
+            stream
+        }
+        Err(err) => {
+            checks.fail(
+                "connection",
+                format!(
+                    "can't connect to {}: {}; is OpenOCD (or the probe server) running and \
+                     serving the trace output on this port?",
+                    args.listen, err
+                ),
+            );
+            return summarize(&checks);
+        }
+    };
+
+    let traffic = receive(&mut stream, &args, table.as_ref())?;
+    check_traffic(&traffic, &args, table.as_ref(), &mut checks);
+
+    summarize(&checks)
+}
+
+fn check_elf(args: &DoctorArgs, checks: &mut Checks) -> Option<Table> {
+    let parsed = fetch::read_elf(&args.elf)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| {
+            let table = elf::parse_table(&bytes)?;
+            let locs = table.get_locations(&bytes)?;
+            Ok((table, locs))
+        });
+    let (table, locs) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            checks.fail("ELF", format!("{}: {}", args.elf.display(), err));
+            return None;
+        }
+    };
+
+    checks.ok(
+        "ELF",
+        format!(
+            "{}: defmt table with {} entries",
+            args.elf.display(),
+            table.indices().count()
+        ),
+    );
+    match table.encoding().can_recover() {
+        true => checks.ok(
+            "encoding",
+            format!("{:?}, decoding resumes after lost data", table.encoding()),
+        ),
+        false => checks.warn(
+            "encoding",
+            format!(
+                "{:?} can't resume after lost data, a single dropped byte ends the session; \
+                 prefer the rzcobs encoding",
+                table.encoding()
+            ),
+        ),
+    }
+    let missing = table
+        .indices()
+        .filter(|&index| !locs.contains_key(&(index as u64)))
+        .count();
+    match missing {
+        0 => checks.ok("locations", "every log site has a location"),
+        _ => checks.warn(
+            "locations",
+            format!(
+                "{} log sites lack a location, so none are printed; build with `debug = 2` \
+                 in the cargo profile",
+                missing
+            ),
+        ),
+    }
+
+    Some(table)
+}
+
+fn receive(
+    stream: &mut TcpStream,
+    args: &DoctorArgs,
+    table: Option<&Table>,
+) -> anyhow::Result<Traffic> {
+    let mut traffic = Traffic::default();
+    let mut itm_packet = ItmPacket::new();
+    itm_packet.quiet = true;
+    let mut decoder = table.map(FrameDecoder::new);
+
+    let deadline = Instant::now() + Duration::from_secs_f64(args.wait);
+    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+    let mut buffer = [0; 4096];
+    while Instant::now() < deadline {
+        let n = match stream.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                continue
+            }
+            Err(err) => return Err(anyhow!("Read failed: {}", err)),
+        };
+
+        traffic.bytes += n;
+        let missing = framing::SAMPLE.saturating_sub(traffic.sample.len());
+        traffic.sample.extend(buffer[..n].iter().take(missing));
+        for &byte in &buffer[..n] {
+            let port = match itm_packet.receive_any(byte) {
+                Some(port) => port,
+                None => continue,
+            };
+            *traffic.packets.entry(port).or_default() += 1;
+            if let (true, Some(decoder)) = (port == args.port, &mut decoder) {
+                decoder.received(itm_packet.payload());
+                loop {
+                    match decoder.decode() {
+                        Ok(_) => traffic.frames += 1,
+                        Err(DecodeError::UnexpectedEof) => break,
+                        Err(DecodeError::Malformed) => traffic.malformed += 1,
+                    }
+                }
+            }
+        }
+    }
+
+    traffic.overflows = itm_packet.overflows;
+    traffic.invalid_headers = itm_packet.invalid_headers;
+    Ok(traffic)
+}
+
+fn check_traffic(traffic: &Traffic, args: &DoctorArgs, table: Option<&Table>, checks: &mut Checks) {
+    let on_port = traffic.packets.get(&args.port).copied().unwrap_or_default();
+    let total: usize = traffic.packets.values().sum();
+
+    if traffic.bytes == 0 {
+        checks.fail(
+            "ITM",
+            format!(
+                "nothing received within {}s; is the target running, and are the SWO output and \
+                 ITM enabled (e.g. `--openocd-tcl`)?",
+                args.wait
+            ),
+        );
+        return;
+    }
+    if total == 0 || traffic.invalid_headers > total {
+        let cause = match other_framing(traffic, args, table) {
+            Some(framing) => format!(
+                "the stream decodes with `--framing {}` though",
+                framing.name().to_lowercase()
+            ),
+            None => "the SWO or CPU frequency is likely wrong, or the TPIU formatter is on".into(),
+        };
+        checks.fail(
+            "ITM",
+            format!(
+                "{} bytes received, but {} invalid ITM headers and {} packets; {}",
+                traffic.bytes, traffic.invalid_headers, total, cause
+            ),
+        );
+        return;
+    }
+    if on_port == 0 {
+        let ports = traffic
+            .packets
+            .keys()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        checks.fail(
+            "ITM",
+            format!(
+                "{} packets received on port {}, none on --port {}",
+                total, ports, args.port
+            ),
+        );
+        return;
+    }
+    checks.ok(
+        "ITM",
+        format!(
+            "{} bytes, {} packets on port {} within {}s",
+            traffic.bytes, on_port, args.port, args.wait
+        ),
+    );
+    if traffic.overflows > 0 {
+        checks.warn(
+            "ITM",
+            format!(
+                "{} overflow packets, the target logs faster than SWO can send; raise the SWO \
+                 frequency or log less",
+                traffic.overflows
+            ),
+        );
+    }
+
+    if table.is_none() {
+        return;
+    }
+    match (traffic.frames, traffic.malformed) {
+        (0, 0) => checks.warn(
+            "frames",
+            "no complete frame yet, let the target log more or raise --wait",
+        ),
+        (frames, malformed) if malformed > frames => {
+            let cause = match other_framing(traffic, args, table) {
+                Some(framing) => format!(
+                    "the stream decodes with `--framing {}` though",
+                    framing.name().to_lowercase()
+                ),
+                None => "the ELF likely doesn't match the firmware".into(),
+            };
+            checks.fail(
+                "frames",
+                format!(
+                    "{} frames decoded, {} malformed; {}",
+                    frames, malformed, cause
+                ),
+            )
+        }
+        (frames, 0) => checks.ok("frames", format!("{} frames decoded", frames)),
+        (frames, malformed) => checks.warn(
+            "frames",
+            format!(
+                "{} frames decoded, {} malformed; some data is lost on the wire",
+                frames, malformed
+            ),
+        ),
+    }
+}
+
+/// A framing under which the first bytes decode more frames than as ITM.
+fn other_framing(traffic: &Traffic, args: &DoctorArgs, table: Option<&Table>) -> Option<Framing> {
+    let candidates = [Framing::Itm, Framing::Tpiu, Framing::Raw];
+    match framing::detect(&traffic.sample, table?, args.port, &candidates) {
+        (Framing::Itm, _) | (_, 0) => None,
+        (framing, _) => Some(framing),
+    }
+}
+
+fn summarize(checks: &Checks) -> anyhow::Result<()> {
+    println!();
+    match checks.failed {
+        0 => {
+            println!("no problems found");
+            Ok(())
+        }
+        failed => Err(anyhow!("failed checks: {}", failed)),
+    }
+}
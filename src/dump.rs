@@ -0,0 +1,67 @@
+use crate::itm::{Completed, ItmPacket};
+
+/// What `--dump-raw` prints.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpRaw {
+    /// The hexdump and the decoded frames
+    Alongside,
+    /// Only the hexdump
+    Only,
+}
+
+/// Annotated hexdump of the received bytes, one ITM packet per line.
+pub struct RawDump {
+    /// Bytes received on this connection
+    offset: usize,
+    /// Offset of the first byte of the current packet
+    start: usize,
+}
+
+impl RawDump {
+    pub fn new() -> Self {
+        RawDump {
+            offset: 0,
+            start: 0,
+        }
+    }
+
+    /// Prints the packet completed by the byte just passed to `itm_packet`, if any and `show` is
+    /// set. `decoded` tells the ports passed to a defmt decoder.
+    pub fn byte(&mut self, itm_packet: &ItmPacket, decoded: impl Fn(u8) -> bool, show: bool) {
+        self.offset += 1;
+        let completed = match itm_packet.completed() {
+            Some(completed) => completed,
+            None => return,
+        };
+        let start = std::mem::replace(&mut self.start, self.offset);
+        if !show {
+            return;
+        }
+
+        let (bytes, note) = match completed {
+            Completed::Stimulus { header, port: p } => {
+                let mut bytes = vec![header];
+                bytes.extend_from_slice(itm_packet.payload());
+                let note = match decoded(p) {
+                    true => format!("port {} -> defmt decoder", p),
+                    false => format!("port {}, skipped", p),
+                };
+                (bytes, note)
+            }
+            Completed::Overflow => (vec![0x70], "overflow, packets were dropped".to_string()),
+            Completed::Invalid(byte) => (vec![byte], "invalid ITM header, skipped".to_string()),
+        };
+
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("(RAW) {:08x}  {:<14}  {}", start, hex, note);
+    }
+
+    /// Prints that the decoder consumed `size` bytes for the frame with `index`.
+    pub fn frame(&self, index: u64, size: usize) {
+        println!("(RAW) frame {:#06x} decoded from {} bytes", index, size);
+    }
+}
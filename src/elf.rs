@@ -0,0 +1,146 @@
+use defmt_decoder::{Location, Table, DEFMT_VERSION};
+use object::{Object, ObjectSection, ObjectSymbol};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+pub fn parse_port_elf(text: &str) -> Result<(u8, PathBuf), String> {
+    match text.split_once('=') {
+        Some((port, elf)) if !elf.is_empty() => match port.parse() {
+            Ok(port) => Ok((port, PathBuf::from(elf))),
+            Err(_) => Err(format!("invalid port `{}`", port)),
+        },
+        _ => Err(format!("invalid `{}`, expected `PORT=ELF`", text)),
+    }
+}
+
+/// Parses the defmt table, explaining wire format version mismatches. Only `DEFMT_VERSION` is
+/// decoded, there is no fallback to other versions of the wire format.
+pub fn parse_table(bytes: &[u8]) -> crate::Result<Table> {
+    if let Some(version) = defmt_version(bytes)? {
+        if version != DEFMT_VERSION {
+            return Err(Error::ElfParse(format!(
+                "defmt wire format version mismatch: the firmware uses version {} ({}), \
+                 defmt-listener supports version {} ({})\nsuggestion: {}",
+                version,
+                describe_version(&version),
+                DEFMT_VERSION,
+                describe_version(DEFMT_VERSION),
+                suggest_for_version(&version)
+            )));
+        }
+    }
+
+    Table::parse(bytes)
+        .map_err(|err| Error::ElfParse(format!("{:#}", err)))?
+        .ok_or_else(|| Error::ElfParse(".defmt data not found".to_string()))
+}
+
+/// Reads the `_defmt_version_` symbol of the firmware.
+fn defmt_version(bytes: &[u8]) -> crate::Result<Option<String>> {
+    let elf = object::File::parse(bytes).map_err(|err| Error::ElfParse(err.to_string()))?;
+
+    // LLD keeps the quotes of the symbol defined in the linker script
+    Ok(elf.symbols().find_map(|entry| {
+        let name = entry.name().ok()?.trim_matches('"');
+        name.strip_prefix("_defmt_version_ = ").map(String::from)
+    }))
+}
+
+fn describe_version(version: &str) -> &'static str {
+    match version {
+        "0.1" => "defmt 0.1",
+        "0.2" => "defmt 0.2",
+        "3" => "defmt 0.3.0 to 0.3.3",
+        "4" => "defmt 0.3.4 or newer",
+        _ if is_git_version(version) => "a git version of defmt",
+        _ => "an unknown version of defmt",
+    }
+}
+
+fn suggest_for_version(version: &str) -> &'static str {
+    match version {
+        "0.1" | "0.2" => "upgrade the firmware to defmt 0.3.3, older wire formats can't be decoded",
+        "4" => "pin `defmt = \"=0.3.3\"` in the firmware, or build defmt-listener against defmt-decoder 0.3.6 or newer",
+        _ if is_git_version(version) => "use a released version of defmt in the firmware, e.g. `defmt = \"=0.3.3\"`",
+        _ => "use `defmt = \"=0.3.3\"` in the firmware",
+    }
+}
+
+fn is_git_version(version: &str) -> bool {
+    version.len() == 40 && version.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// A demangled symbol of the `.defmt` section.
+#[derive(Debug, Deserialize)]
+pub struct DefmtSymbol {
+    /// Address of the symbol, which is the index of the entry in the defmt table
+    #[serde(skip)]
+    pub index: u64,
+    pub tag: String,
+    pub data: String,
+}
+
+impl DefmtSymbol {
+    /// Returns the tag without the `defmt_` prefix, e.g. `info` or `prim`.
+    pub fn kind(&self) -> &str {
+        self.tag.strip_prefix("defmt_").unwrap_or(&self.tag)
+    }
+
+    pub fn is_log_site(&self) -> bool {
+        matches!(
+            self.kind(),
+            "trace" | "debug" | "info" | "warn" | "error" | "println"
+        )
+    }
+}
+
+/// The `.defmt` section of an ELF file.
+#[derive(Debug)]
+pub struct DefmtSection {
+    pub size: u64,
+    /// Symbols sorted by index, the timestamp format is included
+    pub symbols: Vec<DefmtSymbol>,
+}
+
+impl DefmtSection {
+    pub fn parse(bytes: &[u8]) -> crate::Result<Self> {
+        let elf = object::File::parse(bytes).map_err(|err| Error::ElfParse(err.to_string()))?;
+        let section = elf
+            .section_by_name(".defmt")
+            .ok_or_else(|| Error::ElfParse(".defmt data not found".to_string()))?;
+
+        let mut symbols = Vec::new();
+        for entry in elf.symbols() {
+            // everything else in the section (version, encoding, markers) is not JSON encoded
+            let name = match entry.name() {
+                Ok(name) if name.starts_with('{') => name,
+                _ => continue,
+            };
+
+            if entry.section_index() == Some(section.index()) {
+                let mut symbol: DefmtSymbol = serde_json::from_str(name).map_err(|err| {
+                    Error::ElfParse(format!(
+                        "Failed to demangle defmt symbol `{}`: {}",
+                        name, err
+                    ))
+                })?;
+                symbol.index = entry.address();
+                symbols.push(symbol);
+            }
+        }
+        symbols.sort_by_key(|symbol| symbol.index);
+
+        Ok(DefmtSection {
+            size: section.size(),
+            symbols,
+        })
+    }
+}
+
+/// Formats `loc` as `<module> @ <file>:<line>`, with the file relative to `current_dir` if possible.
+pub fn display_location(loc: &Location, current_dir: &Path) -> String {
+    let path = loc.file.strip_prefix(current_dir).unwrap_or(&loc.file);
+    format!("{} @ {}:{}", loc.module, path.display(), loc.line)
+}
@@ -0,0 +1,135 @@
+//! `--email` digests, mailed from a thread every `--email-interval` rather than once per frame.
+
+use chrono::{Local, SecondsFormat};
+use lettre::{message::Mailbox, Message, SmtpTransport, Transport};
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    filter::host_level,
+    level_map, sanitize,
+    sinks::{Record, Sink},
+};
+
+/// Frames listed in one digest, the rest are only counted
+const MAX_DIGEST_LINES: usize = 500;
+
+pub struct Digest {
+    level: log::Level,
+    sender: Sender<String>,
+    thread: JoinHandle<()>,
+}
+
+/// Where and how often the digests are mailed.
+pub struct Mail {
+    pub smtp: String,
+    pub from: Mailbox,
+    pub to: Vec<Mailbox>,
+    pub interval: Duration,
+}
+
+impl Digest {
+    /// Mails frames of `level` or more severe, collected over `mail.interval`.
+    pub fn new(mail: Mail, level: log::Level) -> anyhow::Result<Self> {
+        let transport = SmtpTransport::from_url(&mail.smtp)?.build();
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || send_digests(&transport, &mail, receiver));
+        Ok(Digest {
+            level,
+            sender,
+            thread,
+        })
+    }
+}
+
+impl Sink for Digest {
+    fn frame(&mut self, record: &Record) {
+        let level = match level_map::level(record.frame, record.location.2) {
+            Some(level) if host_level(level) <= self.level => level,
+            _ => return,
+        };
+
+        let location = match record.location {
+            (Some(file), Some(line), _) => format!(" @ {}:{}", file, line),
+            _ => String::new(),
+        };
+        let target = match record.label {
+            Some(label) => format!("{} of {}", record.target, label),
+            None => record.target.to_string(),
+        };
+        let _ = self.sender.send(format!(
+            "{} {:<5} {}: {}{}",
+            Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            level.as_str().to_uppercase(),
+            target,
+            sanitize::message(record.frame),
+            location
+        ));
+    }
+
+    /// Mails what was collected since the last digest before returning.
+    fn finish(self: Box<Self>) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
+
+fn send_digests(transport: &SmtpTransport, mail: &Mail, receiver: Receiver<String>) {
+    let mut lines = Vec::new();
+    let mut count = 0;
+    let mut next = Instant::now() + mail.interval;
+    loop {
+        let ended = match receiver.recv_timeout(next.saturating_duration_since(Instant::now())) {
+            Ok(line) => {
+                count += 1;
+                if lines.len() < MAX_DIGEST_LINES {
+                    lines.push(line);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+
+        if count > 0 {
+            if let Err(err) = send(transport, mail, &lines, count) {
+                println!(
+                    "(HOST) email digest failed, {} frames dropped: {}",
+                    count, err
+                );
+            }
+            lines.clear();
+            count = 0;
+        }
+        if ended {
+            return;
+        }
+        next = Instant::now() + mail.interval;
+    }
+}
+
+fn send(
+    transport: &SmtpTransport,
+    mail: &Mail,
+    lines: &[String],
+    count: usize,
+) -> anyhow::Result<()> {
+    let mut body = lines.join("\n");
+    if count > lines.len() {
+        body += &format!("\n... and {} more", count - lines.len());
+    }
+
+    let mut message = Message::builder().from(mail.from.clone()).subject(format!(
+        "[{}] {} frames logged",
+        env!("CARGO_PKG_NAME"),
+        count
+    ));
+    for to in &mail.to {
+        message = message.to(to.clone());
+    }
+    transport.send(&message.body(body + "\n")?)?;
+    Ok(())
+}
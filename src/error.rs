@@ -0,0 +1,25 @@
+//! The errors of the library, by the stage that failed, for embedding applications to decide what
+//! to retry.
+
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading the stream, a capture or an ELF file failed; worth retrying
+    #[error(transparent)]
+    Transport(#[from] io::Error),
+    /// The ELF file has no usable defmt data
+    #[error("{0}")]
+    ElfParse(String),
+    /// The bytes aren't valid ITM, TPIU or capture framing
+    #[error("{0}")]
+    Framing(String),
+    /// A frame can't be decoded and the encoding can't recover
+    #[error("{0}")]
+    Decode(String),
+    /// Writing a capture failed
+    #[error("{0}")]
+    Sink(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,52 @@
+//! `--events`, one JSON object per line for tooling supervising the listener.
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::io::{self, Write};
+
+pub struct Events {
+    sink: Box<dyn Write + Send>,
+}
+
+impl Events {
+    /// Writes to stderr.
+    pub fn stderr() -> Self {
+        Events {
+            sink: Box::new(io::stderr()),
+        }
+    }
+
+    /// Writes to the file descriptor `fd`, inherited from the parent process.
+    #[cfg(unix)]
+    pub fn fd(fd: i32) -> anyhow::Result<Self> {
+        use std::{fs::File, os::fd::FromRawFd};
+
+        // SAFETY: checked to be open, and nothing else in the listener uses it
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            return Err(anyhow::anyhow!(
+                "--events-fd {} is not an open file descriptor",
+                fd
+            ));
+        }
+        Ok(Events {
+            sink: Box::new(unsafe { File::from_raw_fd(fd) }),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn fd(_: i32) -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!("--events-fd is only supported on Unix"))
+    }
+
+    /// Writes `{"event": event, "time": ..., ...fields}`, dropping it if the sink failed.
+    pub fn emit(&mut self, event: &str, fields: Value) {
+        let mut object = json!({
+            "event": event,
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        });
+        if let (Some(object), Value::Object(fields)) = (object.as_object_mut(), fields) {
+            object.extend(fields);
+        }
+        let _ = writeln!(self.sink, "{}", object).and_then(|()| self.sink.flush());
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// Reads the ELF from a local path or downloads it from an `http(s)://` URL.
+///
+/// Downloads are cached and revalidated with the `ETag` of the server, the cached copy is used
+/// when the server can't be reached.
+pub fn read_elf(elf: &Path) -> crate::Result<Vec<u8>> {
+    match elf.to_str() {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => fetch(url),
+        _ => Ok(fs::read(elf)?),
+    }
+}
+
+fn fetch(url: &str) -> crate::Result<Vec<u8>> {
+    let (cached, etag) = cache_paths(url)?;
+
+    let mut request = ureq::get(url);
+    if cached.exists() {
+        if let Ok(etag) = fs::read_to_string(&etag) {
+            request = request.set("If-None-Match", &etag);
+        }
+    }
+
+    match request.call() {
+        Ok(response) if response.status() == 304 => Ok(fs::read(&cached)?),
+        Ok(response) => {
+            let new_etag = response.header("ETag").map(String::from);
+
+            let mut bytes = Vec::new();
+            response.into_reader().read_to_end(&mut bytes)?;
+
+            fs::write(&cached, &bytes)?;
+            match new_etag {
+                Some(new_etag) => fs::write(&etag, new_etag)?,
+                None => fs::remove_file(&etag).or_else(ignore_not_found)?,
+            }
+
+            Ok(bytes)
+        }
+        Err(err) if cached.exists() => {
+            println!(
+                "(HOST) failed to download the ELF, using the cached copy: {}",
+                err
+            );
+            Ok(fs::read(&cached)?)
+        }
+        Err(err) => Err(io::Error::other(format!("Failed to download the ELF: {}", err)).into()),
+    }
+}
+
+/// The cache directory of the listener, e.g. `~/.cache/defmt-listener`.
+pub fn cache_dir() -> crate::Result<PathBuf> {
+    Ok(env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .ok_or_else(|| io::Error::other("Can't determine a cache directory for ELF files"))?
+        .join(env!("CARGO_PKG_NAME")))
+}
+
+/// Returns the location of the cached ELF and its `ETag` for `url`.
+fn cache_paths(url: &str) -> crate::Result<(PathBuf, PathBuf)> {
+    let dir = cache_dir()?.join("elf");
+    fs::create_dir_all(&dir)?;
+
+    let name = format!("{:016x}", url_hash(url));
+
+    Ok((dir.join(&name), dir.join(name + ".etag")))
+}
+
+/// 64-bit FNV-1a of `url`, stable across builds unlike the std hasher so the cache outlives them.
+fn url_hash(url: &str) -> u64 {
+    url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn ignore_not_found(err: io::Error) -> io::Result<()> {
+    match err.kind() {
+        io::ErrorKind::NotFound => Ok(()),
+        _ => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_hash_is_fnv_1a() {
+        assert_eq!(url_hash(""), 0xcbf29ce484222325);
+        assert_eq!(url_hash("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(url_hash("foobar"), 0x85944171f73967e8);
+    }
+}
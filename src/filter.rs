@@ -0,0 +1,75 @@
+use clap::Parser;
+use regex::Regex;
+
+use crate::{level_map, sinks::Record};
+
+/// Selects the frames to print, editable at runtime in interactive mode.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct Filter {
+    /// Only print frames of this level or more severe (`println` frames are always printed)
+    #[arg(long)]
+    pub level: Option<log::Level>,
+    /// Only print frames of modules starting with this path, can be given multiple times
+    #[arg(long = "module")]
+    pub modules: Vec<String>,
+    /// Only print frames whose message matches this regex
+    #[arg(long)]
+    pub grep: Option<Regex>,
+}
+
+/// Parses a filter typed in interactive mode, e.g. `--level warn --module app::net`.
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true, disable_help_flag = true)]
+struct FilterLine {
+    #[command(flatten)]
+    filter: Filter,
+}
+
+impl Filter {
+    pub fn parse_line(line: &str) -> Result<Self, clap::Error> {
+        FilterLine::try_parse_from(line.split_whitespace()).map(|line| line.filter)
+    }
+
+    /// Whether it matches every frame.
+    pub fn is_empty(&self) -> bool {
+        self.level.is_none() && self.modules.is_empty() && self.grep.is_none()
+    }
+
+    pub fn matches_record(&self, record: &Record) -> bool {
+        let (frame, module) = (record.frame, record.location.2);
+        if let (Some(max), Some(level)) = (self.level, level_map::level(frame, module)) {
+            if host_level(level) > max {
+                return false;
+            }
+        }
+
+        if !self.modules.is_empty() {
+            match module {
+                Some(module) if self.modules.iter().any(|m| module.starts_with(m.as_str())) => {}
+                _ => return false,
+            }
+        }
+
+        match &self.grep {
+            Some(grep) => grep.is_match(record.message()),
+            None => true,
+        }
+    }
+}
+
+/// The first line of a `Filter::parse_line` error, without the `error: ` prefix.
+pub fn error_message(err: &clap::Error) -> String {
+    let err = err.to_string();
+    let line = err.lines().next().unwrap_or_default();
+    line.trim_start_matches("error: ").to_string()
+}
+
+pub fn host_level(level: defmt_parser::Level) -> log::Level {
+    match level {
+        defmt_parser::Level::Trace => log::Level::Trace,
+        defmt_parser::Level::Debug => log::Level::Debug,
+        defmt_parser::Level::Info => log::Level::Info,
+        defmt_parser::Level::Warn => log::Level::Warn,
+        defmt_parser::Level::Error => log::Level::Error,
+    }
+}
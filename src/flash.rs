@@ -0,0 +1,56 @@
+//! `--flash`, programming the ELF through probe-rs before listening.
+
+use anyhow::anyhow;
+use probe_rs::{
+    flashing::{DownloadOptions, ElfLoader, ElfOptions},
+    probe::DebugProbeSelector,
+    Permissions,
+};
+use std::{io::Cursor, path::Path, time::Instant};
+
+use crate::{fetch, rtt};
+
+/// Programs `elf` to `chip` and resets it to run.
+pub fn flash(
+    chip: &str,
+    probe: Option<&DebugProbeSelector>,
+    elf: &Path,
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let bytes = fetch::read_elf(elf)?;
+    let failed =
+        |err: &dyn std::fmt::Display| anyhow!("Failed to flash {}: {}", elf.display(), err);
+
+    if !quiet {
+        println!("(HOST) flashing {} to {}...", elf.display(), chip);
+    }
+    let start = Instant::now();
+    let mut session = rtt::open_probe(probe)
+        .map_err(|err| failed(&err))?
+        .attach(chip, Permissions::default())
+        .map_err(|err| failed(&err))?;
+    let mut loader = session.target().flash_loader();
+    loader
+        .load_image(
+            &mut session,
+            &mut Cursor::new(bytes),
+            ElfLoader(ElfOptions::default()),
+            None,
+        )
+        .map_err(|err| failed(&err))?;
+    loader
+        .commit(&mut session, DownloadOptions::default())
+        .map_err(|err| failed(&err))?;
+    session
+        .core(0)
+        .and_then(|mut core| core.reset())
+        .map_err(|err| failed(&err))?;
+
+    if !quiet {
+        println!(
+            "(HOST) flashed in {:.1}s, target reset",
+            start.elapsed().as_secs_f64()
+        );
+    }
+    Ok(())
+}
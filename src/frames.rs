@@ -0,0 +1,712 @@
+use defmt_decoder::{DecodeError, Encoding, Frame, Table};
+use std::{
+    io::{BufRead, BufReader},
+    mem,
+    ops::Range,
+    path::Path,
+    sync::atomic::Ordering,
+    thread,
+};
+
+use crate::{capture, itm::ItmPacket, Error};
+
+/// Bytes of defmt data `decode_capture_parallel` collects before splitting them between threads
+#[cfg(not(test))]
+const BLOCK: usize = 16 << 20;
+/// Small enough for the frames of the tests to straddle the blocks
+#[cfg(test)]
+const BLOCK: usize = 100;
+
+/// Totals of a decoded capture.
+pub struct CaptureStats {
+    pub bytes: usize,
+    pub malformed: usize,
+    /// See `capture::Opened::corrupted`
+    pub corrupted: Vec<Range<u64>>,
+}
+
+/// Stream decoder that also reports how many encoded bytes each frame took.
+pub struct FrameDecoder<'t> {
+    table: &'t Table,
+    data: Vec<u8>,
+    /// Encoded bytes of the last frame
+    frame: Vec<u8>,
+    /// Decoded bytes of the last rzCOBS frame
+    decoded: Vec<u8>,
+    /// Bytes buffered without a complete frame before they're dropped as malformed
+    pub max_bytes: Option<usize>,
+    /// Whether the last malformed frame was dropped for exceeding `max_bytes`
+    overflowed: bool,
+}
+
+impl<'t> FrameDecoder<'t> {
+    pub fn new(table: &'t Table) -> Self {
+        FrameDecoder {
+            table,
+            data: Vec::new(),
+            frame: Vec::new(),
+            decoded: Vec::new(),
+            max_bytes: None,
+            overflowed: false,
+        }
+    }
+
+    /// Encoded bytes of the last frame, e.g. to show a malformed one.
+    ///
+    /// Malformed raw frames can't be delimited, only the bytes skipped by `resync` are known.
+    pub fn last_frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Whether the last malformed frame was the bytes dropped for exceeding `max_bytes`.
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Skips the bytes of a malformed raw frame up to the next plausible frame start, i.e. the
+    /// first position that doesn't decode as malformed. Returns the number of skipped bytes.
+    ///
+    /// Malformed rzCOBS frames are skipped by `decode` already.
+    pub fn resync(&mut self) -> usize {
+        // the bytes of an overflow are dropped already
+        if !matches!(self.table.encoding(), Encoding::Raw) || self.data.is_empty() {
+            return 0;
+        }
+
+        let skip = (1..self.data.len())
+            .find(|&start| {
+                !matches!(
+                    self.table.decode(&self.data[start..]),
+                    Err(DecodeError::Malformed)
+                )
+            })
+            .unwrap_or(self.data.len());
+        self.frame.clear();
+        self.frame.extend(self.data.drain(..skip));
+        skip
+    }
+
+    /// Whether no bytes of an incomplete frame are buffered.
+    pub fn idle(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn received(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// The buffered bytes of an incomplete frame, to be passed on to another decoder.
+    pub fn into_pending(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Decodes the next frame and returns it with its encoded size. The frame only borrows the
+    /// table, e.g. to be passed to another thread.
+    pub fn decode(&mut self) -> Result<(Frame<'t>, usize), DecodeError> {
+        // a desynchronized stream may never complete a frame
+        self.overflowed = self.max_bytes.is_some_and(|max| self.data.len() > max)
+            && match self.table.encoding() {
+                Encoding::Raw => matches!(
+                    self.table.decode(&self.data),
+                    Err(DecodeError::UnexpectedEof)
+                ),
+                _ => !self.data.contains(&0),
+            };
+        if self.overflowed {
+            self.frame = std::mem::take(&mut self.data);
+            return Err(DecodeError::Malformed);
+        }
+
+        match self.table.encoding() {
+            Encoding::Raw => {
+                let (frame, consumed) = self.table.decode(&self.data)?;
+                self.frame.clear();
+                self.frame.extend(self.data.drain(..consumed));
+                Ok((frame, consumed))
+            }
+            // rzCOBS
+            _ => {
+                // leading zeros are frame separators without a frame
+                let start = self.data.iter().take_while(|&&byte| byte == 0).count();
+                self.data.drain(..start);
+
+                let end = self
+                    .data
+                    .iter()
+                    .position(|&byte| byte == 0)
+                    .ok_or(DecodeError::UnexpectedEof)?;
+                self.frame.clear();
+                self.frame.extend(self.data.drain(..=end));
+
+                rzcobs_decode(&self.frame[..end], &mut self.decoded)?;
+                match self.table.decode(&self.decoded) {
+                    Ok((frame, _)) => Ok((frame, self.frame.len())),
+                    Err(_) => Err(DecodeError::Malformed),
+                }
+            }
+        }
+    }
+}
+
+/// Decodes a complete rzCOBS frame without its zero separator into `decoded`, as the stream
+/// decoder of defmt-decoder does; its frames borrow the decoder rather than the table.
+fn rzcobs_decode(data: &[u8], decoded: &mut Vec<u8>) -> Result<(), DecodeError> {
+    decoded.clear();
+    let mut data = data.iter().rev().copied();
+    while let Some(byte) = data.next() {
+        match byte {
+            0 => return Err(DecodeError::Malformed),
+            // the zeros among the next 7 bytes, from the end
+            0x01..=0x7f => {
+                for i in 0..7 {
+                    match byte & (1 << (6 - i)) {
+                        0 => decoded.push(data.next().ok_or(DecodeError::Malformed)?),
+                        _ => decoded.push(0),
+                    }
+                }
+            }
+            // a zero after 7 to 133 non-zero bytes
+            0x80..=0xfe => {
+                decoded.push(0);
+                for _ in 0..(byte & 0x7f) + 7 {
+                    decoded.push(data.next().ok_or(DecodeError::Malformed)?);
+                }
+            }
+            0xff => {
+                for _ in 0..134 {
+                    decoded.push(data.next().ok_or(DecodeError::Malformed)?);
+                }
+            }
+        }
+    }
+    decoded.reverse();
+    Ok(())
+}
+
+/// Decodes the ITM capture at `path`, raw or timestamped, passing each frame on `port` and its encoded size to `f`.
+pub fn decode_capture(
+    path: &Path,
+    port: u8,
+    table: &Table,
+    f: impl FnMut(&Frame<'_>, usize),
+) -> crate::Result<CaptureStats> {
+    let opened = capture::open_replay(path, 0.0, None)?;
+    let mut stats = decode_stream(BufReader::new(opened.source), port, table, f)?;
+    stats.corrupted = mem::take(&mut opened.corrupted.lock().unwrap());
+    Ok(stats)
+}
+
+/// Like `decode_capture`, also passing the host time in microseconds since the Unix epoch at which
+/// a timestamped capture received the end of the frame, `None` for raw captures.
+pub fn decode_capture_timed(
+    path: &Path,
+    port: u8,
+    table: &Table,
+    mut f: impl FnMut(&Frame<'_>, usize, Option<u64>),
+) -> crate::Result<CaptureStats> {
+    let opened = capture::open_replay(path, 0.0, None)?;
+    let (timestamped, host_time) = (opened.timestamped, opened.host_time);
+    // NOTE each read of the records returns the bytes of one, so a buffer never spans two
+    let mut stats = decode_stream(BufReader::new(opened.source), port, table, |frame, size| {
+        f(
+            frame,
+            size,
+            timestamped.then(|| host_time.load(Ordering::Relaxed)),
+        )
+    })?;
+    stats.corrupted = mem::take(&mut opened.corrupted.lock().unwrap());
+    Ok(stats)
+}
+
+/// `--jobs`, by default one thread per CPU.
+pub fn jobs(jobs: Option<usize>) -> usize {
+    jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Like `decode_capture`, decoding on `jobs` threads: the defmt data is split at the zeros ending
+/// the rzCOBS frames, `map` runs on the threads and `f` takes its results in capture order.
+///
+/// Raw frames can't be delimited without decoding them, they are decoded on one thread.
+pub fn decode_capture_parallel<T: Send>(
+    path: &Path,
+    port: u8,
+    table: &Table,
+    jobs: usize,
+    map: impl Fn(&Frame<'_>, usize) -> T + Sync,
+    mut f: impl FnMut(T),
+) -> crate::Result<CaptureStats> {
+    if jobs <= 1 || matches!(table.encoding(), Encoding::Raw) {
+        return decode_capture(path, port, table, |frame, size| f(map(frame, size)));
+    }
+
+    let opened = capture::open_replay(path, 0.0, None)?;
+    let mut source = BufReader::new(opened.source);
+    let mut itm_packet = ItmPacket::new();
+    let mut data = Vec::new();
+    let mut stats = CaptureStats {
+        bytes: 0,
+        malformed: 0,
+        corrupted: Vec::new(),
+    };
+
+    loop {
+        let chunk = source.fill_buf()?;
+        let end = chunk.is_empty();
+        let len = chunk.len();
+        for packet in itm_packet.packets(chunk) {
+            if packet.port == port {
+                data.extend_from_slice(packet.payload());
+            }
+        }
+        stats.bytes += len;
+        source.consume(len);
+        if data.len() < BLOCK && !end {
+            continue;
+        }
+
+        // the bytes after the last zero start a frame of the next block
+        let complete = match end {
+            true => data.len(),
+            false => data
+                .iter()
+                .rposition(|&byte| byte == 0)
+                .map_or(0, |i| i + 1),
+        };
+        let results = thread::scope(|scope| {
+            let threads = split(&data[..complete], jobs)
+                .into_iter()
+                .map(|chunk| scope.spawn(|| decode_chunk(chunk, table, &map)))
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("decoding thread panicked"))
+                .collect::<Vec<_>>()
+        });
+        for (items, malformed) in results {
+            items.into_iter().for_each(&mut f);
+            stats.malformed += malformed;
+        }
+        data.drain(..complete);
+
+        if end {
+            break;
+        }
+    }
+
+    stats.corrupted = mem::take(&mut opened.corrupted.lock().unwrap());
+    Ok(stats)
+}
+
+/// Splits `data` into about `parts` chunks of whole rzCOBS frames.
+fn split(data: &[u8], parts: usize) -> Vec<&[u8]> {
+    let size = data.len().div_ceil(parts).max(1);
+    let mut chunks = Vec::with_capacity(parts + 1);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let end = rest
+            .get(size..)
+            .and_then(|tail| tail.iter().position(|&byte| byte == 0))
+            .map_or(rest.len(), |i| size + i + 1);
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+/// The results of `map` for the frames of `chunk`, and the number of malformed ones.
+fn decode_chunk<T>(
+    chunk: &[u8],
+    table: &Table,
+    map: &impl Fn(&Frame<'_>, usize) -> T,
+) -> (Vec<T>, usize) {
+    let mut decoder = FrameDecoder::new(table);
+    let (mut items, mut malformed) = (Vec::new(), 0);
+    // a frame at a time, `decode` moves the bytes after the frame
+    for frame in chunk.split_inclusive(|&byte| byte == 0) {
+        decoder.received(frame);
+        loop {
+            match decoder.decode() {
+                Ok((frame, size)) => items.push(map(&frame, size)),
+                Err(DecodeError::UnexpectedEof) => break,
+                Err(DecodeError::Malformed) => malformed += 1,
+            }
+        }
+    }
+    (items, malformed)
+}
+
+/// Like `decode_capture`, for a raw ITM stream.
+pub fn decode_stream(
+    mut source: impl BufRead,
+    port: u8,
+    table: &Table,
+    mut f: impl FnMut(&Frame<'_>, usize),
+) -> crate::Result<CaptureStats> {
+    let mut itm_packet = ItmPacket::new();
+    let mut decoder = FrameDecoder::new(table);
+    let mut stats = CaptureStats {
+        bytes: 0,
+        malformed: 0,
+        corrupted: Vec::new(),
+    };
+
+    loop {
+        let chunk = source.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let len = chunk.len();
+        for packet in itm_packet.packets(chunk) {
+            if packet.port != port {
+                continue;
+            }
+            decoder.received(packet.payload());
+
+            loop {
+                match decoder.decode() {
+                    Ok((frame, size)) => f(&frame, size),
+                    Err(DecodeError::UnexpectedEof) => break,
+                    Err(DecodeError::Malformed) => match table.encoding().can_recover() {
+                        false => {
+                            return Err(Error::Decode(format!(
+                            "Malformed frame at byte {} of the capture, the encoding can't recover",
+                            stats.bytes + packet.end + 1
+                        )))
+                        }
+                        true => stats.malformed += 1,
+                    },
+                }
+            }
+        }
+        stats.bytes += len;
+        source.consume(len);
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    /// A decoded frame as its message, its timestamp and its encoded size
+    type Decoded = (String, Option<String>, usize);
+
+    /// The table of `fixtures/<encoding>.elf`, built from `fixtures/fw`.
+    pub(crate) fn table(encoding: &str) -> Table {
+        let path = format!("{}/fixtures/{}.elf", env!("CARGO_MANIFEST_DIR"), encoding);
+        Table::parse(&fs::read(path).unwrap()).unwrap().unwrap()
+    }
+
+    /// The frame of `table` printing `message`, one without arguments, at `timestamp` as sent,
+    /// i.e. encoded with rzCOBS and ended with a zero unless the table is raw.
+    pub(crate) fn frame(table: &Table, message: &str, timestamp: u32) -> Vec<u8> {
+        let raw = table
+            .indices()
+            .map(|index| {
+                let mut bytes = (index as u16).to_le_bytes().to_vec();
+                bytes.extend(timestamp.to_le_bytes());
+                bytes
+            })
+            .find(|bytes| {
+                table
+                    .decode(bytes)
+                    .is_ok_and(|(frame, _)| frame.display_message().to_string() == message)
+            })
+            .expect("no frame prints the message");
+        match table.encoding() {
+            Encoding::Raw => raw,
+            _ => {
+                let mut encoded = rzcobs_encode(&raw);
+                encoded.push(0);
+                encoded
+            }
+        }
+    }
+
+    /// `data` wrapped in ITM packets of one byte on `port`.
+    pub(crate) fn itm(data: &[u8], port: u8) -> Vec<u8> {
+        data.iter()
+            .flat_map(|&byte| [port << 3 | 1, byte])
+            .collect()
+    }
+
+    /// Encodes `data` with rzCOBS as defmt does, without the zero ending the frame.
+    fn rzcobs_encode(data: &[u8]) -> Vec<u8> {
+        let (mut encoded, mut run, mut zeros) = (Vec::new(), 0, 0u8);
+        for &byte in data {
+            if run < 7 {
+                match byte {
+                    0 => zeros |= 1 << run,
+                    _ => encoded.push(byte),
+                }
+                run += 1;
+                if run == 7 && zeros != 0 {
+                    encoded.push(zeros);
+                    (run, zeros) = (0, 0);
+                }
+            } else if byte == 0 {
+                encoded.push((run - 7) as u8 | 0x80);
+                (run, zeros) = (0, 0);
+            } else {
+                encoded.push(byte);
+                run += 1;
+                if run == 134 {
+                    encoded.push(0xff);
+                    (run, zeros) = (0, 0);
+                }
+            }
+        }
+        // the missing bytes of the last group decode as zeros
+        match run {
+            0 => {}
+            1..=6 => encoded.push(zeros | (0x7f << run & 0x7f)),
+            _ => encoded.push((run - 7) as u8 | 0x80),
+        }
+        encoded
+    }
+
+    /// Bytes with runs of zeros and of non-zero bytes of all lengths.
+    fn data(len: usize, seed: u32) -> Vec<u8> {
+        // a linear congruential generator, for the same bytes every run
+        let mut state = seed;
+        let mut zeros = false;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                if state >> 28 == 0 {
+                    zeros = !zeros;
+                }
+                match zeros {
+                    true => 0,
+                    false => (state >> 16) as u8 | 1,
+                }
+            })
+            .collect()
+    }
+
+    fn decoded(frame: &Frame, size: usize) -> Decoded {
+        let timestamp = frame.display_timestamp().map(|ts| ts.to_string());
+        (frame.display_message().to_string(), timestamp, size)
+    }
+
+    /// The frames of `table` on port 0 of the capture at `path`, by `decode_capture`.
+    fn decode_all(path: &Path, table: &Table) -> (Vec<Decoded>, usize) {
+        let mut frames = Vec::new();
+        let stats = decode_capture(path, 0, table, |frame, size| {
+            frames.push(decoded(frame, size))
+        })
+        .unwrap();
+        (frames, stats.malformed)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("defmt-listener-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn rzcobs_round_trips() {
+        let mut decoded = Vec::new();
+        for len in (0..300).chain([1000, 4000]) {
+            let data = data(len, len as u32);
+            rzcobs_decode(&rzcobs_encode(&data), &mut decoded).unwrap();
+            // up to 6 zeros complete the last group of 7 bytes
+            let (head, tail) = decoded.split_at(data.len());
+            assert_eq!(head, data, "{} bytes", len);
+            assert!(tail.len() < 7 && tail.iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    fn rzcobs_rejects_truncated_frames() {
+        let mut decoded = Vec::new();
+        // a group of 7 bytes with one zero, without the 6 others
+        assert!(matches!(
+            rzcobs_decode(&[0x01], &mut decoded),
+            Err(DecodeError::Malformed)
+        ));
+        assert!(matches!(
+            rzcobs_decode(&[1, 2, 0x81], &mut decoded),
+            Err(DecodeError::Malformed)
+        ));
+        assert!(matches!(
+            rzcobs_decode(&[0, 0x7f], &mut decoded),
+            Err(DecodeError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn frames_match_the_stream_decoder_of_defmt_decoder() {
+        for encoding in ["rzcobs", "raw"] {
+            let table = table(encoding);
+            // timestamps with zeros, and runs of bytes without
+            let timestamps = [0, 1, 0x100, 0xff00_ff00, 0x0102_0304, u32::MAX];
+            let bytes = (timestamps.iter())
+                .flat_map(|&ts| [frame(&table, "boot", ts), frame(&table, "tick", ts)])
+                .flatten()
+                .collect::<Vec<_>>();
+
+            let mut expected = Vec::new();
+            let mut stream = table.new_stream_decoder();
+            stream.received(&bytes);
+            while let Ok(frame) = stream.decode() {
+                expected.push(decoded(&frame, 0));
+            }
+            assert_eq!(expected.len(), 2 * timestamps.len());
+
+            // reads of all sizes, so frames straddle them
+            for read in [1, 2, 5, bytes.len()] {
+                let mut decoder = FrameDecoder::new(&table);
+                let (mut frames, mut size) = (Vec::new(), 0);
+                for chunk in bytes.chunks(read) {
+                    decoder.received(chunk);
+                    while let Ok((frame, encoded)) = decoder.decode() {
+                        frames.push(decoded(&frame, 0));
+                        size += encoded;
+                    }
+                }
+                assert_eq!(frames, expected, "{} in reads of {}", encoding, read);
+                assert_eq!(size, bytes.len());
+                assert!(decoder.idle());
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_rzcobs_frames_are_skipped() {
+        let table = table("rzcobs");
+        let mut decoder = FrameDecoder::new(&table);
+        // leading zeros separate no frames
+        decoder.received(&[0, 0, 0x01, 0]);
+        decoder.received(&frame(&table, "boot", 1));
+        assert!(matches!(decoder.decode(), Err(DecodeError::Malformed)));
+        assert_eq!(decoder.last_frame(), [0x01, 0]);
+        assert!(!decoder.overflowed());
+        // nothing to resynchronize, the frame is dropped already
+        assert_eq!(decoder.resync(), 0);
+        let (frame, _) = decoder.decode().unwrap();
+        assert_eq!(frame.display_message().to_string(), "boot");
+    }
+
+    #[test]
+    fn raw_frames_resync_after_malformed_bytes() {
+        let table = table("raw");
+        let mut decoder = FrameDecoder::new(&table);
+        // no frame has the index 0xffff
+        decoder.received(&[0xff, 0xff]);
+        decoder.received(&frame(&table, "boot", 1));
+        assert!(matches!(decoder.decode(), Err(DecodeError::Malformed)));
+        assert_eq!(decoder.resync(), 2);
+        assert_eq!(decoder.last_frame(), [0xff, 0xff]);
+        let (frame, size) = decoder.decode().unwrap();
+        assert_eq!(frame.display_message().to_string(), "boot");
+        assert_eq!(size, 6);
+        assert!(decoder.idle());
+        assert_eq!(decoder.resync(), 0);
+    }
+
+    #[test]
+    fn frames_past_max_bytes_overflow() {
+        let table = table("rzcobs");
+        let mut decoder = FrameDecoder::new(&table);
+        decoder.max_bytes = Some(8);
+        decoder.received(&[0x55; 8]);
+        assert!(matches!(decoder.decode(), Err(DecodeError::UnexpectedEof)));
+        decoder.received(&[0x55; 4]);
+        assert!(matches!(decoder.decode(), Err(DecodeError::Malformed)));
+        assert!(decoder.overflowed());
+        assert_eq!(decoder.last_frame(), [0x55; 12]);
+        assert!(decoder.idle());
+
+        // the next frame starts after the dropped bytes
+        decoder.received(&frame(&table, "boot", 1));
+        assert!(decoder.decode().is_ok());
+        assert!(!decoder.overflowed());
+
+        // a raw frame still missing its bytes
+        let table = self::table("raw");
+        let mut decoder = FrameDecoder::new(&table);
+        decoder.max_bytes = Some(4);
+        decoder.received(&frame(&table, "boot", 1)[..5]);
+        assert!(matches!(decoder.decode(), Err(DecodeError::Malformed)));
+        assert!(decoder.overflowed());
+    }
+
+    #[test]
+    fn pending_bytes_are_passed_on() {
+        let table = table("rzcobs");
+        let mut decoder = FrameDecoder::new(&table);
+        let next = frame(&table, "tick", 2);
+        decoder.received(&frame(&table, "boot", 1));
+        decoder.received(&next[..3]);
+        assert!(decoder.decode().is_ok());
+        assert!(matches!(decoder.decode(), Err(DecodeError::UnexpectedEof)));
+        assert!(!decoder.idle());
+        assert_eq!(decoder.into_pending(), next[..3]);
+    }
+
+    #[test]
+    fn chunks_split_after_the_zeros() {
+        let table = table("rzcobs");
+        let frames = (0..10)
+            .map(|ts| frame(&table, "boot", ts))
+            .collect::<Vec<_>>();
+        let data = frames.concat();
+        for parts in [1, 3, 10, 50] {
+            let chunks = split(&data, parts);
+            assert_eq!(chunks.concat(), data);
+            assert!(chunks.len() <= parts.min(frames.len()));
+            assert!(chunks.iter().all(|chunk| chunk.last() == Some(&0)));
+        }
+        assert!(split(&[], 4).is_empty());
+        // the bytes of an incomplete frame end the last chunk
+        assert_eq!(split(&[1, 2, 0, 3], 2), [&[1, 2, 0][..], &[3]]);
+    }
+
+    #[test]
+    fn chunks_decode_with_their_malformed_frames() {
+        let table = table("rzcobs");
+        let chunk = [
+            frame(&table, "boot", 1),
+            vec![0x01, 0],
+            frame(&table, "tick", 2),
+        ]
+        .concat();
+        let (items, malformed) = decode_chunk(&chunk, &table, &|frame, _| {
+            frame.display_message().to_string()
+        });
+        assert_eq!(items, ["boot", "tick"]);
+        assert_eq!(malformed, 1);
+    }
+
+    #[test]
+    fn parallel_decoding_matches_decoding_in_order() {
+        let table = table("rzcobs");
+        // blocks of `BLOCK` bytes end inside the frames
+        let data = (0..500)
+            .flat_map(|ts| {
+                let message = ["boot", "tick"][ts as usize % 2];
+                frame(&table, message, ts * 997)
+            })
+            .chain([0x01, 0])
+            .collect::<Vec<_>>();
+        let path = temp_path("parallel.bin");
+        fs::write(&path, itm(&data, 0)).unwrap();
+
+        let (expected, malformed) = decode_all(&path, &table);
+        assert_eq!((expected.len(), malformed), (500, 1));
+        for jobs in [1, 2, 7] {
+            let mut frames = Vec::new();
+            let stats = decode_capture_parallel(&path, 0, &table, jobs, decoded, |frame| {
+                frames.push(frame)
+            })
+            .unwrap();
+            assert_eq!(frames, expected, "{} jobs", jobs);
+            assert_eq!(stats.malformed, 1);
+            assert_eq!(stats.bytes, 2 * data.len());
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,443 @@
+//! `--framing`, how the defmt bytes are wrapped on the wire.
+
+use defmt_decoder::{DecodeError, Encoding, Table};
+
+use crate::{frames::FrameDecoder, itm::ItmPacket};
+
+/// TPIU source ID of the ITM, as set by OpenOCD and probe-rs
+const ITM_ID: u8 = 1;
+/// Full synchronization packet of the TPIU formatter, sent between frames
+const SYNC: u32 = 0xffff_ff7f;
+const FRAME: usize = 16;
+/// Sent by esp-println before a defmt frame, which ends with the zero of rzCOBS and another one
+const ESP_START: u8 = 0xff;
+/// Bytes inspected by `--framing auto` before it picks, fewer if the stream pauses or ends
+pub const SAMPLE: usize = 256;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// ITM packets, as sent over SWO with the TPIU formatter off
+    Itm,
+    /// ITM packets in the 16-byte frames of the TPIU formatter
+    Tpiu,
+    /// The defmt frames of esp-println between `ff 00` and `00`, with the text printed in between,
+    /// as read by espflash; only `--port` is decoded
+    #[value(name = "esp-println")]
+    EspPrintln,
+    /// The defmt bytes without ITM, e.g. from a UART; only `--port` is decoded
+    Raw,
+    /// Detected from the first bytes of the stream
+    Auto,
+}
+
+impl Framing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Framing::Itm => "ITM",
+            Framing::Tpiu => "TPIU",
+            Framing::EspPrintln => "esp-println",
+            Framing::Raw => "raw",
+            Framing::Auto => "auto",
+        }
+    }
+}
+
+/// Extracts the ITM bytes from the frames of the TPIU formatter.
+pub struct Tpiu {
+    frame: [u8; FRAME],
+    len: usize,
+    /// Source of the following data bytes
+    id: u8,
+    /// The last 4 bytes received, to find the synchronization packets
+    window: u32,
+    data: Vec<u8>,
+}
+
+impl Tpiu {
+    /// Assumes the stream starts at a frame until synchronized.
+    pub fn new() -> Self {
+        Tpiu {
+            frame: [0; FRAME],
+            len: 0,
+            id: 0,
+            window: 0,
+            data: Vec::with_capacity(FRAME),
+        }
+    }
+
+    /// The ITM bytes of the frame completed by `byte`, if any.
+    pub fn receive(&mut self, byte: u8) -> &[u8] {
+        self.data.clear();
+        self.window = self.window << 8 | byte as u32;
+        if self.window == SYNC {
+            self.len = 0;
+            return &self.data;
+        }
+
+        self.frame[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME {
+            return &self.data;
+        }
+        self.len = 0;
+
+        // the even bytes are data with the LSB in the last byte, or an ID change if their LSB is set
+        let aux = self.frame[FRAME - 1];
+        for i in 0..FRAME / 2 {
+            let even = self.frame[2 * i];
+            let aux_bit = aux >> i & 1;
+            // the last byte holds the aux bits rather than data
+            let odd = (i < FRAME / 2 - 1).then(|| self.frame[2 * i + 1]);
+            if even & 1 == 0 {
+                self.push(even | aux_bit);
+                self.push_some(odd);
+            } else if aux_bit == 1 {
+                // the change takes effect after the odd byte
+                self.push_some(odd);
+                self.id = even >> 1;
+            } else {
+                self.id = even >> 1;
+                self.push_some(odd);
+            }
+        }
+        &self.data
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.id == ITM_ID {
+            self.data.push(byte);
+        }
+    }
+
+    fn push_some(&mut self, byte: Option<u8>) {
+        if let Some(byte) = byte {
+            self.push(byte);
+        }
+    }
+}
+
+impl Default for Tpiu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a byte of an esp-println stream completed.
+pub enum EspOutput {
+    Defmt(u8),
+    /// A line of text, without its line break, not necessarily UTF-8
+    Line(Vec<u8>),
+}
+
+/// Tells the defmt frames of esp-println from the text in between.
+pub struct Esp {
+    state: EspState,
+    text: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EspState {
+    Text,
+    /// After `ESP_START`, a frame if a zero follows
+    Start,
+    Frame,
+}
+
+impl Esp {
+    pub fn new() -> Self {
+        Esp {
+            state: EspState::Text,
+            text: Vec::new(),
+        }
+    }
+
+    pub fn receive(&mut self, byte: u8) -> Option<EspOutput> {
+        match (self.state, byte) {
+            (EspState::Frame, 0) => {
+                self.state = EspState::Text;
+                Some(EspOutput::Defmt(0))
+            }
+            (EspState::Frame, _) => Some(EspOutput::Defmt(byte)),
+            // the text printed before the frame ends with it
+            (EspState::Start, 0) => {
+                self.state = EspState::Frame;
+                self.line()
+            }
+            (EspState::Start, _) => {
+                self.text.push(ESP_START);
+                self.state = EspState::Text;
+                self.receive(byte)
+            }
+            (EspState::Text, ESP_START) => {
+                self.state = EspState::Start;
+                None
+            }
+            (EspState::Text, b'\n') => self.line().or(Some(EspOutput::Line(Vec::new()))),
+            // the zero ending a frame, and the carriage returns of line breaks
+            (EspState::Text, 0 | b'\r') => None,
+            (EspState::Text, _) => {
+                self.text.push(byte);
+                None
+            }
+        }
+    }
+
+    fn line(&mut self) -> Option<EspOutput> {
+        if self.text.is_empty() {
+            return None;
+        }
+        Some(EspOutput::Line(std::mem::take(&mut self.text)))
+    }
+}
+
+impl Default for Esp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The framing of `candidates` under which `sample` decodes the most frames of `table` on `port`,
+/// and that number. The first candidate wins a tie, TPIU if nothing decodes but it has its
+/// synchronization packets.
+pub fn detect(sample: &[u8], table: &Table, port: u8, candidates: &[Framing]) -> (Framing, usize) {
+    let (framing, frames) = candidates
+        .iter()
+        .rev()
+        .map(|&framing| (framing, count_frames(sample, table, port, framing)))
+        .max_by_key(|&(_, frames)| frames)
+        .unwrap_or((Framing::Itm, 0));
+    let synced = sample
+        .windows(4)
+        .any(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) == SYNC);
+    match (frames, synced && candidates.contains(&Framing::Tpiu)) {
+        (0, true) => (Framing::Tpiu, 0),
+        _ => (framing, frames),
+    }
+}
+
+fn count_frames(sample: &[u8], table: &Table, port: u8, framing: Framing) -> usize {
+    let mut decoder = FrameDecoder::new(table);
+    let mut itm_packet = ItmPacket::new();
+    itm_packet.quiet = true;
+    let mut tpiu = Tpiu::new();
+    let mut esp = Esp::new();
+
+    let mut itm = |bytes: &[u8], decoder: &mut FrameDecoder| {
+        for packet in itm_packet.packets(bytes) {
+            if packet.port == port {
+                decoder.received(packet.payload());
+            }
+        }
+    };
+    match framing {
+        Framing::Itm => itm(sample, &mut decoder),
+        Framing::Tpiu => {
+            let bytes = sample
+                .iter()
+                .flat_map(|&byte| tpiu.receive(byte).to_vec())
+                .collect::<Vec<_>>();
+            itm(&bytes, &mut decoder);
+        }
+        Framing::EspPrintln => {
+            for &byte in sample {
+                if let Some(EspOutput::Defmt(byte)) = esp.receive(byte) {
+                    decoder.received(&[byte]);
+                }
+            }
+        }
+        Framing::Raw => decoder.received(sample),
+        Framing::Auto => unreachable!("not a candidate"),
+    }
+
+    let mut frames = 0;
+    loop {
+        match decoder.decode() {
+            Ok(_) => frames += 1,
+            Err(DecodeError::UnexpectedEof) => break,
+            // malformed rzCOBS frames are skipped by `decode`
+            Err(DecodeError::Malformed) => {
+                if matches!(table.encoding(), Encoding::Raw) && decoder.resync() == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frames::tests::{frame, itm, table};
+
+    /// A frame switching to the ITM, with data bytes whose LSB is in the aux byte, a deferred
+    /// switch to another source and a switch back.
+    const TPIU_FRAME: [u8; FRAME] = [
+        0x03, 0xa1, 0x10, 0x22, 0x05, 0x33, 0x44, 0x55, 0x03, 0x66, 0x78, 0x99, 0xaa, 0xbb, 0xcc,
+        0x46,
+    ];
+    /// The ITM bytes of `TPIU_FRAME`
+    const TPIU_DATA: [u8; 10] = [0xa1, 0x11, 0x22, 0x33, 0x66, 0x78, 0x99, 0xab, 0xbb, 0xcc];
+
+    /// The ITM bytes of `bytes` received one at a time.
+    fn tpiu_data(tpiu: &mut Tpiu, bytes: &[u8]) -> Vec<u8> {
+        bytes
+            .iter()
+            .flat_map(|&byte| tpiu.receive(byte).to_vec())
+            .collect()
+    }
+
+    /// `data` in TPIU frames of the ITM after a synchronization packet, up to the last whole frame.
+    fn tpiu(data: &[u8]) -> Vec<u8> {
+        let mut bytes = SYNC.to_be_bytes().to_vec();
+        for data in data.chunks_exact(14) {
+            // the ITM from the first byte, its odd byte the first data byte
+            let mut frame = vec![ITM_ID << 1 | 1, data[0]];
+            let mut aux = 0;
+            for (i, pair) in data[1..].chunks(2).enumerate() {
+                frame.push(pair[0] & !1);
+                aux |= (pair[0] & 1) << (i + 1);
+                frame.extend(pair.get(1));
+            }
+            frame.push(aux);
+            bytes.extend(frame);
+        }
+        bytes
+    }
+
+    /// Frames of the rzCOBS table as sent over a UART.
+    fn raw() -> Vec<u8> {
+        let table = table("rzcobs");
+        (0..20)
+            .flat_map(|ts| frame(&table, ["boot", "tick"][ts as usize % 2], ts * 1000))
+            .collect()
+    }
+
+    const CANDIDATES: [Framing; 4] = [
+        Framing::Itm,
+        Framing::Tpiu,
+        Framing::EspPrintln,
+        Framing::Raw,
+    ];
+
+    #[test]
+    fn tpiu_frames_switch_sources() {
+        let mut tpiu = Tpiu::new();
+        assert!(tpiu_data(&mut tpiu, &TPIU_FRAME[..FRAME - 1]).is_empty());
+        assert_eq!(tpiu.receive(TPIU_FRAME[FRAME - 1]), TPIU_DATA);
+        // the ITM is still the source of the next frame
+        let mut next = [0x10; FRAME];
+        next[FRAME - 1] = 0;
+        assert_eq!(tpiu_data(&mut tpiu, &next), [0x10; 15]);
+    }
+
+    #[test]
+    fn tpiu_synchronizes_on_full_sync_packets() {
+        let mut tpiu = Tpiu::new();
+        // the stream starts in the middle of a frame
+        let mut bytes = vec![0x12, 0x34, 0x56];
+        bytes.extend(SYNC.to_be_bytes());
+        bytes.extend(TPIU_FRAME);
+        // and a synchronization packet between frames
+        bytes.extend(SYNC.to_be_bytes());
+        bytes.extend(TPIU_FRAME);
+        assert_eq!(
+            tpiu_data(&mut tpiu, &bytes),
+            [TPIU_DATA, TPIU_DATA].concat()
+        );
+    }
+
+    #[test]
+    fn esp_println_frames_between_text() {
+        let table = table("rzcobs");
+        let mut bytes = b"hello\r\n".to_vec();
+        bytes.extend(b"no frame \xff here\n");
+        bytes.extend(b"before");
+        for ts in [1, 2] {
+            bytes.extend([ESP_START, 0]);
+            bytes.extend(frame(&table, "boot", ts));
+            bytes.push(0);
+        }
+        bytes.extend(b"\nafter\n");
+
+        // reads of all sizes, so the frames and `ff 00` straddle them
+        for read in [1, 2, 3, bytes.len()] {
+            let mut esp = Esp::new();
+            let mut decoder = FrameDecoder::new(&table);
+            let (mut lines, mut frames) = (Vec::new(), 0);
+            for chunk in bytes.chunks(read) {
+                for &byte in chunk {
+                    match esp.receive(byte) {
+                        Some(EspOutput::Defmt(byte)) => decoder.received(&[byte]),
+                        Some(EspOutput::Line(line)) => lines.push(line),
+                        None => {}
+                    }
+                }
+                while decoder.decode().is_ok() {
+                    frames += 1;
+                }
+            }
+            let expected: [&[u8]; 5] = [b"hello", b"no frame \xff here", b"before", b"", b"after"];
+            assert_eq!(lines, expected, "reads of {}", read);
+            assert_eq!(frames, 2);
+        }
+    }
+
+    #[test]
+    fn frames_are_counted_in_each_framing() {
+        let table = table("rzcobs");
+        let raw = raw();
+        let itm = itm(&raw, 0);
+        assert_eq!(count_frames(&raw, &table, 0, Framing::Raw), 20);
+        assert_eq!(count_frames(&itm, &table, 0, Framing::Itm), 20);
+        // only the packets of `port`
+        assert_eq!(count_frames(&itm, &table, 1, Framing::Itm), 0);
+        // up to the last whole TPIU frame
+        let tpiu_frames = itm.len() / 14 * 14;
+        let complete = count_frames(&itm[..tpiu_frames], &table, 0, Framing::Itm);
+        assert!(complete >= 19);
+        assert_eq!(
+            count_frames(&tpiu(&itm), &table, 0, Framing::Tpiu),
+            complete
+        );
+        assert_eq!(count_frames(&raw, &table, 0, Framing::Itm), 0);
+    }
+
+    #[test]
+    fn detects_the_framing_of_samples() {
+        let table = table("rzcobs");
+        let raw = raw();
+        let itm = itm(&raw, 0);
+        let detect = |sample: &[u8]| detect(sample, &table, 0, &CANDIDATES).0;
+        assert_eq!(detect(&itm), Framing::Itm);
+        assert_eq!(detect(&tpiu(&itm)), Framing::Tpiu);
+        assert_eq!(detect(&raw), Framing::Raw);
+
+        let mut esp = b"ESP-ROM:esp32c3\r\n".to_vec();
+        for ts in 0..4 {
+            esp.extend([ESP_START, 0]);
+            esp.extend(frame(&table, "boot", ts));
+            esp.extend(b"\0text\n");
+        }
+        assert_eq!(detect(&esp), Framing::EspPrintln);
+    }
+
+    #[test]
+    fn detection_without_frames() {
+        let table = table("rzcobs");
+        // the first candidate wins a tie
+        assert_eq!(detect(&[], &table, 0, &CANDIDATES), (Framing::Itm, 0));
+        assert_eq!(
+            detect(&[], &table, 0, &[Framing::Raw, Framing::Itm]),
+            (Framing::Raw, 0)
+        );
+        // TPIU with its synchronization packets
+        let mut sample = SYNC.to_be_bytes().to_vec();
+        sample.extend([0x55; 32]);
+        assert_eq!(detect(&sample, &table, 0, &CANDIDATES), (Framing::Tpiu, 0));
+        assert_eq!(
+            detect(&sample, &table, 0, &[Framing::Itm, Framing::Raw]),
+            (Framing::Itm, 0)
+        );
+    }
+}
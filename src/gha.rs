@@ -0,0 +1,57 @@
+//! GitHub Actions workflow commands, see
+//! <https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions>.
+
+use defmt_decoder::Frame;
+use defmt_parser::Level;
+
+use crate::{
+    level_map, sanitize,
+    sinks::{Record, Sink},
+    LocationInfo,
+};
+
+/// `--gha-annotations`, printing the frames shown on the console as annotations.
+pub struct Annotations;
+
+impl Sink for Annotations {
+    fn frame(&mut self, record: &Record) {
+        annotate(record.frame, &record.location);
+    }
+}
+
+/// Prints an `::error` or `::warning` annotation for error and warn frames.
+fn annotate(frame: &Frame, location: &LocationInfo) {
+    let command = match level_map::level(frame, location.2) {
+        Some(Level::Error) => "error",
+        Some(Level::Warn) => "warning",
+        _ => return,
+    };
+
+    let mut properties = Vec::new();
+    if let Some(file) = &location.0 {
+        properties.push(format!("file={}", escape_property(file)));
+    }
+    if let Some(line) = location.1 {
+        properties.push(format!("line={}", line));
+    }
+    if let Some(module) = &location.2 {
+        properties.push(format!("title={}", escape_property(module)));
+    }
+
+    println!(
+        "::{} {}::{}",
+        command,
+        properties.join(","),
+        escape_data(&sanitize::message(frame))
+    );
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
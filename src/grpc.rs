@@ -0,0 +1,284 @@
+//! `--grpc`, streaming the decoded frames to subscribers as in `proto/defmt_listener.proto`.
+//!
+//! The service is written out by hand rather than generated, so building doesn't need `protoc`.
+
+use anyhow::anyhow;
+use chrono::Utc;
+use defmt_decoder::Frame;
+use defmt_parser::{Fragment, ParserMode};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tonic::{
+    body::BoxBody,
+    codec::ProstCodec,
+    codegen::{
+        empty_body, http, tokio_stream::wrappers::ReceiverStream, Body, BoxFuture, Context, Poll,
+        Service, StdError,
+    },
+    server::{Grpc, NamedService, ServerStreamingService},
+    transport::{server::TcpIncoming, Server},
+    Code, Status,
+};
+
+use crate::{
+    elf::DefmtSection,
+    filter::{self, Filter},
+    level_map, sanitize,
+    sinks::{Record, Sink},
+    LocationInfo,
+};
+
+/// Frames queued per subscriber, more are dropped for it rather than holding up decoding
+const QUEUE: usize = 1024;
+
+/// The messages of `proto/defmt_listener.proto`.
+mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {
+        #[prost(string, tag = "1")]
+        pub filter: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Frame {
+        #[prost(string, tag = "1")]
+        pub level: String,
+        #[prost(string, tag = "2")]
+        pub message: String,
+        #[prost(string, repeated, tag = "3")]
+        pub args: Vec<String>,
+        #[prost(message, optional, tag = "4")]
+        pub location: Option<Location>,
+        #[prost(string, tag = "5")]
+        pub device_timestamp: String,
+        #[prost(int64, tag = "6")]
+        pub host_timestamp: i64,
+        #[prost(string, tag = "7")]
+        pub target: String,
+        #[prost(uint64, tag = "8")]
+        pub index: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Location {
+        #[prost(string, tag = "1")]
+        pub file: String,
+        #[prost(uint32, tag = "2")]
+        pub line: u32,
+        #[prost(string, tag = "3")]
+        pub module: String,
+    }
+}
+
+struct Subscriber {
+    filter: Filter,
+    sender: mpsc::Sender<Result<pb::Frame, Status>>,
+}
+
+type Subscribers = Arc<Mutex<Vec<Subscriber>>>;
+
+pub struct GrpcServer {
+    subscribers: Subscribers,
+    /// Regexes taking the arguments out of the messages, by format string
+    args: HashMap<String, Option<Regex>>,
+}
+
+impl GrpcServer {
+    /// Serves the `Frames` service on `address`, from a thread.
+    pub fn spawn(address: SocketAddr) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        // bound here to report the error before the session starts
+        let incoming = runtime
+            .block_on(async { TcpIncoming::new(address, true, None) })
+            .map_err(|err| anyhow!("Failed to listen on --grpc {}: {}", address, err))?;
+
+        let subscribers = Subscribers::default();
+        let service = FramesService {
+            subscribers: subscribers.clone(),
+        };
+        thread::spawn(move || {
+            let server = Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming);
+            if let Err(err) = runtime.block_on(server) {
+                println!("(HOST) WARNING: --grpc server failed: {}", err);
+            }
+        });
+        Ok(GrpcServer {
+            subscribers,
+            args: HashMap::new(),
+        })
+    }
+}
+
+impl Sink for GrpcServer {
+    /// Sends the frame to the subscribers whose filter it matches.
+    fn frame(&mut self, record: &Record) {
+        let (frame, location) = (record.frame, &record.location);
+        let mut subscribers = self.subscribers.lock().expect("not poisoned");
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let mut message = None;
+        subscribers.retain(|subscriber| {
+            if !subscriber.filter.matches_record(record) {
+                return !subscriber.sender.is_closed();
+            }
+            let message = message.get_or_insert_with(|| {
+                to_message(
+                    frame,
+                    location,
+                    record.target,
+                    record.section,
+                    &mut self.args,
+                )
+            });
+            match subscriber.sender.try_send(Ok(message.clone())) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+}
+
+fn to_message(
+    frame: &Frame,
+    location: &LocationInfo,
+    target: &str,
+    section: &DefmtSection,
+    regexes: &mut HashMap<String, Option<Regex>>,
+) -> pb::Frame {
+    let message = sanitize::message(frame);
+
+    let format = section
+        .symbols
+        .binary_search_by_key(&frame.index(), |symbol| symbol.index)
+        .ok()
+        .map(|i| section.symbols[i].data.as_str());
+    let regex = format.and_then(|format| {
+        regexes
+            .entry(format.to_string())
+            .or_insert_with(|| args_regex(format))
+            .as_ref()
+    });
+    let args = regex
+        .and_then(|regex| regex.captures(&message))
+        .map(|captures| {
+            let args = captures.iter().skip(1).flatten();
+            args.map(|arg| arg.as_str().to_string()).collect()
+        })
+        .unwrap_or_default();
+
+    let (file, line, module) = location;
+    let location = (file.is_some() || module.is_some()).then(|| pb::Location {
+        file: file.unwrap_or_default().to_string(),
+        line: line.unwrap_or_default(),
+        module: module.unwrap_or_default().to_string(),
+    });
+
+    pb::Frame {
+        level: level_map::level(frame, *module)
+            .map(|level| level.as_str().to_string())
+            .unwrap_or_default(),
+        message,
+        args,
+        location,
+        device_timestamp: frame
+            .display_timestamp()
+            .map(|timestamp| timestamp.to_string())
+            .unwrap_or_default(),
+        host_timestamp: Utc::now().timestamp_nanos(),
+        target: target.to_string(),
+        index: frame.index(),
+    }
+}
+
+/// Matches a message of `format`, capturing each parameter; `None` if it can't be parsed.
+fn args_regex(format: &str) -> Option<Regex> {
+    let fragments = defmt_parser::parse(format, ParserMode::ForwardsCompatible).ok()?;
+    let mut pattern = String::from("(?s)^");
+    for fragment in fragments {
+        match fragment {
+            Fragment::Literal(literal) => pattern.push_str(&regex::escape(&literal)),
+            Fragment::Parameter(_) => pattern.push_str("(.*?)"),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).ok()
+}
+
+#[derive(Clone)]
+struct FramesService {
+    subscribers: Subscribers,
+}
+
+impl NamedService for FramesService {
+    const NAME: &'static str = "defmt_listener.Frames";
+}
+
+impl<B> Service<http::Request<B>> for FramesService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        match request.uri().path() {
+            "/defmt_listener.Frames/Subscribe" => {
+                let subscribe = Subscribe(self.subscribers.clone());
+                Box::pin(async move {
+                    let mut grpc = Grpc::new(ProstCodec::default());
+                    Ok(grpc.server_streaming(subscribe, request).await)
+                })
+            }
+            _ => Box::pin(async {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert(Status::GRPC_STATUS, (Code::Unimplemented as i32).into());
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    tonic::metadata::GRPC_CONTENT_TYPE,
+                );
+                Ok(response)
+            }),
+        }
+    }
+}
+
+struct Subscribe(Subscribers);
+
+impl ServerStreamingService<pb::SubscribeRequest> for Subscribe {
+    type Response = pb::Frame;
+    type ResponseStream = ReceiverStream<Result<pb::Frame, Status>>;
+    type Future = BoxFuture<tonic::Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: tonic::Request<pb::SubscribeRequest>) -> Self::Future {
+        let subscribers = self.0.clone();
+        Box::pin(async move {
+            let line = request.into_inner().filter;
+            let filter = Filter::parse_line(&line)
+                .map_err(|err| Status::invalid_argument(filter::error_message(&err)))?;
+            let (sender, receiver) = mpsc::channel(QUEUE);
+            let subscriber = Subscriber { filter, sender };
+            subscribers.lock().expect("not poisoned").push(subscriber);
+            Ok(tonic::Response::new(ReceiverStream::new(receiver)))
+        })
+    }
+}
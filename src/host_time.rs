@@ -0,0 +1,32 @@
+use chrono::{Local, Utc};
+use std::{fmt::Write as _, time::Instant};
+
+use crate::Args;
+
+/// Host time printed in front of each frame, picked by `--utc`, `--local` or `--relative`.
+pub enum HostTime {
+    Utc,
+    Local,
+    /// Seconds since the start of the session
+    Relative(Instant),
+}
+
+impl HostTime {
+    pub fn new(args: &Args) -> Option<Self> {
+        match (args.utc, args.local, args.relative) {
+            (true, _, _) => Some(HostTime::Utc),
+            (_, true, _) => Some(HostTime::Local),
+            (_, _, true) => Some(HostTime::Relative(Instant::now())),
+            _ => None,
+        }
+    }
+
+    /// Appends the host time to `out`, RFC 3339 with microseconds or the seconds since the start.
+    pub fn write(&self, out: &mut String) {
+        let _ = match self {
+            HostTime::Utc => write!(out, "{}", Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ")),
+            HostTime::Local => write!(out, "{}", Local::now().format("%Y-%m-%dT%H:%M:%S%.6f%:z")),
+            HostTime::Relative(start) => write!(out, "{:.6}", start.elapsed().as_secs_f64()),
+        };
+    }
+}
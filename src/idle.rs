@@ -0,0 +1,54 @@
+//! `--idle-warning` and `--idle-reconnect`, for a stream gone quiet.
+
+use std::time::{Duration, Instant};
+
+pub struct Idle {
+    warning: Option<Duration>,
+    reconnect: Option<Duration>,
+    /// When the last bytes arrived
+    last: Instant,
+    /// Whether the current silence was warned about
+    warned: bool,
+}
+
+impl Idle {
+    pub fn new(warning: Option<Duration>, reconnect: Option<Duration>) -> Self {
+        Idle {
+            warning,
+            reconnect,
+            last: Instant::now(),
+            warned: false,
+        }
+    }
+
+    /// Records received bytes, returning how long the stream was quiet if that was warned about.
+    pub fn data(&mut self) -> Option<Duration> {
+        let silence = self.last.elapsed();
+        self.last = Instant::now();
+        std::mem::take(&mut self.warned).then_some(silence)
+    }
+
+    /// Returns how long the stream is quiet, once per silence longer than the warning.
+    pub fn check(&mut self) -> Option<Duration> {
+        let silence = self.last.elapsed();
+        if self.warned || self.warning.is_none_or(|warning| silence < warning) {
+            return None;
+        }
+        self.warned = true;
+        Some(silence)
+    }
+
+    /// How long the stream is quiet if it's time to connect again.
+    pub fn reconnect(&self) -> Option<Duration> {
+        let silence = self.last.elapsed();
+        self.reconnect
+            .filter(|&reconnect| silence >= reconnect)
+            .map(|_| silence)
+    }
+
+    /// Starts over on a new connection.
+    pub fn reset(&mut self) {
+        self.last = Instant::now();
+        self.warned = false;
+    }
+}
@@ -0,0 +1,115 @@
+use defmt_decoder::Locations;
+use std::{collections::BTreeMap, env, path::PathBuf};
+
+use crate::{
+    elf::{self, DefmtSection},
+    fetch,
+};
+
+/// Number of formats listed by `--stats`
+const LARGEST_FORMATS: usize = 10;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct InspectArgs {
+    /// Path or `http(s)://` URL of the ELF file
+    #[arg(long)]
+    elf: PathBuf,
+    /// Print log site statistics instead of the table entries
+    #[arg(long)]
+    stats: bool,
+}
+
+pub fn run(args: InspectArgs) -> anyhow::Result<()> {
+    let bytes = fetch::read_elf(&args.elf)?;
+    let table = elf::parse_table(&bytes)?;
+    let locs = table.get_locations(&bytes)?;
+    let section = DefmtSection::parse(&bytes)?;
+
+    let log_sites = section.symbols.iter().filter(|s| s.is_log_site()).count();
+    let string_bytes: usize = section.symbols.iter().map(|s| s.data.len()).sum();
+
+    println!("encoding: {:?}", table.encoding());
+    println!(
+        "table: {} entries ({} log sites), {} bytes of strings, .defmt section of {} bytes",
+        section.symbols.len(),
+        log_sites,
+        string_bytes,
+        section.size
+    );
+    println!();
+
+    match args.stats {
+        false => print_entries(&section, &locs),
+        true => print_stats(&section, &locs),
+    }
+}
+
+fn print_entries(section: &DefmtSection, locs: &Locations) -> anyhow::Result<()> {
+    let current_dir = env::current_dir()?;
+
+    for symbol in &section.symbols {
+        println!(
+            "{:#06x} {:<9} {}",
+            symbol.index,
+            symbol.kind().to_uppercase(),
+            symbol.data
+        );
+
+        match locs.get(&symbol.index) {
+            Some(loc) => println!("└─ {}", elf::display_location(loc, &current_dir)),
+            None if symbol.is_log_site() => println!("└─ <location unknown>"),
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn print_stats(section: &DefmtSection, locs: &Locations) -> anyhow::Result<()> {
+    let log_sites = || section.symbols.iter().filter(|s| s.is_log_site());
+
+    let mut per_level = BTreeMap::<_, usize>::new();
+    let mut per_module = BTreeMap::<_, (usize, usize)>::new();
+    for symbol in log_sites() {
+        *per_level.entry(symbol.kind()).or_default() += 1;
+
+        let module = locs
+            .get(&symbol.index)
+            .map_or("<unknown>", |loc| loc.module.as_str());
+        let (sites, bytes) = per_module.entry(module).or_default();
+        *sites += 1;
+        *bytes += symbol.data.len();
+    }
+
+    println!("log sites per level:");
+    for level in ["trace", "debug", "info", "warn", "error", "println"] {
+        println!(
+            "  {:<9} {}",
+            level.to_uppercase(),
+            per_level.get(level).copied().unwrap_or(0)
+        );
+    }
+    println!();
+
+    println!("log sites per module (sites, string bytes):");
+    for (module, (sites, bytes)) in &per_module {
+        println!("  {:>5} {:>7}  {}", sites, bytes, module);
+    }
+    println!();
+
+    let mut largest = section.symbols.iter().collect::<Vec<_>>();
+    largest.sort_by_key(|symbol| std::cmp::Reverse(symbol.data.len()));
+
+    println!("largest formats (bytes):");
+    for symbol in largest.into_iter().take(LARGEST_FORMATS) {
+        println!(
+            "  {:>5}  {:#06x} {:<9} {}",
+            symbol.data.len(),
+            symbol.index,
+            symbol.kind().to_uppercase(),
+            symbol.data
+        );
+    }
+
+    Ok(())
+}
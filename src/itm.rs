@@ -0,0 +1,301 @@
+use crate::Error;
+
+const MAX_ITM_PAYLOAD: usize = 4;
+/// Sent by the target when ITM packets were dropped
+pub const ITM_OVERFLOW: u8 = 0x70;
+/// Payload size of the instrumentation packets by header, 0 for the other bytes
+const PAYLOAD_SIZES: [u8; 256] = payload_sizes();
+
+const fn payload_sizes() -> [u8; 256] {
+    let mut sizes = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        sizes[byte] = match byte & 0b111 {
+            0b001 => 1,
+            0b010 => 2,
+            0b011 => 4,
+            _ => 0,
+        };
+        byte += 1;
+    }
+    sizes
+}
+
+#[derive(Debug)]
+struct ItmHeader {
+    pub byte: u8,
+    pub port: u8,
+    pub payload_size: usize,
+}
+
+/// A packet completed by the last received byte.
+#[derive(Clone, Copy)]
+pub enum Completed {
+    /// Instrumentation packet of a stimulus port, its payload is `ItmPacket::payload`
+    Stimulus {
+        header: u8,
+        port: u8,
+    },
+    Overflow,
+    Invalid(u8),
+}
+
+/// An instrumentation packet completed by `ItmPacket::packets`.
+#[derive(Clone, Copy)]
+pub struct Packet {
+    pub port: u8,
+    /// Index of its last byte in the received bytes
+    pub end: usize,
+    payload: [u8; MAX_ITM_PAYLOAD],
+    payload_size: u8,
+}
+
+impl Packet {
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_size as usize]
+    }
+}
+
+pub struct ItmPacket {
+    header: Option<ItmHeader>,
+    completed: Option<Completed>,
+    payload: [u8; MAX_ITM_PAYLOAD],
+    payload_size: usize,
+    /// Overflow packets received
+    pub overflows: usize,
+    /// Bytes skipped because they were no valid ITM header
+    pub invalid_headers: usize,
+    /// Skip invalid headers without a message
+    pub quiet: bool,
+}
+
+impl ItmHeader {
+    fn from_byte(byte: u8) -> crate::Result<Self> {
+        match byte & 0b111 {
+            0b001..=0b011 => Ok(ItmHeader {
+                byte,
+                port: byte >> 3,
+                payload_size: match byte & 0b11 {
+                    0b01 => 1,
+                    0b10 => 2,
+                    0b11 => 4,
+                    _ => unreachable!(),
+                },
+            }),
+            _ => Err(Error::Framing(format!("Unknown ITM header {}", byte))),
+        }
+    }
+}
+
+impl Default for ItmPacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ItmPacket {
+    pub fn new() -> Self {
+        ItmPacket {
+            header: None,
+            completed: None,
+            payload: [0; MAX_ITM_PAYLOAD],
+            payload_size: 0,
+            overflows: 0,
+            invalid_headers: 0,
+            quiet: false,
+        }
+    }
+
+    /// The instrumentation packets completed by `bytes`, of all stimulus ports. Packets inside
+    /// `bytes` are taken at once, only those straddling two reads go through `receive_any`.
+    pub fn packets<'a>(&'a mut self, bytes: &'a [u8]) -> Packets<'a> {
+        Packets {
+            itm_packet: self,
+            bytes,
+            pos: 0,
+        }
+    }
+
+    pub fn receive(&mut self, port: u8, byte: u8) -> Option<&[u8]> {
+        match self.receive_any(byte) {
+            Some(p) if p == port => Some(self.payload()),
+            _ => None,
+        }
+    }
+
+    /// Like `receive` for the packets of all stimulus ports, returning the port of a completed one.
+    pub fn receive_any(&mut self, byte: u8) -> Option<u8> {
+        self.completed = None;
+
+        match &self.header {
+            Some(header) => {
+                self.payload[self.payload_size] = byte;
+                self.payload_size += 1;
+
+                if self.payload_size == header.payload_size {
+                    let port = header.port;
+                    self.completed = Some(Completed::Stimulus {
+                        header: header.byte,
+                        port,
+                    });
+                    self.header = None;
+                    return Some(port);
+                }
+            }
+            None if byte == ITM_OVERFLOW => {
+                self.overflows += 1;
+                self.completed = Some(Completed::Overflow);
+            }
+            None => match ItmHeader::from_byte(byte) {
+                Ok(header) => {
+                    self.header = Some(header);
+                    self.payload_size = 0;
+                }
+                Err(err) => {
+                    self.invalid_headers += 1;
+                    self.completed = Some(Completed::Invalid(byte));
+                    if !self.quiet {
+                        println!("Failed to parse ITM header: {}", err);
+                    }
+                }
+            },
+        };
+
+        None
+    }
+
+    /// Whether no packet is incomplete.
+    pub fn idle(&self) -> bool {
+        self.header.is_none()
+    }
+
+    /// The packet completed by the last received byte, if any.
+    pub fn completed(&self) -> Option<Completed> {
+        self.completed
+    }
+
+    /// Payload of the last completed stimulus packet.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.payload_size]
+    }
+}
+
+pub struct Packets<'a> {
+    itm_packet: &'a mut ItmPacket,
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Iterator for Packets<'_> {
+    type Item = Packet;
+
+    fn next(&mut self) -> Option<Packet> {
+        while self.pos < self.bytes.len() {
+            let byte = self.bytes[self.pos];
+            let size = PAYLOAD_SIZES[byte as usize] as usize;
+            // the whole packet is in `bytes`, unless one is still pending from the last read
+            if self.itm_packet.header.is_none() && size > 0 && self.pos + size < self.bytes.len() {
+                let mut payload = [0; MAX_ITM_PAYLOAD];
+                payload[..size].copy_from_slice(&self.bytes[self.pos + 1..=self.pos + size]);
+                let packet = Packet {
+                    port: byte >> 3,
+                    end: self.pos + size,
+                    payload,
+                    payload_size: size as u8,
+                };
+                self.pos += size + 1;
+                return Some(packet);
+            }
+
+            self.pos += 1;
+            if let Some(port) = self.itm_packet.receive_any(byte) {
+                let mut payload = [0; MAX_ITM_PAYLOAD];
+                let completed = self.itm_packet.payload();
+                payload[..completed.len()].copy_from_slice(completed);
+                return Some(Packet {
+                    port,
+                    end: self.pos - 1,
+                    payload,
+                    payload_size: completed.len() as u8,
+                });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A packet as its port, payload and the index of its last byte
+    type Received = (u8, Vec<u8>, usize);
+
+    /// Bytes of packets of all sizes and ports, overflows and invalid headers.
+    fn stream() -> Vec<u8> {
+        // a linear congruential generator, for the same bytes every run
+        let mut state = 0x2545_f491u32;
+        (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    /// The packets completed a byte at a time, and the overflows and invalid headers.
+    fn byte_by_byte(bytes: &[u8]) -> (Vec<Received>, usize, usize) {
+        let mut itm_packet = ItmPacket::new();
+        itm_packet.quiet = true;
+        let mut packets = Vec::new();
+        for (i, &byte) in bytes.iter().enumerate() {
+            if let Some(port) = itm_packet.receive_any(byte) {
+                packets.push((port, itm_packet.payload().to_vec(), i));
+            }
+        }
+        (packets, itm_packet.overflows, itm_packet.invalid_headers)
+    }
+
+    #[test]
+    fn packets_match_the_bytes_received_one_at_a_time() {
+        let bytes = stream();
+        let expected = byte_by_byte(&bytes);
+        // reads of all sizes, so packets straddle them
+        for read in [1, 2, 3, 5, 7, 64, bytes.len()] {
+            let mut itm_packet = ItmPacket::new();
+            itm_packet.quiet = true;
+            let mut packets = Vec::new();
+            for (n, chunk) in bytes.chunks(read).enumerate() {
+                for packet in itm_packet.packets(chunk) {
+                    packets.push((
+                        packet.port,
+                        packet.payload().to_vec(),
+                        n * read + packet.end,
+                    ));
+                }
+            }
+            let actual = (packets, itm_packet.overflows, itm_packet.invalid_headers);
+            assert_eq!(actual, expected, "reads of {} bytes", read);
+        }
+    }
+
+    #[test]
+    fn packets_of_each_size() {
+        // 1, 2 and 4 payload bytes on ports 0, 1 and 31, an overflow in between
+        let bytes = [0x01, 0xaa, 0x0a, 0xbb, 0xcc, ITM_OVERFLOW, 0xfb, 1, 2, 3, 4];
+        let mut itm_packet = ItmPacket::new();
+        let packets = itm_packet
+            .packets(&bytes)
+            .map(|packet| (packet.port, packet.payload().to_vec(), packet.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packets,
+            [
+                (0, vec![0xaa], 1),
+                (1, vec![0xbb, 0xcc], 4),
+                (31, vec![1, 2, 3, 4], 10)
+            ]
+        );
+        assert_eq!(itm_packet.overflows, 1);
+    }
+}
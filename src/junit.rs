@@ -0,0 +1,167 @@
+use regex::Regex;
+use std::{fmt::Write as _, fs, path::Path, time::Instant};
+
+/// Message of defmt-test starting a test, e.g. "(1/3) running `it_works`..."
+const RUNNING: &str = r"^\(\d+/\d+\) running `(.+)`\.\.\.$";
+/// Message of defmt-test for an `#[ignore]`d test
+const IGNORING: &str = r"^\(\d+/\d+\) ignoring `(.+)`$";
+/// Message of defmt-test after all tests passed
+const ALL_PASSED: &str = "all tests passed!";
+
+struct Case {
+    name: String,
+    start: Instant,
+    /// Duration of the test once it ended
+    secs: Option<f64>,
+    /// Output logged while the test ran
+    output: String,
+    failure: Option<String>,
+    skipped: bool,
+}
+
+/// Test results collected from the output of defmt-test, for `--junit`.
+pub struct TestReport {
+    running: Regex,
+    ignoring: Regex,
+    cases: Vec<Case>,
+    finished: bool,
+}
+
+impl TestReport {
+    pub fn new() -> Self {
+        TestReport {
+            running: Regex::new(RUNNING).expect("valid regex"),
+            ignoring: Regex::new(IGNORING).expect("valid regex"),
+            cases: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Whether the test run is over, because all tests passed or one panicked.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn message(&mut self, level: Option<&str>, message: &str) {
+        if self.finished {
+            return;
+        }
+
+        if let Some(name) = self.running.captures(message).map(|c| c[1].to_string()) {
+            return self.start_case(name, false);
+        }
+        if let Some(name) = self.ignoring.captures(message).map(|c| c[1].to_string()) {
+            return self.start_case(name, true);
+        }
+        if message == ALL_PASSED {
+            self.end_case();
+            self.finished = true;
+            return;
+        }
+
+        let case = match self.cases.last_mut() {
+            Some(case) => case,
+            None => return,
+        };
+        let _ = writeln!(case.output, "{} {}", level.unwrap_or("PRINTLN"), message);
+        // panic-probe reports the panic at error level
+        if level == Some("ERROR") && message.contains("panicked") {
+            case.failure = Some(message.to_string());
+            self.end_case();
+            self.finished = true;
+        }
+    }
+
+    fn start_case(&mut self, name: String, skipped: bool) {
+        self.end_case();
+        self.cases.push(Case {
+            name,
+            start: Instant::now(),
+            secs: None,
+            output: String::new(),
+            failure: None,
+            skipped,
+        });
+        if skipped {
+            self.end_case();
+        }
+    }
+
+    fn end_case(&mut self) {
+        if let Some(case) = self.cases.last_mut() {
+            case.secs
+                .get_or_insert_with(|| case.start.elapsed().as_secs_f64());
+        }
+    }
+
+    /// Number of failed tests, including a test that never finished.
+    pub fn failures(&self) -> usize {
+        self.cases
+            .iter()
+            .enumerate()
+            .filter(|(n, case)| case.failure.is_some() || self.unfinished(*n))
+            .count()
+    }
+
+    pub fn tests(&self) -> usize {
+        self.cases.len()
+    }
+
+    /// Whether the test at `n` was still running at the end of the session.
+    fn unfinished(&self, n: usize) -> bool {
+        !self.finished && n + 1 == self.cases.len() && !self.cases[n].skipped
+    }
+
+    pub fn write(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.end_case();
+
+        let skipped = self.cases.iter().filter(|case| case.skipped).count();
+        let secs: f64 = self.cases.iter().filter_map(|case| case.secs).sum();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        writeln!(
+            xml,
+            "<testsuites>\n  <testsuite name=\"defmt-test\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+            self.tests(),
+            self.failures(),
+            skipped,
+            secs
+        )?;
+        for (n, case) in self.cases.iter().enumerate() {
+            writeln!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"defmt-test\" time=\"{:.3}\">",
+                escape(&case.name),
+                case.secs.unwrap_or_default()
+            )?;
+            if case.skipped {
+                writeln!(xml, "      <skipped/>")?;
+            } else if let Some(failure) = &case.failure {
+                writeln!(xml, "      <failure message=\"{}\"/>", escape(failure))?;
+            } else if self.unfinished(n) {
+                writeln!(
+                    xml,
+                    "      <failure message=\"the session ended while the test was running\"/>"
+                )?;
+            }
+            if !case.output.is_empty() {
+                writeln!(
+                    xml,
+                    "      <system-out>{}</system-out>",
+                    escape(&case.output)
+                )?;
+            }
+            writeln!(xml, "    </testcase>")?;
+        }
+        writeln!(xml, "  </testsuite>\n</testsuites>")?;
+
+        fs::write(path, xml)?;
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::{self, Read},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+/// Key presses read from the terminal in interactive mode.
+///
+/// The terminal is switched to unbuffered input without echo for as long as this exists.
+pub struct Keys {
+    rx: Receiver<char>,
+}
+
+impl Keys {
+    pub fn new() -> anyhow::Result<Self> {
+        terminal::enable()?;
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                match byte {
+                    Ok(byte) if tx.send(byte as char).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+
+        Ok(Keys { rx })
+    }
+
+    pub fn poll(&self) -> Option<char> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for Keys {
+    fn drop(&mut self) {
+        terminal::restore();
+    }
+}
+
+/// Puts the terminal back the way it was before interactive mode; safe to call from a signal handler.
+pub fn restore_terminal() {
+    terminal::restore();
+}
+
+#[cfg(unix)]
+mod terminal {
+    use anyhow::anyhow;
+    use std::{mem, sync::OnceLock};
+
+    static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+    pub fn enable() -> anyhow::Result<()> {
+        // SAFETY: plain libc calls on stdin with a properly initialized `termios`
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return Err(anyhow!("Interactive mode requires stdin to be a terminal"));
+            }
+
+            let mut termios = mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            ORIGINAL.get_or_init(|| termios);
+
+            // keep `ISIG`, so Ctrl-C still terminates the process
+            termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+            termios.c_cc[libc::VMIN] = 1;
+            termios.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+
+            // restore the terminal when terminated by a signal as well
+            for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+                libc::signal(
+                    signal,
+                    restore_and_reraise as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn restore() {
+        if let Some(original) = ORIGINAL.get() {
+            // SAFETY: `original` was filled by `tcgetattr`
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+
+    extern "C" fn restore_and_reraise(signal: libc::c_int) {
+        // NOTE `tcsetattr`, `signal` and `raise` are async-signal-safe
+        restore();
+        // SAFETY: resets the default disposition and terminates with the same signal
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    use anyhow::anyhow;
+
+    pub fn enable() -> anyhow::Result<()> {
+        Err(anyhow!("Interactive mode is only supported on Unix"))
+    }
+
+    pub fn restore() {}
+}
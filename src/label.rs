@@ -0,0 +1,99 @@
+//! `--name`, the label of the listener in its outputs, and the labels telling apart the lines of the
+//! `--source`s or of the ports of `--port-elf`, each in a color of its own.
+
+use colored::{Color, Colorize};
+
+/// By the order of the sources or ports, unless given with `--name`
+const COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Blue,
+    Color::Green,
+    Color::BrightCyan,
+    Color::BrightMagenta,
+];
+
+/// A `--name`
+#[derive(Debug, Clone)]
+pub enum Name {
+    /// Of the listener, e.g. the board it listens to
+    Label(String),
+    /// Of the label of a source or port
+    Color(String, Color),
+}
+
+/// Parses a label, or `<name>=<color>`, e.g. `net=bright-blue`.
+pub fn parse_name(text: &str) -> Result<Name, String> {
+    let Some((name, color)) = text.split_once('=') else {
+        return match text.is_empty() {
+            true => Err("the label is empty".to_string()),
+            false => Ok(Name::Label(text.to_string())),
+        };
+    };
+    let color = color.replace(['-', '_'], " ").parse().map_err(|()| {
+        format!(
+            "unknown color `{}`, expected e.g. `red`, `blue`, `bright-blue` or `white`",
+            color
+        )
+    })?;
+    Ok(Name::Color(name.to_string(), color))
+}
+
+/// The label of the listener, the last one given.
+pub fn label(names: &[Name]) -> Option<&str> {
+    names.iter().rev().find_map(|name| match name {
+        Name::Label(label) => Some(label.as_str()),
+        Name::Color(..) => None,
+    })
+}
+
+/// The colors given for the labels.
+pub fn colors(names: &[Name]) -> Vec<(String, Color)> {
+    names
+        .iter()
+        .filter_map(|name| match name {
+            Name::Color(name, color) => Some((name.clone(), *color)),
+            Name::Label(_) => None,
+        })
+        .collect()
+}
+
+/// The first `--name` of a name none of `names` has.
+pub fn unknown<'n>(colors: &'n [(String, Color)], names: &[&str]) -> Option<&'n str> {
+    colors
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .find(|name| !names.contains(name))
+}
+
+/// The labels of the sources or ports, padded to the same width and colored.
+#[derive(Debug)]
+pub struct Labels {
+    labels: Vec<String>,
+}
+
+impl Labels {
+    pub fn new(names: &[&str], colors: &[(String, Color)]) -> Self {
+        let width = names.iter().map(|name| name.chars().count()).max();
+        let labels = names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| {
+                let color = colors
+                    .iter()
+                    .rev()
+                    .find(|(n, _)| n == name)
+                    .map_or(COLORS[i % COLORS.len()], |&(_, color)| color);
+                format!("{:<width$}", name, width = width.unwrap_or(0))
+                    .color(color)
+                    .to_string()
+            })
+            .collect();
+        Labels { labels }
+    }
+
+    /// The label of the `i`th source or port.
+    pub fn get(&self, i: usize) -> &str {
+        &self.labels[i]
+    }
+}
@@ -0,0 +1,89 @@
+//! `--level-style`, how the console prints the level of a frame.
+
+use colored::{Color, Colorize};
+use defmt_decoder::Frame;
+use defmt_parser::Level;
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{level_map, LocationInfo};
+
+/// Widest timestamp printed so far, to align the levels like the logger of defmt-decoder
+static TIMESTAMP_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelStyle {
+    /// `ERROR`, `WARN`, `INFO`, `DEBUG` and `TRACE`
+    Word,
+    /// `E`, `W`, `I`, `D` and `T`
+    Letter,
+    /// A colored symbol
+    Icon,
+}
+
+impl LevelStyle {
+    /// Columns taken by the level.
+    pub fn width(self) -> usize {
+        match self {
+            LevelStyle::Word => "ERROR".len(),
+            LevelStyle::Letter | LevelStyle::Icon => 1,
+        }
+    }
+
+    fn render(self, level: Level) -> String {
+        let text = match self {
+            LevelStyle::Word => format!("{:<5}", level.as_str().to_uppercase()),
+            LevelStyle::Letter => level.as_str()[..1].to_uppercase(),
+            LevelStyle::Icon => match level {
+                Level::Error => "✖",
+                Level::Warn => "▲",
+                Level::Info => "●",
+                Level::Debug => "○",
+                Level::Trace => "·",
+            }
+            .to_string(),
+        };
+        text.color(color(level)).to_string()
+    }
+}
+
+/// The colors of the logger of defmt-decoder.
+fn color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::Red,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::Green,
+        Level::Debug => Color::BrightWhite,
+        Level::Trace => Color::BrightBlack,
+    }
+}
+
+/// Prints `frame` with `message` like the logger of defmt-decoder, with the level in `style`.
+pub fn print(frame: &Frame, message: &str, location: &LocationInfo, style: LevelStyle) {
+    let timestamp = frame
+        .display_timestamp()
+        .map(|timestamp| timestamp.to_string())
+        .unwrap_or_default();
+    let width = TIMESTAMP_WIDTH.fetch_max(timestamp.len(), Ordering::Relaxed);
+    let mut line = match timestamp.is_empty() {
+        true => String::new(),
+        false => format!("{:>1$} ", timestamp, width.max(timestamp.len())),
+    };
+    if let Some(level) = level_map::level(frame, location.2) {
+        line += &format!("{} ", style.render(level));
+    }
+    line += message;
+
+    let mut out = io::stdout().lock();
+    // NOTE a closed stdout is noticed by the next println
+    let _ = writeln!(out, "{}", line);
+    if let (Some(file), Some(line), Some(module)) = location {
+        let _ = writeln!(
+            out,
+            "{}",
+            format!("└─ {} @ {}:{}", module, file, line).dimmed()
+        );
+    }
+}
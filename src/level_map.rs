@@ -0,0 +1,115 @@
+//! `--map-level`, the levels the host logs the frames at, e.g. the `trace` frames as `debug` or the
+//! warnings of a module as errors. The console, its filter and the sinks see the mapped levels, the
+//! summary counts the levels sent by the device.
+
+use defmt_decoder::Frame;
+use defmt_parser::Level;
+use std::sync::OnceLock;
+
+/// The rules of the session, set once at its start
+static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+
+/// Maps the `from` frames of the modules starting with `module`, of every module if `None`, to `to`.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    module: Option<String>,
+    from: Level,
+    to: Level,
+}
+
+/// Parses `[<module>:]<from>=<to>`, e.g. `trace=debug` or `app::net:warn=error`.
+pub fn parse_rule(text: &str) -> Result<Rule, String> {
+    let (module, levels) = match text.rsplit_once(':') {
+        Some((module, levels)) if !module.is_empty() && !module.ends_with(':') => {
+            (Some(module.to_string()), levels)
+        }
+        _ => (None, text),
+    };
+    let (from, to) = levels.split_once('=').ok_or_else(|| {
+        format!(
+            "invalid rule `{}`, expected `<from>=<to>` or `<module>:<from>=<to>`, e.g. `trace=debug`",
+            text
+        )
+    })?;
+    Ok(Rule {
+        module,
+        from: parse_level(from)?,
+        to: parse_level(to)?,
+    })
+}
+
+fn parse_level(level: &str) -> Result<Level, String> {
+    match level.to_ascii_lowercase().as_str() {
+        "trace" => Ok(Level::Trace),
+        "debug" => Ok(Level::Debug),
+        "info" => Ok(Level::Info),
+        "warn" => Ok(Level::Warn),
+        "error" => Ok(Level::Error),
+        _ => Err(format!(
+            "invalid level `{}`, expected `trace`, `debug`, `info`, `warn` or `error`",
+            level
+        )),
+    }
+}
+
+pub fn configure(rules: Vec<Rule>) {
+    let _ = RULES.set(rules);
+}
+
+/// Whether a frame can be logged at another level than its own.
+pub fn active() -> bool {
+    RULES.get().is_some_and(|rules| !rules.is_empty())
+}
+
+/// The level `frame` of `module` is logged at, by the last rule matching it, `None` for `println`.
+pub fn level(frame: &Frame, module: Option<&str>) -> Option<Level> {
+    let level = frame.level()?;
+    let rules = RULES.get().map_or(&[][..], |rules| rules.as_slice());
+    let rule = rules.iter().rev().find(|rule| {
+        rule.from == level
+            && match &rule.module {
+                Some(prefix) => module.is_some_and(|module| module.starts_with(prefix.as_str())),
+                None => true,
+            }
+    });
+    Some(rule.map_or(level, |rule| rule.to))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(text: &str) -> (Option<String>, Level, Level) {
+        let rule = parse_rule(text).unwrap();
+        (rule.module, rule.from, rule.to)
+    }
+
+    #[test]
+    fn rules_of_every_module() {
+        assert_eq!(rule("trace=debug"), (None, Level::Trace, Level::Debug));
+        assert_eq!(rule("WARN=Error"), (None, Level::Warn, Level::Error));
+    }
+
+    #[test]
+    fn rules_of_a_module() {
+        let module = Some("app::net".to_string());
+        assert_eq!(
+            rule("app::net:warn=error"),
+            (module, Level::Warn, Level::Error)
+        );
+        assert_eq!(
+            rule("app:info=warn"),
+            (Some("app".into()), Level::Info, Level::Warn)
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        for text in ["trace", "trace=", "=debug", "trace=fatal", ":trace=debug"] {
+            assert!(parse_rule(text).is_err(), "{}", text);
+        }
+        // the module ends at a single `:`, not at the `::` of its path
+        assert!(parse_rule("app::net::warn=error").is_err());
+        assert!(parse_rule("app:trace").is_err());
+    }
+}
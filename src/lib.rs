@@ -0,0 +1,16 @@
+//! The core of defmt-listener: the ITM and its framings, the defmt frames, the ELF files, the
+//! captures and the transports, for applications embedding the decoder.
+
+pub mod capture;
+mod compress;
+pub mod elf;
+pub mod error;
+pub mod fetch;
+pub mod frames;
+pub mod framing;
+pub mod itm;
+pub mod listen;
+mod socket;
+pub mod source;
+
+pub use error::{Error, Result};
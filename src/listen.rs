@@ -0,0 +1,213 @@
+//! The listener in library form: the frames read from an `Endpoint` passed to `Hooks`, e.g. for
+//! host-side tests to assert on what the firmware logs instead of scraping stdout.
+
+use defmt_decoder::{DecodeError, Encoding, Frame, Table};
+use std::{
+    io::{self, ErrorKind, Read},
+    ops::ControlFlow,
+    time::Duration,
+};
+
+use crate::{
+    frames::FrameDecoder,
+    framing::{Esp, EspOutput, Framing, Tpiu},
+    itm::ItmPacket,
+    source::Endpoint,
+    Error,
+};
+
+/// How long to wait for a TCP connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a read waits for data before it's tried again
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Bytes buffered without a complete frame before they're dropped, as by `--max-frame-bytes`
+const MAX_FRAME_BYTES: usize = 64 << 10;
+
+/// Called by `listen` as connections and frames come and go, each does nothing by default. A hook
+/// returning `ControlFlow::Break` stops listening. The frames borrow the table, `'t`.
+pub trait Hooks<'t> {
+    fn on_connect(&mut self, _endpoint: &Endpoint) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// The bytes of a read, before they're unwrapped from the framing.
+    fn on_received(&mut self, _bytes: &[u8]) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// A frame of the defmt stimulus port and its encoded size.
+    fn on_frame(&mut self, _frame: Frame<'t>, _size: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// The encoded bytes of a frame that didn't decode, as far as they're known, and whether they
+    /// were dropped for not ending within the bytes buffered at most.
+    fn on_malformed(&mut self, _bytes: &[u8], _overflowed: bool) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// A line of text in between the frames of `Framing::EspPrintln`, without its line ending.
+    fn on_text(&mut self, _line: &[u8]) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// A read timed out without data, e.g. to stop listening to a quiet source.
+    fn on_idle(&mut self) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// The connection ended, with the error that ended it if any.
+    fn on_disconnect(&mut self, _err: Option<&io::Error>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Reads the frames of `table` on stimulus `port` from `endpoint`, wrapped in `framing`, until a
+/// hook stops or a file or stdin ends. Other endpoints are connected again once they end.
+///
+/// Connecting fails with `Error::Transport`, the policy for retrying is the caller's, e.g. with a
+/// `source::Backoff`. `rtt://` and `Framing::Auto` are only supported by the listener itself.
+pub fn listen<'t>(
+    endpoint: &Endpoint,
+    table: &'t Table,
+    port: u8,
+    framing: Framing,
+    hooks: &mut impl Hooks<'t>,
+) -> crate::Result<()> {
+    listen_bounded(endpoint, table, port, framing, MAX_FRAME_BYTES, hooks)
+}
+
+/// Like `listen`, dropping the bytes buffered without a complete frame past `max_frame_bytes`
+/// instead of 64 KiB.
+pub fn listen_bounded<'t>(
+    endpoint: &Endpoint,
+    table: &'t Table,
+    port: u8,
+    framing: Framing,
+    max_frame_bytes: usize,
+    hooks: &mut impl Hooks<'t>,
+) -> crate::Result<()> {
+    if framing == Framing::Auto {
+        return Err(Error::Framing(
+            "`Framing::Auto` can't be listened to, pick one with `framing::detect`".to_string(),
+        ));
+    }
+
+    loop {
+        let mut source = endpoint.connect(CONNECT_TIMEOUT, READ_TIMEOUT)?;
+        if hooks.on_connect(endpoint).is_break() {
+            return Ok(());
+        }
+
+        let mut connection = Connection::new(table, port, framing, max_frame_bytes);
+        let mut buf = [0; 4096];
+        let ended = loop {
+            let n = match source.read(&mut buf) {
+                Ok(0) => break None,
+                Ok(n) => n,
+                Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                    match hooks.on_idle() {
+                        ControlFlow::Continue(()) => continue,
+                        ControlFlow::Break(()) => return Ok(()),
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => break Some(err),
+            };
+            if hooks.on_received(&buf[..n]).is_break()
+                || connection.received(&buf[..n], hooks).is_break()
+            {
+                return Ok(());
+            }
+        };
+
+        if hooks.on_disconnect(ended.as_ref()).is_break() || !endpoint.reconnects() {
+            return Ok(());
+        }
+    }
+}
+
+/// The decoding state of a connection.
+struct Connection<'t> {
+    port: u8,
+    framing: Framing,
+    /// Whether the frames are raw, whose malformed bytes are skipped by `resync`
+    raw: bool,
+    itm_packet: ItmPacket,
+    tpiu: Tpiu,
+    esp: Esp,
+    decoder: FrameDecoder<'t>,
+    /// The defmt bytes of the last read
+    data: Vec<u8>,
+}
+
+impl<'t> Connection<'t> {
+    fn new(table: &'t Table, port: u8, framing: Framing, max_frame_bytes: usize) -> Self {
+        let mut itm_packet = ItmPacket::new();
+        itm_packet.quiet = true;
+        let mut decoder = FrameDecoder::new(table);
+        decoder.max_bytes = Some(max_frame_bytes);
+        Connection {
+            port,
+            framing,
+            raw: matches!(table.encoding(), Encoding::Raw),
+            itm_packet,
+            tpiu: Tpiu::new(),
+            esp: Esp::new(),
+            decoder,
+            data: Vec::new(),
+        }
+    }
+
+    fn received(&mut self, bytes: &[u8], hooks: &mut impl Hooks<'t>) -> ControlFlow<()> {
+        self.data.clear();
+        match self.framing {
+            Framing::Itm => self.demux(bytes),
+            Framing::Tpiu => {
+                let mut itm = Vec::new();
+                for &byte in bytes {
+                    itm.extend_from_slice(self.tpiu.receive(byte));
+                }
+                self.demux(&itm);
+            }
+            Framing::EspPrintln => {
+                for &byte in bytes {
+                    match self.esp.receive(byte) {
+                        Some(EspOutput::Defmt(byte)) => self.data.push(byte),
+                        Some(EspOutput::Line(line)) => hooks.on_text(&line)?,
+                        None => {}
+                    }
+                }
+            }
+            Framing::Raw | Framing::Auto => self.data.extend_from_slice(bytes),
+        }
+        self.decoder.received(&self.data);
+
+        loop {
+            match self.decoder.decode() {
+                Ok((frame, size)) => hooks.on_frame(frame, size)?,
+                Err(DecodeError::UnexpectedEof) => return ControlFlow::Continue(()),
+                Err(DecodeError::Malformed) => {
+                    // malformed rzCOBS frames are skipped by `decode`
+                    let overflowed = self.decoder.overflowed();
+                    let skipped = match self.raw && !overflowed {
+                        true => self.decoder.resync(),
+                        false => self.decoder.last_frame().len(),
+                    };
+                    hooks.on_malformed(self.decoder.last_frame(), overflowed)?;
+                    if skipped == 0 {
+                        return ControlFlow::Continue(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn demux(&mut self, bytes: &[u8]) {
+        for packet in self.itm_packet.packets(bytes) {
+            if packet.port == self.port {
+                self.data.extend_from_slice(packet.payload());
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{capture, itm::ItmPacket};
+
+/// `--max-malformed`: at most `count` malformed frames, in any `window` if given.
+#[derive(Clone, Copy, Debug)]
+pub struct MalformedLimit {
+    count: usize,
+    window: Option<Duration>,
+}
+
+pub fn parse_malformed_limit(text: &str) -> Result<MalformedLimit, String> {
+    let (count, window) = match text.split_once('/') {
+        Some((count, window)) => (count, Some(capture::parse_duration(window)?)),
+        None => (text, None),
+    };
+    let count = count
+        .parse()
+        .map_err(|_| format!("invalid count `{}`, expected e.g. `10` or `10/1m`", count))?;
+    Ok(MalformedLimit { count, window })
+}
+
+impl fmt::Display for MalformedLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.window {
+            Some(window) => write!(f, "{} in {}s", self.count, window.as_secs_f64()),
+            None => write!(f, "{}", self.count),
+        }
+    }
+}
+
+/// What to do once `--max-malformed` is exceeded.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MalformedAction {
+    /// End the session with an error
+    Exit,
+    /// Drop the connection and connect again, reloading the ELF
+    Reconnect,
+}
+
+/// Malformed frames of the connection, checked against `--max-malformed`.
+pub struct MalformedRate {
+    limit: MalformedLimit,
+    times: VecDeque<Instant>,
+}
+
+impl MalformedRate {
+    pub fn new(limit: MalformedLimit) -> Self {
+        MalformedRate {
+            limit,
+            times: VecDeque::new(),
+        }
+    }
+
+    /// Records a malformed frame, returning whether the limit is exceeded.
+    pub fn record(&mut self) -> bool {
+        let now = Instant::now();
+        self.times.push_back(now);
+        if let Some(window) = self.limit.window {
+            while self
+                .times
+                .front()
+                .is_some_and(|&time| now.duration_since(time) > window)
+            {
+                self.times.pop_front();
+            }
+        }
+        self.times.len() > self.limit.count
+    }
+
+    pub fn reset(&mut self) {
+        self.times.clear();
+    }
+}
+
+/// Signs of data lost on the wire, collected for `--loss-report`.
+#[derive(Default)]
+pub struct Losses {
+    overflows: usize,
+    invalid_headers: usize,
+    malformed: usize,
+    gaps: usize,
+    longest_gap: f64,
+    last_time: Option<f64>,
+}
+
+impl Losses {
+    pub fn add_itm(&mut self, itm_packet: &ItmPacket) {
+        self.overflows += itm_packet.overflows;
+        self.invalid_headers += itm_packet.invalid_headers;
+    }
+
+    pub fn malformed(&mut self) {
+        self.malformed += 1;
+    }
+
+    /// Records the device `time` of a frame, counting gaps longer than `threshold` seconds.
+    pub fn timestamp(&mut self, time: f64, threshold: f64) {
+        // going back in time is a restart of the device, not a gap
+        if let Some(gap) = self.last_time.map(|last| time - last) {
+            if gap > threshold {
+                self.gaps += 1;
+                self.longest_gap = self.longest_gap.max(gap);
+            }
+        }
+        self.last_time = Some(time);
+    }
+
+    /// Starts over with the time of the next frame, e.g. after a reconnection.
+    pub fn reset_time(&mut self) {
+        self.last_time = None;
+    }
+
+    pub fn print(&self, threshold: f64) {
+        if self.overflows + self.invalid_headers + self.malformed + self.gaps == 0 {
+            println!("(HOST) loss report: no signs of data loss");
+            return;
+        }
+
+        println!("(HOST) loss report: suspected data loss");
+        println!("    ITM overflow packets  {:>6}", self.overflows);
+        println!("    invalid ITM headers   {:>6}", self.invalid_headers);
+        println!("    malformed frames      {:>6}", self.malformed);
+        print!(
+            "    gaps over {:<11} {:>6}",
+            format!("{}s", threshold),
+            self.gaps
+        );
+        match self.gaps {
+            0 => println!(),
+            _ => println!(" (longest {:.3}s)", self.longest_gap),
+        }
+    }
+}
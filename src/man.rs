@@ -0,0 +1,50 @@
+use clap_mangen::Man;
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ManArgs {
+    /// Write `defmt-listener.1` and a `defmt-listener-<subcommand>.1` page per subcommand to this
+    /// directory instead of printing the main page
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+}
+
+/// Prints the man page of `command`, or writes the pages of it and its subcommands.
+pub fn run(args: ManArgs, command: clap::Command) -> anyhow::Result<()> {
+    // for the footer, without the `-V` clap would add
+    let command = command
+        .version(env!("CARGO_PKG_VERSION"))
+        .disable_version_flag(true);
+    let Some(dir) = &args.out_dir else {
+        io::stdout().write_all(&render(command)?)?;
+        return Ok(());
+    };
+
+    fs::create_dir_all(dir)?;
+    let name = command.get_name().to_string();
+    for subcommand in command.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        // the pages of subcommands are named like those of git, e.g. `git-log`
+        let page = format!("{}-{}", name, subcommand.get_name());
+        let subcommand = subcommand
+            .clone()
+            .bin_name(format!("{} {}", name, subcommand.get_name()))
+            .name(page.clone())
+            .version(env!("CARGO_PKG_VERSION"))
+            .disable_version_flag(true);
+        fs::write(dir.join(format!("{}.1", page)), render(subcommand)?)?;
+    }
+    let path = dir.join(format!("{}.1", name));
+    fs::write(&path, render(command)?)?;
+    println!("man pages written to {}", dir.display());
+    Ok(())
+}
+
+fn render(command: clap::Command) -> io::Result<Vec<u8>> {
+    let mut page = Vec::new();
+    Man::new(command).render(&mut page)?;
+    Ok(page)
+}
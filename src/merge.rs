@@ -0,0 +1,289 @@
+use anyhow::anyhow;
+use chrono::{Local, SecondsFormat, TimeZone};
+use defmt_decoder::{DecodeError, Table};
+use std::{
+    env, fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    capture::{self, Capture, CaptureFormat, Header, Metadata, Records},
+    decode, elf, fetch,
+    frames::FrameDecoder,
+    itm::{Completed, ItmPacket, ITM_OVERFLOW},
+    location_info, render_locations,
+};
+
+/// Stimulus ports of ITM, one per merged capture
+const MAX_CAPTURES: usize = 32;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MergeArgs {
+    /// Timestamped captures, e.g. one per board
+    #[arg(num_args = 2.., required = true)]
+    captures: Vec<PathBuf>,
+    /// ITM stimulus port carrying the defmt data in the captures
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Merged capture, with the data of the n-th capture on stimulus port n; with `--decode` the
+    /// decoded log, stdout by default
+    #[arg(long, short, required_unless_present = "decode")]
+    out: Option<PathBuf>,
+    /// Write the decoded frames instead, each labeled with its capture
+    #[arg(long)]
+    decode: bool,
+    /// ELF of the n-th capture for `--decode`, by default the one it embeds (can be repeated)
+    #[arg(long, requires = "decode")]
+    elf: Vec<PathBuf>,
+    /// Label of the n-th capture for `--decode`, by default its file name up to the first dot (can
+    /// be repeated)
+    #[arg(long, requires = "decode")]
+    label: Vec<String>,
+}
+
+/// A capture being merged, with its next record.
+struct Source {
+    records: Records,
+    header: Option<Header>,
+    next: Option<(u64, Vec<u8>)>,
+    itm_packet: ItmPacket,
+}
+
+impl Source {
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let (records, header) = capture::open_records(path)?;
+        let mut itm_packet = ItmPacket::new();
+        itm_packet.quiet = true;
+        let mut source = Source {
+            records,
+            header,
+            next: None,
+            itm_packet,
+        };
+        source.advance()?;
+        Ok(source)
+    }
+
+    fn advance(&mut self) -> io::Result<()> {
+        self.next = self
+            .records
+            .record()?
+            .map(|(time, bytes)| (time, bytes.to_vec()));
+        Ok(())
+    }
+}
+
+pub fn run(args: MergeArgs) -> anyhow::Result<()> {
+    if args.captures.len() > MAX_CAPTURES {
+        return Err(anyhow!(
+            "Up to {} captures can be merged, one per stimulus port",
+            MAX_CAPTURES
+        ));
+    }
+    let mut sources = args
+        .captures
+        .iter()
+        .map(|path| Source::open(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    match args.decode {
+        true => merge_decoded(&args, &mut sources),
+        false => merge_captures(&args, &mut sources),
+    }
+}
+
+/// Takes the records of all sources in the order of their host times, passing each with the index
+/// of its source to `f`.
+fn merge(
+    sources: &mut [Source],
+    mut f: impl FnMut(usize, &mut Source, u64, &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    // NOTE records of the same time are taken in the order of the captures
+    while let Some(i) = (0..sources.len())
+        .filter(|&i| sources[i].next.is_some())
+        .min_by_key(|&i| sources[i].next.as_ref().map(|(time, _)| *time))
+    {
+        let (time, bytes) = sources[i].next.take().expect("filtered");
+        f(i, &mut sources[i], time, &bytes)?;
+        sources[i].advance()?;
+    }
+    Ok(())
+}
+
+fn merge_captures(args: &MergeArgs, sources: &mut [Source]) -> anyhow::Result<()> {
+    let out = args.out.as_ref().expect("required without --decode");
+
+    let mut targets = Vec::new();
+    let mut elfs = Vec::new();
+    for (i, (source, path)) in sources.iter().zip(&args.captures).enumerate() {
+        let header = source.header.as_ref();
+        let target = header.and_then(|header| {
+            let target = header.metadata.targets.iter().find(|t| t.port == args.port);
+            target.map(|target| (target.clone(), header.elf(args.port).map(<[u8]>::to_vec)))
+        });
+        match target {
+            Some((mut target, elf)) => {
+                target.port = i as u8;
+                targets.push(target);
+                elfs.extend(elf);
+            }
+            None => println!(
+                "(HOST) WARNING: {} doesn't describe its ELF, pass it with `--port-elf {}=<elf>` to replay the merged capture",
+                path.display(),
+                i
+            ),
+        }
+    }
+    let header = Header {
+        metadata: Metadata {
+            created: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+            targets,
+        },
+        elfs,
+    };
+
+    // NOTE `Capture` appends, a merged capture starts from scratch
+    if out.exists() {
+        fs::remove_file(out)?;
+    }
+    let mut capture = Capture::new(out, CaptureFormat::Timestamped, Some(&header), None)?;
+    let mut packets = Vec::new();
+    merge(sources, |i, source, time, bytes| {
+        // moves the stimulus port of the data to the one of the capture
+        packets.clear();
+        for &byte in bytes {
+            source.itm_packet.receive_any(byte);
+            match source.itm_packet.completed() {
+                Some(Completed::Stimulus { header, port }) if port == args.port => {
+                    packets.push((i as u8) << 3 | header & 0b111);
+                    packets.extend_from_slice(source.itm_packet.payload());
+                }
+                Some(Completed::Overflow) => packets.push(ITM_OVERFLOW),
+                _ => {}
+            }
+        }
+        if !packets.is_empty() {
+            capture.write_record(time, &packets)?;
+        }
+        Ok(())
+    })?;
+    capture.finish()?;
+
+    for (i, path) in args.captures.iter().enumerate() {
+        println!("(HOST) port {}: {}", i, path.display());
+    }
+    Ok(())
+}
+
+fn merge_decoded(args: &MergeArgs, sources: &mut [Source]) -> anyhow::Result<()> {
+    let mut elfs = Vec::new();
+    for (i, (source, path)) in sources.iter().zip(&args.captures).enumerate() {
+        let elf = match args.elf.get(i) {
+            Some(elf) => fetch::read_elf(elf)?,
+            None => source
+                .header
+                .as_ref()
+                .and_then(|header| header.elf(args.port))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{} doesn't embed its ELF, pass it as the {}. --elf",
+                        path.display(),
+                        i + 1
+                    )
+                })?,
+        };
+        elfs.push(elf);
+    }
+    let tables = elfs
+        .iter()
+        .map(|elf| elf::parse_table(elf))
+        .collect::<defmt_listener::Result<Vec<Table>>>()?;
+    let locs = tables
+        .iter()
+        .zip(&elfs)
+        .map(|(table, elf)| {
+            let locs = table.get_locations(elf)?;
+            Ok(table
+                .indices()
+                .all(|idx| locs.contains_key(&(idx as u64)))
+                .then_some(locs))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut decoders = tables.iter().map(FrameDecoder::new).collect::<Vec<_>>();
+
+    let labels = args
+        .captures
+        .iter()
+        .enumerate()
+        .map(|(i, path)| match args.label.get(i) {
+            Some(label) => label.clone(),
+            None => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect::<Vec<_>>();
+    let width = labels.iter().map(|label| label.chars().count()).max();
+    let width = width.unwrap_or_default();
+
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let current_dir = env::current_dir()?;
+    let locations = locs
+        .iter()
+        .map(|locs| {
+            locs.as_ref()
+                .map(|locs| render_locations(locs, &current_dir))
+        })
+        .collect::<Vec<_>>();
+    let mut malformed = 0;
+
+    merge(sources, |i, source, time, bytes| {
+        let host_time = Local
+            .timestamp_opt((time / 1_000_000) as i64, (time % 1_000_000) as u32 * 1000)
+            .single()
+            .map(|time| time.to_rfc3339_opts(SecondsFormat::Micros, false))
+            .unwrap_or_default();
+        let decoder = &mut decoders[i];
+
+        for &byte in bytes {
+            match source.itm_packet.receive(args.port, byte) {
+                Some(payload) => decoder.received(payload),
+                None => continue,
+            }
+            loop {
+                match decoder.decode() {
+                    Ok((frame, _)) => {
+                        let text = decode::text(&frame, location_info(&locations[i], &frame));
+                        writeln!(out, "{:<width$} {} {}", labels[i], host_time, text)?;
+                    }
+                    Err(DecodeError::UnexpectedEof) => break,
+                    Err(DecodeError::Malformed) => match tables[i].encoding().can_recover() {
+                        false => {
+                            return Err(anyhow!(
+                                "Malformed frame in {}, the encoding can't recover",
+                                args.captures[i].display()
+                            ))
+                        }
+                        true => malformed += 1,
+                    },
+                }
+            }
+        }
+        Ok(())
+    })?;
+    out.flush()?;
+
+    if malformed > 0 {
+        println!("(HOST) {} malformed frames skipped", malformed);
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use anyhow::anyhow;
+use std::{
+    env,
+    fs::{self, File},
+    io::{Read, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+/// Address the OpenOCD trace output is served on unless `--listen` is given.
+pub const DEFAULT_LISTEN: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 50003));
+
+const TCL_TERMINATOR: u8 = 0x1a;
+const TCL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// OpenOCD running as a child process, serving the SWO output over TCP.
+///
+/// It's killed when this is dropped.
+pub struct OpenOcd {
+    child: Child,
+    config: PathBuf,
+    /// Output of OpenOCD, kept if it failed
+    log: PathBuf,
+    failed: bool,
+}
+
+impl OpenOcd {
+    /// Spawns OpenOCD with the user `configs` and a generated config enabling the ITM `ports` and
+    /// serving the trace output on `tcp_port`.
+    pub fn spawn(
+        configs: &[PathBuf],
+        tcp_port: u16,
+        ports: &[u8],
+        cpu_freq: u32,
+        swo_freq: u32,
+    ) -> anyhow::Result<Self> {
+        let dir = env::temp_dir();
+        let config = dir.join(format!("defmt-listener-{}.cfg", std::process::id()));
+        let log = dir.join(format!("defmt-listener-{}-openocd.log", std::process::id()));
+        let mut script = format!(
+            "init\n\
+             tpiu config internal :{} uart off {} {}\n",
+            tcp_port, cpu_freq, swo_freq
+        );
+        for port in ports {
+            script += &format!("itm port {} on\n", port);
+        }
+        fs::write(&config, script)?;
+
+        let mut command = Command::new("openocd");
+        for path in configs.iter().chain([&config]) {
+            command.arg("-f").arg(path);
+        }
+        let output = File::create(&log)?;
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(output.try_clone()?)
+            .stderr(output)
+            .spawn()
+            .map_err(|err| anyhow!("Failed to start openocd: {}", err))?;
+
+        Ok(OpenOcd {
+            child,
+            config,
+            log,
+            failed: false,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn log(&self) -> &Path {
+        &self.log
+    }
+
+    /// Fails if OpenOCD has exited.
+    pub fn check(&mut self) -> anyhow::Result<()> {
+        match self.child.try_wait()? {
+            Some(status) => {
+                self.failed = true;
+                Err(anyhow!(
+                    "openocd exited with {}, see {}",
+                    status,
+                    self.log.display()
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OpenOcd {
+    fn drop(&mut self) {
+        // NOTE errors are ignored, OpenOCD may have exited already
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_file(&self.config);
+        if !self.failed {
+            let _ = fs::remove_file(&self.log);
+        }
+    }
+}
+
+/// Enables the SWO output and the ITM `ports` on the target through the TCL RPC server of a
+/// running OpenOCD at `address`.
+///
+/// The registers are reset with the target, so this is done on every connection.
+pub fn configure_target(
+    address: &str,
+    ports: &[u8],
+    cpu_freq: u32,
+    swo_freq: u32,
+) -> anyhow::Result<()> {
+    if let Some(port) = ports.iter().find(|&&port| port >= 32) {
+        return Err(anyhow!("ITM has no stimulus port {}", port));
+    }
+    if swo_freq == 0 || cpu_freq < swo_freq {
+        return Err(anyhow!(
+            "SWO frequency {} Hz can't be derived from the CPU frequency {} Hz",
+            swo_freq,
+            cpu_freq
+        ));
+    }
+
+    let commands = [
+        // DEMCR.TRCENA
+        "mmw 0xE000EDFC 0x01000000 0".to_string(),
+        // TPIU: 1 bit port, asynchronous NRZ (UART) output at the SWO frequency, no formatter
+        "mww 0xE0040004 0x00000001".to_string(),
+        format!("mww 0xE0040010 {:#010x}", cpu_freq / swo_freq - 1),
+        "mww 0xE00400F0 0x00000002".to_string(),
+        "mww 0xE0040304 0x00000100".to_string(),
+        // ITM: unlock, enable with trace bus ID 1 and sync packets, enable the stimulus ports
+        "mww 0xE0000FB0 0xC5ACCE55".to_string(),
+        "mww 0xE0000E80 0x00010005".to_string(),
+        format!(
+            "mmw 0xE0000E00 {:#010x} 0",
+            ports.iter().fold(0u32, |mask, port| mask | 1 << port)
+        ),
+    ];
+
+    let mut stream = TcpStream::connect(address)
+        .map_err(|err| anyhow!("Failed to connect to OpenOCD at {}: {}", address, err))?;
+    stream.set_read_timeout(Some(TCL_TIMEOUT))?;
+    for command in commands {
+        tcl_command(&mut stream, &command)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `command` over the TCL RPC protocol, where messages end with 0x1a.
+fn tcl_command(stream: &mut TcpStream, command: &str) -> anyhow::Result<String> {
+    stream.write_all(command.as_bytes())?;
+    stream.write_all(&[TCL_TERMINATOR])?;
+
+    let mut response = Vec::new();
+    let mut byte = [0];
+    loop {
+        stream.read_exact(&mut byte)?;
+        match byte[0] {
+            TCL_TERMINATOR => break,
+            byte => response.push(byte),
+        }
+    }
+
+    let response = String::from_utf8_lossy(&response).into_owned();
+    // memory commands print nothing on success
+    match response.trim().is_empty() {
+        true => Ok(response),
+        false => Err(anyhow!("OpenOCD `{}` failed: {}", command, response.trim())),
+    }
+}
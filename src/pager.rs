@@ -0,0 +1,67 @@
+//! `--pager`, sending the output through `$PAGER` when it goes to a terminal.
+
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Child, Command, Stdio},
+};
+
+pub struct Pager {
+    child: Child,
+}
+
+/// Starts `$PAGER`, or `less`, reading everything printed to stdout from now on. Does nothing when
+/// stdout isn't a terminal.
+#[cfg(unix)]
+pub fn spawn() -> anyhow::Result<Option<Pager>> {
+    use std::os::fd::AsRawFd;
+
+    if !io::stdout().is_terminal() {
+        return Ok(None);
+    }
+
+    let command = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let mut words = command.split_whitespace();
+    let mut pager = Command::new(words.next().expect("not empty"));
+    pager.args(words).stdin(Stdio::piped());
+    // like git: keep the colors, quit if it fits on one screen and don't clear it
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", "FRX");
+    }
+    let mut child = pager
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("Failed to start the pager `{}`: {}", command, err))?;
+
+    // NOTE stdout is a pipe now, which turns the colors of the logger off
+    env::set_var("CLICOLOR_FORCE", "1");
+    let stdin = child.stdin.take().expect("piped");
+    io::stdout().flush()?;
+    // SAFETY: both are open file descriptors, the pipe stays open through stdout
+    if unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(Some(Pager { child }))
+}
+
+#[cfg(not(unix))]
+pub fn spawn() -> anyhow::Result<Option<Pager>> {
+    Ok(None)
+}
+
+impl Pager {
+    /// Ends the output and waits for the pager to be closed.
+    pub fn wait(mut self) -> io::Result<()> {
+        io::stdout().flush()?;
+        // SAFETY: stdout isn't written to anymore, closing it ends the input of the pager
+        #[cfg(unix)]
+        unsafe {
+            libc::close(libc::STDOUT_FILENO);
+        }
+        self.child.wait()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+//! `--tee-pipe`, serving the printed frames to the clients of a Windows named pipe.
+
+use crate::{
+    decode,
+    sinks::{Broadcast, Record, Sink},
+};
+
+/// Size of the pipe buffer
+#[cfg(windows)]
+const BUFFER: u32 = 64 * 1024;
+
+pub struct TeePipe {
+    clients: Broadcast,
+}
+
+impl TeePipe {
+    /// Serves the pipe `name`, e.g. `\\.\pipe\defmt`, to any number of clients from threads.
+    #[cfg(windows)]
+    pub fn spawn(name: &str) -> anyhow::Result<Self> {
+        use std::{ffi::OsStr, os::windows::ffi::OsStrExt, thread};
+
+        let wide = OsStr::new(name)
+            .encode_wide()
+            .chain(Some(0))
+            .collect::<Vec<_>>();
+        // the first instance is created here to report the error before the session starts
+        let mut pipe = windows::create(&wide)
+            .map_err(|err| anyhow::anyhow!("Failed to create --tee-pipe {}: {}", name, err))?;
+
+        let clients = Broadcast::default();
+        let accepted = clients.clone();
+        let name = name.to_string();
+        thread::spawn(move || loop {
+            if let Err(err) = windows::connect(&pipe) {
+                println!("(HOST) WARNING: --tee-pipe {}: {}", name, err);
+                return;
+            }
+            accepted.add(pipe);
+            pipe = match windows::create(&wide) {
+                Ok(pipe) => pipe,
+                Err(err) => {
+                    println!("(HOST) WARNING: --tee-pipe {}: {}", name, err);
+                    return;
+                }
+            };
+        });
+        Ok(TeePipe { clients })
+    }
+
+    #[cfg(not(windows))]
+    pub fn spawn(_: &str) -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!("--tee-pipe is only supported on Windows"))
+    }
+}
+
+impl Sink for TeePipe {
+    /// Sends the frame as printed to the connected clients.
+    fn frame(&mut self, record: &Record) {
+        self.clients
+            .line(&decode::text(record.frame, record.location));
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        fs::File,
+        io,
+        os::windows::io::{AsRawHandle, FromRawHandle},
+        ptr,
+    };
+    use windows_sys::Win32::{
+        Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_OUTBOUND,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    };
+
+    use super::BUFFER;
+
+    /// Creates an instance of the pipe `name`, a NUL terminated UTF-16 string.
+    pub fn create(name: &[u16]) -> io::Result<File> {
+        // SAFETY: `name` is NUL terminated, the handle is owned by the returned file
+        unsafe {
+            let handle = CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER,
+                0,
+                0,
+                ptr::null(),
+            );
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(File::from_raw_handle(handle))
+        }
+    }
+
+    /// Waits for a client to open the instance.
+    pub fn connect(pipe: &File) -> io::Result<()> {
+        // SAFETY: the handle is open as long as `pipe`
+        if unsafe { ConnectNamedPipe(pipe.as_raw_handle(), ptr::null_mut()) } == 0 {
+            let err = io::Error::last_os_error();
+            // a client that opened the pipe in between is connected as well
+            if err.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Live plotting window for `--plot`.
+
+use anyhow::anyhow;
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use std::{
+    collections::BTreeMap,
+    process::ExitCode,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    replay::Clock,
+    shutdown, sinks,
+    telemetry::{Extractor, Field},
+};
+
+/// Oldest points are dropped beyond this many per field
+const MAX_POINTS: usize = 100_000;
+const REPAINT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A value of a field at device time `.1`
+type Point = (String, f64, f64);
+
+/// Sends the plotted values of messages to the window.
+pub struct Chart {
+    tx: Sender<Point>,
+    names: Vec<String>,
+    extractor: Extractor,
+    clock: Clock,
+    start: Instant,
+}
+
+impl sinks::Sink for Chart {
+    /// Sends the plotted values in the message, at its device time if known.
+    fn frame(&mut self, record: &sinks::Record) {
+        let (values, time) = (self.extractor.extract(record.message()), record.time);
+        if !values.iter().any(|(name, _)| self.names.contains(name)) {
+            return;
+        }
+
+        // fall back to the host time for frames without a timestamp
+        let time = match time {
+            Some(time) => self.clock.advance(time),
+            None => self.start.elapsed().as_secs_f64(),
+        };
+        for (name, value) in values {
+            if self.names.contains(&name) {
+                // NOTE the window may have been closed already
+                let _ = self.tx.send((name, time, value));
+            }
+        }
+    }
+}
+
+/// Runs the `listener` in the background while the window shows the fields `names`, and ends the
+/// session when the window is closed.
+pub fn window(
+    names: &[String],
+    fields: Vec<Field>,
+    listener: impl FnOnce(Chart) -> anyhow::Result<ExitCode> + Send + 'static,
+) -> anyhow::Result<ExitCode> {
+    let (tx, rx) = mpsc::channel();
+    let chart = Chart {
+        tx,
+        names: names.to_vec(),
+        extractor: Extractor::new(fields),
+        clock: Clock::default(),
+        start: Instant::now(),
+    };
+    let listener = thread::spawn(move || listener(chart));
+
+    let app = PlotApp {
+        rx,
+        series: names
+            .iter()
+            .map(|name| (name.clone(), Vec::new()))
+            .collect(),
+    };
+    let result = eframe::run_native(
+        env!("CARGO_PKG_NAME"),
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(app))),
+    );
+
+    shutdown::request();
+    let exit_code = listener
+        .join()
+        .map_err(|_| anyhow!("The listener thread panicked"))?;
+    result.map_err(|err| anyhow!("Failed to open the plot window: {}", err))?;
+    exit_code
+}
+
+struct PlotApp {
+    rx: Receiver<Point>,
+    series: BTreeMap<String, Vec<[f64; 2]>>,
+}
+
+impl eframe::App for PlotApp {
+    fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
+        while let Ok((name, time, value)) = self.rx.try_recv() {
+            let points = self.series.entry(name).or_default();
+            if points.len() == MAX_POINTS {
+                points.remove(0);
+            }
+            points.push([time, value]);
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            Plot::new("fields")
+                .legend(Legend::default())
+                .x_axis_label("device time [s]")
+                .show(ui, |plot_ui| {
+                    for (name, points) in &self.series {
+                        plot_ui.line(Line::new(PlotPoints::from(points.clone())).name(name));
+                    }
+                });
+        });
+
+        ctx.request_repaint_after(REPAINT_INTERVAL);
+    }
+}
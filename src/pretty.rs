@@ -0,0 +1,77 @@
+//! `--pretty-args`, rendering byte arrays in messages as a hexdump under the message.
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Shorter arrays stay inline
+const MIN_BYTES: usize = 8;
+const BYTES_PER_LINE: usize = 16;
+
+/// An array of integers as printed by defmt, e.g. `[1, 2, 3]` or `[0x1, 0x2, 0x3]`
+const ARRAY: &str = r"\[(?:0x[0-9a-fA-F]+|\d+)(?:, (?:0x[0-9a-fA-F]+|\d+))*\]";
+
+/// Replaces the byte arrays in `message` with their length and appends their hexdump, one line
+/// per 16 bytes.
+pub fn render(message: &str) -> String {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| Regex::new(ARRAY).expect("valid regex"));
+
+    let mut dumps = Vec::new();
+    let message = regex.replace_all(message, |captures: &regex::Captures| {
+        let array = &captures[0];
+        match parse_bytes(array) {
+            Some(bytes) if bytes.len() >= MIN_BYTES => {
+                let text = format!("[{} bytes]", bytes.len());
+                dumps.push(bytes);
+                text
+            }
+            _ => array.to_string(),
+        }
+    });
+
+    let mut lines = vec![message.into_owned()];
+    for bytes in dumps {
+        lines.extend(hexdump(&bytes));
+    }
+    lines.join("\n")
+}
+
+/// The values of `[...]` if all of them fit in a byte.
+fn parse_bytes(array: &str) -> Option<Vec<u8>> {
+    array[1..array.len() - 1]
+        .split(", ")
+        .map(|value| match value.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        })
+        .collect()
+}
+
+/// `0010  10 11 12 ...  ....`
+fn hexdump(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+                    true => byte as char,
+                    false => '.',
+                })
+                .collect::<String>();
+            format!(
+                "{:04x}  {:<width$}  {}",
+                i * BYTES_PER_LINE,
+                hex,
+                ascii,
+                width = BYTES_PER_LINE * 3 - 1
+            )
+        })
+        .collect()
+}
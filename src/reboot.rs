@@ -0,0 +1,45 @@
+//! Restarts of the firmware, marked with a separator in the output.
+
+use regex::Regex;
+
+/// Counts the boots of the firmware, from the device time going backwards or `--boot-banner`.
+pub struct Reboots {
+    banner: Option<Regex>,
+    /// Device time of the last frame
+    last_time: Option<f64>,
+    /// Boots seen, 0 until the first frame
+    pub boots: usize,
+}
+
+impl Reboots {
+    pub fn new(banner: Option<Regex>) -> Self {
+        Reboots {
+            banner,
+            last_time: None,
+            boots: 0,
+        }
+    }
+
+    /// Whether the frame at `time` starts a new boot, the first one of the session aside;
+    /// `message` is only rendered to match the banner.
+    pub fn frame<'m>(&mut self, time: Option<f64>, message: impl FnOnce() -> &'m str) -> bool {
+        let backwards = matches!((self.last_time, time), (Some(last), Some(time)) if time < last);
+        let banner = self
+            .banner
+            .as_ref()
+            .is_some_and(|banner| banner.is_match(message()));
+        // a banner without a timestamp starts over as well
+        if time.is_some() || banner {
+            self.last_time = time;
+        }
+
+        if self.boots == 0 {
+            self.boots = 1;
+            return false;
+        }
+        if backwards || banner {
+            self.boots += 1;
+        }
+        backwards || banner
+    }
+}
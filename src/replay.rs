@@ -0,0 +1,150 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Parses a replay speed like `4x`, `0.5` or `0`.
+pub fn parse_speed(text: &str) -> Result<f64, String> {
+    match text.strip_suffix('x').unwrap_or(text).parse::<f64>() {
+        Ok(speed) if speed >= 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!(
+            "invalid speed `{}`, expected e.g. `4x` or `0`",
+            text
+        )),
+    }
+}
+
+/// Delays replayed frames to reproduce the pacing given by their device timestamps.
+pub struct Pacer {
+    speed: f64,
+    tick_rate: Option<f64>,
+    skip_gaps: Option<f64>,
+    /// Device time and host time of the frame the replay is aligned to
+    origin: Option<(f64, Instant)>,
+    last: f64,
+    warned: bool,
+}
+
+impl Pacer {
+    pub fn new(speed: f64, tick_rate: Option<f64>, skip_gaps: Option<f64>) -> Self {
+        Pacer {
+            speed,
+            tick_rate,
+            skip_gaps,
+            origin: None,
+            last: 0.0,
+            warned: false,
+        }
+    }
+
+    /// Waits until the frame with the formatted device `timestamp` is due, but not past `until`.
+    pub fn wait(&mut self, timestamp: &str, until: Option<Instant>) {
+        if self.speed == 0.0 {
+            return;
+        }
+
+        let time = match parse_timestamp(timestamp, self.tick_rate) {
+            Some(time) => time,
+            None => {
+                if !self.warned {
+                    println!(
+                        "(HOST) can't pace the replay by timestamp `{}`, pass --tick-rate for timestamps in ticks",
+                        timestamp
+                    );
+                    self.warned = true;
+                }
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        let origin = match &mut self.origin {
+            // the device restarted, align to its new time base
+            Some(_) if time < self.last => self.origin.insert((time, now)),
+            Some(origin) => origin,
+            None => self.origin.insert((time, now)),
+        };
+
+        if let Some(max) = self.skip_gaps {
+            let gap = time - self.last;
+            if gap > max {
+                origin.0 += gap - max;
+            }
+        }
+        self.last = time;
+
+        let due = origin.1 + Duration::from_secs_f64((time - origin.0).max(0.0) / self.speed);
+        let due = until.map_or(due, |until| due.min(until));
+        if let Some(delay) = due.checked_duration_since(now) {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Converts a formatted defmt timestamp to seconds.
+///
+/// Timestamps with a time unit display as `1.000000` or `00:00:01.000000`, plain integers are
+/// ticks and need the tick rate.
+pub fn parse_timestamp(timestamp: &str, tick_rate: Option<f64>) -> Option<f64> {
+    if timestamp.contains(':') {
+        return timestamp.split(':').try_fold(0.0, |secs, part| {
+            Some(secs * 60.0 + part.parse::<f64>().ok()?)
+        });
+    }
+
+    match timestamp.contains('.') {
+        true => timestamp.parse().ok(),
+        false => Some(timestamp.parse::<u64>().ok()? as f64 / tick_rate?),
+    }
+}
+
+/// Device time made continuous across restarts of the device.
+#[derive(Default)]
+pub struct Clock {
+    offset: f64,
+    last: Option<f64>,
+    start: Option<f64>,
+}
+
+impl Clock {
+    /// Returns the continuous time of a frame at device `time`.
+    pub fn advance(&mut self, time: f64) -> f64 {
+        if let Some(last) = self.last {
+            if time + self.offset < last {
+                self.offset = last - time;
+            }
+        }
+        let time = time + self.offset;
+        self.start.get_or_insert(time);
+        self.last = Some(time);
+        time
+    }
+
+    pub fn start(&self) -> Option<f64> {
+        self.start
+    }
+
+    pub fn elapsed(&self) -> Option<f64> {
+        Some(self.last? - self.start?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds() {
+        assert_eq!(parse_speed("4x"), Ok(4.0));
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert_eq!(parse_speed("0"), Ok(0.0));
+        assert_eq!(parse_speed("0x"), Ok(0.0));
+    }
+
+    #[test]
+    fn invalid_speeds() {
+        for text in ["", "x", "-1", "inf", "NaN", "fast", "4xx"] {
+            assert!(parse_speed(text).is_err(), "{}", text);
+        }
+    }
+}
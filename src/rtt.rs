@@ -0,0 +1,206 @@
+//! `--listen rtt://<chip>`, the defmt bytes of an RTT up channel read through probe-rs.
+
+use probe_rs::{
+    probe::{list::Lister, DebugProbeSelector, Probe},
+    rtt::{self, Rtt, ScanRegion},
+    Core, Permissions, Session,
+};
+use std::{
+    io::{self, Read},
+    ops::Range,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    capture,
+    source::{RunControl, Source},
+};
+
+/// How long the firmware may take to set up RTT after a reset
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the core to halt before the reset
+const HALT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct RttArgs {
+    /// Debug probe to use for `rtt://` and `--flash`, as `VID:PID` or `VID:PID:SERIAL`; the first
+    /// one found by default
+    #[arg(long, value_name = "SELECTOR")]
+    pub probe: Option<DebugProbeSelector>,
+    /// RTT up channel carrying the defmt frames
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    rtt_up_channel: usize,
+    /// Search the RTT control block only in this address range, e.g. `0x20000000..0x20010000`,
+    /// instead of all RAM (can be repeated)
+    #[arg(long = "rtt-scan-range", value_name = "START..END", value_parser = parse_range)]
+    rtt_scan_ranges: Vec<Range<u64>>,
+    /// How often to poll the up channel for new data
+    #[arg(long, value_name = "DURATION", default_value = "10ms", value_parser = capture::parse_duration)]
+    rtt_poll_interval: Duration,
+    /// Reset the target on every connection, to catch the frames logged while it boots
+    #[arg(long)]
+    reset_on_connect: bool,
+    /// Attach with the reset pin asserted, for targets that sleep or disable the debug port
+    #[arg(long)]
+    attach_under_reset: bool,
+}
+
+fn parse_range(text: &str) -> Result<Range<u64>, String> {
+    let address = |text: &str| {
+        let digits = text.trim_start_matches("0x");
+        u64::from_str_radix(digits, 16).map_err(|_| format!("invalid address `{}`", text))
+    };
+    let (start, end) = text
+        .split_once("..")
+        .ok_or_else(|| format!("invalid range `{}`, expected `START..END`", text))?;
+    let (start, end) = (address(start)?, address(end)?);
+    match start < end {
+        true => Ok(start..end),
+        false => Err(format!("empty range `{}`", text)),
+    }
+}
+
+/// An up channel of an attached target.
+pub struct RttSource {
+    session: Session,
+    rtt: Rtt,
+    /// Searched for the control block again after a reset
+    region: ScanRegion,
+    channel: usize,
+    poll_interval: Duration,
+    /// How long a read waits for data
+    timeout: Duration,
+}
+
+/// Attaches to `chip` and finds the control block and up channel of `args`; reads wait up to
+/// `timeout` for data.
+pub fn attach(
+    chip: &str,
+    args: &RttArgs,
+    timeout: Duration,
+    quiet: bool,
+) -> io::Result<Box<dyn Source>> {
+    let probe = open_probe(args.probe.as_ref())?;
+    let mut session = match args.attach_under_reset {
+        true => probe.attach_under_reset(chip, Permissions::default()),
+        false => probe.attach(chip, Permissions::default()),
+    }
+    .map_err(other)?;
+
+    let region = match args.rtt_scan_ranges.is_empty() {
+        true => ScanRegion::Ram,
+        false => ScanRegion::Ranges(args.rtt_scan_ranges.clone()),
+    };
+    let mut rtt = {
+        let mut core = session.core(0).map_err(other)?;
+        match args.reset_on_connect {
+            true => reset(&mut core, &region, args.rtt_poll_interval, quiet)?,
+            false => Rtt::attach_region(&mut core, &region).map_err(other)?,
+        }
+    };
+    let (address, channels) = (rtt.ptr(), rtt.up_channels().len());
+    let channel = rtt.up_channel(args.rtt_up_channel).ok_or_else(|| {
+        other(format!(
+            "no RTT up channel {}, the firmware has {}",
+            args.rtt_up_channel, channels
+        ))
+    })?;
+    if !quiet {
+        println!(
+            "(HOST) RTT control block at {:#010x}, reading up channel {} `{}`",
+            address,
+            args.rtt_up_channel,
+            channel.name().unwrap_or_default()
+        );
+    }
+
+    Ok(Box::new(RttSource {
+        session,
+        rtt,
+        region,
+        channel: args.rtt_up_channel,
+        poll_interval: args.rtt_poll_interval,
+        timeout,
+    }))
+}
+
+/// Opens the probe of `selector`, or the first one found.
+pub fn open_probe(selector: Option<&DebugProbeSelector>) -> io::Result<Probe> {
+    let lister = Lister::new();
+    match selector {
+        Some(selector) => lister.open(selector.clone()).map_err(other),
+        None => lister
+            .list_all()
+            .first()
+            .ok_or_else(|| other("no debug probe found"))?
+            .open()
+            .map_err(other),
+    }
+}
+
+/// Resets the target and attaches to the control block once the firmware has set it up again.
+fn reset(
+    core: &mut Core,
+    region: &ScanRegion,
+    poll_interval: Duration,
+    quiet: bool,
+) -> io::Result<Rtt> {
+    // the control block of the last run would be found before the firmware sets up the new one
+    core.halt(HALT_TIMEOUT).map_err(other)?;
+    let _ = Rtt::clear_control_block(core, region);
+    core.reset().map_err(other)?;
+    if !quiet {
+        println!("(HOST) target reset");
+    }
+
+    let deadline = Instant::now() + INIT_TIMEOUT;
+    loop {
+        match Rtt::attach_region(core, region) {
+            Err(rtt::Error::ControlBlockNotFound) if Instant::now() < deadline => {
+                thread::sleep(poll_interval)
+            }
+            result => return result.map_err(other),
+        }
+    }
+}
+
+fn other(err: impl ToString) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+impl Read for RttSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let mut core = self.session.core(0).map_err(other)?;
+            let channel = self
+                .rtt
+                .up_channel(self.channel)
+                .expect("checked on attach");
+            let n = channel.read(&mut core, buf).map_err(other)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            if Instant::now() >= deadline {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+impl Source for RttSource {
+    fn run_control(&mut self, action: RunControl) -> io::Result<()> {
+        let mut core = self.session.core(0).map_err(other)?;
+        match action {
+            RunControl::Halt => core.halt(HALT_TIMEOUT).map(drop).map_err(other),
+            RunControl::Resume => core.run().map_err(other),
+            // the firmware sets up a new control block
+            RunControl::Reset => {
+                self.rtt = reset(&mut core, &self.region, self.poll_interval, true)?;
+                Ok(())
+            }
+        }
+    }
+}
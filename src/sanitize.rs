@@ -0,0 +1,146 @@
+//! What the outputs make of the text sent by the firmware: `--device-ansi` and `--sanitize`.
+
+use defmt_decoder::Frame;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// The `--device-ansi` of the session, set once at its start
+static DEVICE_ANSI: AtomicU8 = AtomicU8::new(DeviceAnsi::Pass as u8);
+/// The `--sanitize` of the session
+static SANITIZE: AtomicU8 = AtomicU8::new(Sanitize::Pass as u8);
+
+/// What to do with the ANSI escape sequences in device messages, e.g. the color codes of firmware
+/// coloring its own logs.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceAnsi {
+    /// Remove them, for clean files
+    Strip,
+    /// Print them as sent
+    Pass,
+    /// Print the escape character as `\x1b`, making them visible
+    Escape,
+}
+
+/// What to do with the control characters in device messages, other than line breaks, tabs and
+/// the escape sequences of `DeviceAnsi`, and with the invalid UTF-8 in the text of esp-println.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitize {
+    /// Print them as `\xNN`
+    Escape,
+    /// Print `�` instead
+    Replace,
+    /// Print them as sent
+    Pass,
+}
+
+pub fn configure(device_ansi: DeviceAnsi, sanitize: Sanitize) {
+    DEVICE_ANSI.store(device_ansi as u8, Ordering::Relaxed);
+    SANITIZE.store(sanitize as u8, Ordering::Relaxed);
+}
+
+/// Whether messages are printed other than as sent.
+pub fn active() -> bool {
+    device_ansi() != DeviceAnsi::Pass || sanitize() != Sanitize::Pass
+}
+
+fn device_ansi() -> DeviceAnsi {
+    match DEVICE_ANSI.load(Ordering::Relaxed) {
+        value if value == DeviceAnsi::Strip as u8 => DeviceAnsi::Strip,
+        value if value == DeviceAnsi::Escape as u8 => DeviceAnsi::Escape,
+        _ => DeviceAnsi::Pass,
+    }
+}
+
+fn sanitize() -> Sanitize {
+    match SANITIZE.load(Ordering::Relaxed) {
+        value if value == Sanitize::Escape as u8 => Sanitize::Escape,
+        value if value == Sanitize::Replace as u8 => Sanitize::Replace,
+        _ => Sanitize::Pass,
+    }
+}
+
+/// The message of `frame` as the outputs print it.
+pub fn message(frame: &Frame) -> String {
+    text(frame.display_message().to_string())
+}
+
+/// `text` of the firmware as the outputs print it.
+pub fn text(text: String) -> String {
+    let text = match (text.contains(ESC), device_ansi()) {
+        (false, _) | (true, DeviceAnsi::Pass) => text,
+        (true, DeviceAnsi::Escape) => text.replace(ESC, "\\x1b"),
+        (true, DeviceAnsi::Strip) => strip(&text),
+    };
+    match (text.chars().any(is_control), sanitize()) {
+        (false, _) | (true, Sanitize::Pass) => text,
+        (true, sanitize) => text
+            .chars()
+            .map(|c| match (is_control(c), sanitize) {
+                (false, _) => c.to_string(),
+                (true, Sanitize::Replace) => char::REPLACEMENT_CHARACTER.to_string(),
+                (true, _) => format!("\\x{:02x}", c as u32),
+            })
+            .collect(),
+    }
+}
+
+/// A line of text of esp-println as printed, passed through with `--sanitize pass` if it isn't
+/// valid UTF-8.
+pub fn line(bytes: &[u8]) -> Vec<u8> {
+    match (std::str::from_utf8(bytes), sanitize()) {
+        (Ok(line), _) => text(line.to_string()).into_bytes(),
+        (Err(_), Sanitize::Pass) => bytes.to_vec(),
+        (Err(_), Sanitize::Replace) => {
+            text(String::from_utf8_lossy(bytes).into_owned()).into_bytes()
+        }
+        (Err(_), Sanitize::Escape) => {
+            let mut line = String::new();
+            for chunk in bytes.utf8_chunks() {
+                line += chunk.valid();
+                for byte in chunk.invalid() {
+                    line += &format!("\\x{:02x}", byte);
+                }
+            }
+            text(line).into_bytes()
+        }
+    }
+}
+
+/// Whether `c` may garble the terminal or a file, sequences of `DeviceAnsi` aside.
+fn is_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\n' | '\t' | ESC)
+}
+
+/// `text` without its CSI (`ESC [ ... m`), OSC (`ESC ] ... BEL`) and two-character sequences.
+pub fn strip(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // parameters and intermediates up to the final byte
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // ended by BEL or `ESC \`
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
@@ -0,0 +1,49 @@
+//! Graceful shutdown on Ctrl-C, so the end-of-session reports get printed.
+//!
+//! A second Ctrl-C terminates the process right away, e.g. while waiting for a connection.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether Ctrl-C or a termination signal was received since `install`.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handler(signal: libc::c_int) {
+        if !requested() {
+            request();
+            return;
+        }
+
+        // NOTE `tcsetattr`, `signal` and `raise` are async-signal-safe
+        crate::keys::restore_terminal();
+        // SAFETY: resets the default disposition and terminates with the same signal
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only calls async-signal-safe functions
+        unsafe {
+            libc::signal(
+                signal,
+                handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+// NOTE Ctrl-C keeps terminating the process right away elsewhere
+#[cfg(not(unix))]
+pub fn install() {}
+
+/// Ends the session as if Ctrl-C was pressed.
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
@@ -0,0 +1,423 @@
+//! The outputs taking the decoded frames: the console and the others, each with its own filter or
+//! following that of the console.
+
+use anyhow::anyhow;
+use defmt_decoder::Frame;
+use std::{
+    cell::OnceCell,
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    alarm::Event,
+    capture, decode,
+    elf::DefmtSection,
+    filter::{self, Filter},
+    syslog::{self, Protocol, Syslog},
+    webhook::{Webhook, WebhookFormat},
+    LocationInfo,
+};
+
+const KINDS: &str =
+    "`stderr`, `file:<path>`, `json:<path>`, `tcp:<addr>`, `syslog[:<path>]` or `journald[:<path>]`";
+/// Lines queued per client, more are dropped for it rather than holding up decoding
+const QUEUE: usize = 1024;
+
+/// A decoded frame as the sinks take it.
+pub struct Record<'r> {
+    pub frame: &'r Frame<'r>,
+    pub location: LocationInfo<'r>,
+    /// The name of the ELF
+    pub target: &'r str,
+    /// Its `.defmt` section, for the format strings of `--grpc`
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub section: &'r DefmtSection,
+    /// The `--name` of the listener, or the name of the `--source`
+    pub label: Option<&'r str>,
+    /// The stimulus port of the frame, unless it's of a `--source`
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub port: Option<u8>,
+    /// Device time in seconds
+    pub time: Option<f64>,
+    /// Whether the console may show the frame, not while paused or with `--dump-raw only`
+    pub shown: bool,
+    /// Printed in front of the frame on the console, empty unless it shows the frame
+    pub prefix: &'r str,
+    message: OnceCell<String>,
+}
+
+impl<'r> Record<'r> {
+    pub fn new(
+        frame: &'r Frame<'r>,
+        location: LocationInfo<'r>,
+        target: &'r str,
+        section: &'r DefmtSection,
+    ) -> Self {
+        Record {
+            frame,
+            location,
+            target,
+            section,
+            label: None,
+            port: None,
+            time: None,
+            shown: true,
+            prefix: "",
+            message: OnceCell::new(),
+        }
+    }
+
+    /// The message of the frame as sent, formatted once for all the outputs.
+    pub fn message(&self) -> &str {
+        self.message
+            .get_or_init(|| self.frame.display_message().to_string())
+    }
+}
+
+/// `Send` for the session to run on the thread of `--plot`.
+pub trait Sink: Send {
+    /// Takes the decoded frames of the scope of the sink.
+    fn frame(&mut self, record: &Record);
+
+    /// An `--alarm` tripped or cleared by the frame of `record`.
+    fn alarm(&mut self, _event: &Event, _record: &Record) {}
+
+    /// Starts the file of `boot` with `--split-on-reboot`.
+    fn reboot(&mut self, _boot: usize) {}
+
+    /// A malformed frame was skipped.
+    fn malformed(&mut self) {}
+
+    /// Writes or sends what's pending before the session ends.
+    fn finish(self: Box<Self>) {}
+}
+
+/// The frames a sink takes.
+pub enum Scope {
+    /// Those matching its own filter
+    Own(Filter),
+    /// Those shown on the console, e.g. for `--tee-pipe` to serve what's printed
+    Console,
+}
+
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<(Scope, Box<dyn Sink>)>,
+    /// Of the console, replaced in interactive mode and by `--control`
+    pub filter: Filter,
+}
+
+impl Sinks {
+    /// With `filter` for the console.
+    pub fn new(filter: Filter) -> Self {
+        Sinks {
+            sinks: Vec::new(),
+            filter,
+        }
+    }
+
+    pub fn add(&mut self, scope: Scope, sink: Box<dyn Sink>) {
+        self.sinks.push((scope, sink));
+    }
+
+    /// Whether the console shows the frame of `record`.
+    pub fn shows(&self, record: &Record) -> bool {
+        record.shown && self.filter.matches_record(record)
+    }
+
+    pub fn frame(&mut self, record: &Record) {
+        // NOTE the console filter is only matched once, for the sinks following it
+        let mut shows = None;
+        for (scope, sink) in &mut self.sinks {
+            let takes = match scope {
+                Scope::Own(filter) => filter.matches_record(record),
+                Scope::Console => {
+                    *shows.get_or_insert_with(|| record.shown && self.filter.matches_record(record))
+                }
+            };
+            if takes {
+                sink.frame(record);
+            }
+        }
+    }
+
+    /// Passes `alarm` to every sink, whatever its scope.
+    pub fn alarm(&mut self, event: &Event, record: &Record) {
+        for (_, sink) in &mut self.sinks {
+            sink.alarm(event, record);
+        }
+    }
+
+    pub fn reboot(&mut self, boot: usize) {
+        for (_, sink) in &mut self.sinks {
+            sink.reboot(boot);
+        }
+    }
+
+    pub fn malformed(&mut self) {
+        for (_, sink) in &mut self.sinks {
+            sink.malformed();
+        }
+    }
+
+    pub fn finish(self) {
+        for (_, sink) in self.sinks {
+            sink.finish();
+        }
+    }
+}
+
+/// An output given with `--sink`, e.g. `file:trace.log --level trace`, or in the `--config` file.
+#[derive(Debug, Clone)]
+pub struct SinkSpec {
+    pub kind: SinkKind,
+    pub format: SinkFormat,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone)]
+pub enum SinkKind {
+    Stderr,
+    /// Appended to
+    File(PathBuf),
+    /// Served to the clients connecting to the address
+    Tcp(String),
+    /// Like `--webhook`
+    Webhook {
+        url: String,
+        format: WebhookFormat,
+        level: log::Level,
+    },
+    /// A datagram per frame to the socket of syslog or journald, at the severity set by the rules
+    Syslog {
+        protocol: Protocol,
+        path: PathBuf,
+        rules: Vec<syslog::Rule>,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkFormat {
+    /// As printed by the listener
+    Text,
+    /// One JSON object per frame
+    Json,
+}
+
+/// Parses `<sink> [filter options]`, the sink being `stderr`, `file:<path>`, `json:<path>`,
+/// `tcp:<addr>`, `syslog` or `journald`, the last two with the path of their socket if it's not
+/// the usual one.
+pub fn parse_sink(text: &str) -> Result<SinkSpec, String> {
+    let text = text.trim();
+    let (sink, options) = text.split_once(' ').unwrap_or((text, ""));
+    let (kind, format) = match sink.split_once(':') {
+        None if sink == "stderr" => (SinkKind::Stderr, SinkFormat::Text),
+        Some(("file", path)) if !path.is_empty() => (SinkKind::File(path.into()), SinkFormat::Text),
+        Some(("json", path)) if !path.is_empty() => (SinkKind::File(path.into()), SinkFormat::Json),
+        Some(("tcp", address)) if !address.is_empty() => {
+            (SinkKind::Tcp(address.to_string()), SinkFormat::Text)
+        }
+        None if sink == "syslog" => (
+            syslog_kind(Protocol::Syslog, None, Vec::new()),
+            SinkFormat::Text,
+        ),
+        None if sink == "journald" => (
+            syslog_kind(Protocol::Journald, None, Vec::new()),
+            SinkFormat::Text,
+        ),
+        Some(("syslog", path)) if !path.is_empty() => (
+            syslog_kind(Protocol::Syslog, Some(path), Vec::new()),
+            SinkFormat::Text,
+        ),
+        Some(("journald", path)) if !path.is_empty() => (
+            syslog_kind(Protocol::Journald, Some(path), Vec::new()),
+            SinkFormat::Text,
+        ),
+        _ => return Err(format!("invalid sink `{}`, expected {}", sink, KINDS)),
+    };
+    let filter = Filter::parse_line(options).map_err(|err| filter::error_message(&err))?;
+    Ok(SinkSpec {
+        kind,
+        format,
+        filter,
+    })
+}
+
+/// The sink of `protocol` on the socket at `path`, the usual one if `None`, at the severities of
+/// `rules`.
+pub fn syslog_kind(protocol: Protocol, path: Option<&str>, rules: Vec<syslog::Rule>) -> SinkKind {
+    let path = path.unwrap_or(match protocol {
+        Protocol::Syslog => syslog::SYSLOG_PATH,
+        Protocol::Journald => syslog::JOURNALD_PATH,
+    });
+    SinkKind::Syslog {
+        protocol,
+        path: path.into(),
+        rules,
+    }
+}
+
+impl SinkSpec {
+    /// Opens the sink, a file in `name.boot0001.ext` and so on by boot if `split_on_reboot` is set.
+    pub fn open(&self, split_on_reboot: bool) -> anyhow::Result<(Scope, Box<dyn Sink>)> {
+        let format = self.format;
+        let sink: Box<dyn Sink> = match &self.kind {
+            SinkKind::Stderr => Box::new(Lines {
+                out: Box::new(io::stderr()),
+                format,
+                split: None,
+            }),
+            SinkKind::File(path) => match split_on_reboot {
+                true => Box::new(Lines {
+                    out: Box::new(append(&capture::boot_path(path, 1))?),
+                    format,
+                    split: Some(path.clone()),
+                }),
+                false => Box::new(Lines {
+                    out: Box::new(append(path)?),
+                    format,
+                    split: None,
+                }),
+            },
+            SinkKind::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .map_err(|err| anyhow!("Failed to listen on --sink {}: {}", address, err))?;
+                let broadcast = Broadcast {
+                    format,
+                    ..Broadcast::default()
+                };
+                let accepted = broadcast.clone();
+                thread::spawn(move || {
+                    for client in listener.incoming().flatten() {
+                        accepted.add(client);
+                    }
+                });
+                Box::new(broadcast)
+            }
+            SinkKind::Webhook { url, format, level } => {
+                Box::new(Webhook::new(url.clone(), *format, *level))
+            }
+            SinkKind::Syslog {
+                protocol,
+                path,
+                rules,
+            } => Box::new(Syslog::connect(*protocol, path, rules.clone())?),
+        };
+        Ok((Scope::Own(self.filter.clone()), sink))
+    }
+}
+
+fn append(path: &Path) -> anyhow::Result<LineWriter<File>> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(LineWriter::new)
+        .map_err(|err| anyhow!("Failed to open --sink {}: {}", path.display(), err))
+}
+
+impl SinkFormat {
+    /// The line of `record`, with its label in front of the text or as `source` in the JSON.
+    fn line(self, record: &Record) -> String {
+        let (frame, location) = (record.frame, record.location);
+        match (self, record.label) {
+            (SinkFormat::Text, None) => decode::text(frame, location),
+            (SinkFormat::Text, Some(label)) => {
+                format!("{} {}", label, decode::text(frame, location))
+            }
+            (SinkFormat::Json, label) => {
+                let mut json = decode::json(frame, location);
+                if let Some(label) = label {
+                    json["source"] = label.into();
+                }
+                json.to_string()
+            }
+        }
+    }
+}
+
+struct Lines {
+    out: Box<dyn Write + Send>,
+    format: SinkFormat,
+    /// Path of the file split by boot
+    split: Option<PathBuf>,
+}
+
+impl Sink for Lines {
+    fn frame(&mut self, record: &Record) {
+        // NOTE a full disk or closed stderr shouldn't end the session
+        let _ = writeln!(self.out, "{}", self.format.line(record));
+    }
+
+    fn reboot(&mut self, boot: usize) {
+        let Some(path) = &self.split else {
+            return;
+        };
+        match append(&capture::boot_path(path, boot)) {
+            Ok(file) => {
+                let _ = self.out.flush();
+                self.out = Box::new(file);
+            }
+            Err(err) => println!("(HOST) WARNING: {}, keeping the previous file", err),
+        }
+    }
+
+    fn finish(mut self: Box<Self>) {
+        let _ = self.out.flush();
+    }
+}
+
+/// Lines sent to any number of clients, each written from its own thread.
+#[derive(Clone)]
+pub struct Broadcast {
+    clients: Arc<Mutex<Vec<SyncSender<Arc<str>>>>>,
+    /// As a sink
+    format: SinkFormat,
+}
+
+impl Default for Broadcast {
+    fn default() -> Self {
+        Broadcast {
+            clients: Arc::default(),
+            format: SinkFormat::Text,
+        }
+    }
+}
+
+impl Broadcast {
+    pub fn add(&self, mut client: impl Write + Send + 'static) {
+        let (sender, receiver) = mpsc::sync_channel::<Arc<str>>(QUEUE);
+        self.clients.lock().expect("not poisoned").push(sender);
+        thread::spawn(move || {
+            for line in receiver {
+                if client.write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Sends `line` to the connected clients, forgetting those that disconnected.
+    pub fn line(&self, line: &str) {
+        let line = Arc::<str>::from(format!("{}\n", line));
+        let mut clients = self.clients.lock().expect("not poisoned");
+        clients.retain(|client| match client.try_send(line.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+impl Sink for Broadcast {
+    fn frame(&mut self, record: &Record) {
+        self.line(&self.format.line(record));
+    }
+}
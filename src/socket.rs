@@ -0,0 +1,50 @@
+use std::{io, net::TcpStream};
+
+/// Asks the kernel for a receive buffer of `size` bytes, returning the size granted.
+#[cfg(unix)]
+pub fn set_recv_buffer(stream: &TcpStream, size: u32) -> io::Result<usize> {
+    use std::{mem, os::unix::io::AsRawFd};
+
+    let fd = stream.as_raw_fd();
+    let requested = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+    let mut granted: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `fd` is an open socket and the option values are `c_int`s of the given length
+    unsafe {
+        if libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &requested as *const libc::c_int as *const libc::c_void,
+            len,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &mut granted as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    // NOTE Linux doubles the requested size to account for its bookkeeping, and reports that
+    let granted = granted.max(0) as usize;
+    Ok(match cfg!(target_os = "linux") {
+        true => granted / 2,
+        false => granted,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn set_recv_buffer(_: &TcpStream, _: u32) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "not supported on this platform",
+    ))
+}
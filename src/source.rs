@@ -0,0 +1,321 @@
+//! The transports the listener reads from, selected by the scheme of `--listen`.
+
+use std::{
+    fmt,
+    io::{self, Read},
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::socket;
+
+/// Baud rate of a serial port unless `?baud=` is given
+const DEFAULT_BAUD: u32 = 115_200;
+const SCHEMES: &str = "`tcp://`, `serial://`, `file://`, `stdin:`, `unix://` or `rtt://`";
+/// Delay before connecting again after the first failed attempt
+const BACKOFF_MIN: Duration = Duration::from_millis(100);
+/// Longest delay between two attempts
+const BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+/// A connected transport.
+pub trait Source: Read {
+    /// Asks the kernel for a receive buffer of `size` bytes, returning the size granted.
+    fn set_recv_buffer(&self, _size: u32) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported for TCP",
+        ))
+    }
+
+    /// Halts, resumes or resets the target.
+    fn run_control(&mut self, _action: RunControl) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "only supported for `rtt://`",
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunControl {
+    Halt,
+    Resume,
+    Reset,
+}
+
+/// Where to read from, e.g. `tcp://127.0.0.1:50003` or `serial:///dev/ttyACM0?baud=921600`.
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    Tcp(SocketAddr),
+    Serial {
+        path: PathBuf,
+        baud: u32,
+    },
+    /// A file or named pipe, read once
+    File(PathBuf),
+    /// Read once
+    Stdin,
+    Unix(PathBuf),
+    /// The RTT up channel of a chip attached through probe-rs, e.g. `nRF52840_xxAA`
+    Rtt(String),
+}
+
+/// Parses `tcp://<addr>`, `serial://<path>[?baud=<rate>]`, `file://<path>`, `stdin:`,
+/// `unix://<path>`, `rtt://<chip>` or a bare `<ip>:<port>`.
+pub fn parse_endpoint(text: &str) -> Result<Endpoint, String> {
+    let (scheme, rest) = match text.split_once("://") {
+        Some((scheme, rest)) => (scheme, rest),
+        None if text == "stdin:" || text == "-" => return Ok(Endpoint::Stdin),
+        None => ("tcp", text),
+    };
+    let nonempty = |rest: &str| match rest.is_empty() {
+        true => Err(format!("missing the path in `{}`", text)),
+        false => Ok(PathBuf::from(rest)),
+    };
+    match scheme {
+        "tcp" => rest
+            .parse()
+            .map(Endpoint::Tcp)
+            .map_err(|_| format!("invalid address `{}`, expected `<ip>:<port>`", rest)),
+        "serial" => {
+            let (path, baud) = match rest.split_once("?baud=") {
+                Some((path, baud)) => match baud.parse() {
+                    Ok(baud) => (path, baud),
+                    Err(_) => return Err(format!("invalid baud rate `{}`", baud)),
+                },
+                None => (rest, DEFAULT_BAUD),
+            };
+            Ok(Endpoint::Serial {
+                path: nonempty(path)?,
+                baud,
+            })
+        }
+        "file" => nonempty(rest).map(Endpoint::File),
+        "unix" => nonempty(rest).map(Endpoint::Unix),
+        "rtt" if cfg!(feature = "probe-rs") => match rest.is_empty() {
+            true => Err(format!("missing the chip in `{}`", text)),
+            false => Ok(Endpoint::Rtt(rest.to_string())),
+        },
+        "rtt" => Err("`rtt://` needs defmt-listener built with the `probe-rs` feature".to_string()),
+        _ => Err(format!(
+            "unknown scheme `{}://`, expected {}",
+            scheme, SCHEMES
+        )),
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "{}", address),
+            Endpoint::Serial { path, baud } => write!(f, "{} at {} baud", path.display(), baud),
+            Endpoint::File(path) | Endpoint::Unix(path) => write!(f, "{}", path.display()),
+            Endpoint::Stdin => write!(f, "stdin"),
+            Endpoint::Rtt(chip) => write!(f, "RTT of {}", chip),
+        }
+    }
+}
+
+impl Endpoint {
+    /// Whether to connect again once the source ends; files and stdin are read once.
+    pub fn reconnects(&self) -> bool {
+        !matches!(self, Endpoint::File(_) | Endpoint::Stdin)
+    }
+
+    /// Connects, waiting up to `wait` for a TCP connection; reads wait up to `timeout` for data
+    /// so the session can handle keys and Ctrl-C meanwhile.
+    pub fn connect(&self, wait: Duration, timeout: Duration) -> io::Result<Box<dyn Source>> {
+        match self {
+            // attached by `rtt::attach` with the options of `RttArgs`
+            Endpoint::Rtt(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "needs the probe-rs feature",
+            )),
+            Endpoint::Tcp(address) => {
+                let stream = TcpStream::connect_timeout(address, wait)?;
+                stream.set_read_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(timeout))?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Serial { path, baud } => {
+                let file = std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)?;
+                unix::set_raw(&file, *baud)?;
+                Ok(Box::new(unix::Polled { file, timeout }))
+            }
+            #[cfg(unix)]
+            Endpoint::File(path) => Ok(Box::new(unix::Polled {
+                file: std::fs::File::open(path)?,
+                timeout,
+            })),
+            #[cfg(unix)]
+            Endpoint::Stdin => Ok(Box::new(unix::Polled {
+                file: unix::stdin()?,
+                timeout,
+            })),
+            #[cfg(not(unix))]
+            Endpoint::File(path) => Ok(Box::new(std::fs::File::open(path)?)),
+            #[cfg(not(unix))]
+            Endpoint::Unix(_) | Endpoint::Serial { .. } | Endpoint::Stdin => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only supported on Unix",
+            )),
+        }
+    }
+}
+
+/// The delays between the attempts to connect to an endpoint which reconnects, doubling from
+/// 100 ms up to 5 s while they fail.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    /// Attempts failed since the last connection
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Backoff::default()
+    }
+
+    /// The delay before the next attempt, after one more failed.
+    pub fn failed(&mut self) -> Duration {
+        let delay = BACKOFF_MIN.saturating_mul(1 << self.failures.min(16));
+        self.failures += 1;
+        delay.min(BACKOFF_MAX)
+    }
+
+    /// Connected, the next failure is retried soon again.
+    pub fn connected(&mut self) {
+        self.failures = 0;
+    }
+}
+
+impl Source for TcpStream {
+    fn set_recv_buffer(&self, size: u32) -> io::Result<usize> {
+        socket::set_recv_buffer(self, size)
+    }
+}
+
+#[cfg(unix)]
+impl Source for std::os::unix::net::UnixStream {}
+
+/// A `--replay` capture
+impl Source for Box<dyn Read> {}
+
+#[cfg(not(unix))]
+impl Source for std::fs::File {}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs::File,
+        io::{self, Read},
+        os::fd::{AsRawFd, FromRawFd},
+        time::Duration,
+    };
+
+    use super::Source;
+
+    /// A file descriptor whose reads time out like those of a socket.
+    pub struct Polled {
+        pub file: File,
+        pub timeout: Duration,
+    }
+
+    impl Read for Polled {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut fd = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = self.timeout.as_millis().min(i32::MAX as u128) as i32;
+            // SAFETY: `fd` is a single open descriptor
+            match unsafe { libc::poll(&mut fd, 1, timeout) } {
+                0 => Err(io::ErrorKind::TimedOut.into()),
+                n if n < 0 => Err(io::Error::last_os_error()),
+                _ => self.file.read(buf),
+            }
+        }
+    }
+
+    impl Source for Polled {}
+
+    /// A duplicate of stdin, read without the buffering of `io::stdin`.
+    pub fn stdin() -> io::Result<File> {
+        // SAFETY: the duplicate is owned by the returned file
+        match unsafe { libc::dup(libc::STDIN_FILENO) } {
+            fd if fd < 0 => Err(io::Error::last_os_error()),
+            fd => Ok(unsafe { File::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Puts the serial port in raw mode at `baud`.
+    pub fn set_raw(file: &File, baud: u32) -> io::Result<()> {
+        let speed = match baud {
+            9600 => libc::B9600,
+            19200 => libc::B19200,
+            38400 => libc::B38400,
+            57600 => libc::B57600,
+            115_200 => libc::B115200,
+            230_400 => libc::B230400,
+            #[cfg(target_os = "linux")]
+            460_800 => libc::B460800,
+            #[cfg(target_os = "linux")]
+            921_600 => libc::B921600,
+            #[cfg(target_os = "linux")]
+            1_000_000 => libc::B1000000,
+            #[cfg(target_os = "linux")]
+            2_000_000 => libc::B2000000,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsupported baud rate {}", baud),
+                ))
+            }
+        };
+        let fd = file.as_raw_fd();
+        // SAFETY: `fd` is open and `termios` is initialized by `tcgetattr`
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::cfsetspeed(&mut termios, speed) != 0
+                || libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new();
+        let delays = (0..8).map(|_| backoff.failed()).collect::<Vec<_>>();
+        let millis = delays.iter().map(Duration::as_millis).collect::<Vec<_>>();
+        assert_eq!(millis, [100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+        for _ in 0..100 {
+            assert_eq!(backoff.failed(), BACKOFF_MAX);
+        }
+
+        backoff.connected();
+        assert_eq!(backoff.failed(), BACKOFF_MIN);
+    }
+}
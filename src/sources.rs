@@ -0,0 +1,635 @@
+//! `--source`, several targets listened to at once, e.g. two boards or the app and net cores of a
+//! dual-core chip. Each source is read and decoded on a thread of its own, and their frames are
+//! merged into the console and the sinks, labeled with the source.
+//!
+//! The device clock of a source is mapped to the host clock with the lowest difference seen, that
+//! of the frame with the least latency, and the frames are held for `--align-delay` to pass them
+//! on by when they were sent rather than by when they arrived.
+
+use anyhow::anyhow;
+use colored::Colorize;
+use defmt_decoder::{DecodeError, Frame};
+use defmt_listener::{
+    capture,
+    framing::Framing,
+    listen::{self, Hooks},
+    source::{self, Backoff, Endpoint},
+    Error,
+};
+use std::{
+    collections::BTreeMap,
+    env, io,
+    ops::ControlFlow,
+    path::PathBuf,
+    process::ExitCode,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    alarm::Alarms,
+    console_sink,
+    host_time::HostTime,
+    label::{self, Labels},
+    location_info,
+    reboot::Reboots,
+    render_timestamp, replay, shutdown,
+    sinks::{Record, Scope, Sinks},
+    summary::Summary,
+    verify_build_id, Args, Target, TIMEOUT_EXIT_CODE,
+};
+
+/// How often the threads and the merge check for Ctrl-C while the sources are quiet
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How much slower than the host clock a device clock may run, 100 ppm
+const MAX_DRIFT: f64 = 1e-4;
+
+/// A `--source`
+#[derive(Debug, Clone)]
+pub struct SourceSpec {
+    pub name: Option<String>,
+    pub endpoint: Endpoint,
+    pub elf: Option<PathBuf>,
+}
+
+/// Parses `[<name>=]<endpoint>[,elf=<path>]`, e.g. `net=serial:///dev/ttyACM1,elf=net.elf`.
+pub fn parse_source(text: &str) -> Result<SourceSpec, String> {
+    let (rest, elf) = match text.rsplit_once(",elf=") {
+        Some((rest, elf)) if !elf.is_empty() => (rest, Some(PathBuf::from(elf))),
+        Some(_) => return Err(format!("missing the path of `elf=` in `{}`", text)),
+        None => (text, None),
+    };
+    // NOTE endpoints have a `=` after their scheme or path, e.g. `serial:///dev/ttyACM0?baud=`
+    let (name, endpoint) = match rest.split_once('=') {
+        Some((name, endpoint))
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) =>
+        {
+            (Some(name.to_string()), endpoint)
+        }
+        _ => (None, rest),
+    };
+    let endpoint = source::parse_endpoint(endpoint)?;
+    if let Endpoint::Rtt(_) = endpoint {
+        return Err("`rtt://` can't be a --source, only --listen".to_string());
+    }
+    Ok(SourceSpec {
+        name,
+        endpoint,
+        elf,
+    })
+}
+
+/// Estimates when the frames of a source were sent, in µs since the Unix epoch.
+#[derive(Default)]
+struct Clock {
+    /// Host minus device time in µs, the lowest seen
+    offset: Option<f64>,
+    /// Of the last frame with a timestamp, in µs
+    device: f64,
+    host: u64,
+    /// Of the last frame or line, those of a source stay in order
+    last: u64,
+}
+
+impl Clock {
+    /// A frame that arrived at `host` with the `device` time in µs.
+    fn frame(&mut self, host: u64, device: Option<f64>) -> u64 {
+        let time = match device {
+            Some(device) => {
+                let difference = host as f64 - device;
+                let offset = match self.offset {
+                    // the device clock restarted, e.g. on a reboot
+                    Some(_) if device < self.device => difference,
+                    // follows a device clock running slower than the host clock
+                    Some(offset) => {
+                        (offset + host.saturating_sub(self.host) as f64 * MAX_DRIFT).min(difference)
+                    }
+                    None => difference,
+                };
+                self.offset = Some(offset);
+                self.device = device;
+                self.host = host;
+                (device + offset) as u64
+            }
+            None => host,
+        };
+        self.line(time)
+    }
+
+    /// A line that isn't a frame, which arrived at `host`.
+    fn line(&mut self, host: u64) -> u64 {
+        self.last = self.last.max(host);
+        self.last
+    }
+}
+
+/// What the thread of a source passes on to be merged.
+enum Event<'t> {
+    Connected,
+    ConnectionFailed(String),
+    Bytes(usize),
+    Frame(Frame<'t>),
+    /// A skipped frame, and whether it was the bytes dropped for `--max-frame-bytes`
+    Malformed(usize, bool),
+    /// A line of text in between the frames of esp-println
+    Text(String),
+}
+
+/// A frame or line held to be merged.
+enum Item<'t> {
+    Frame {
+        frame: Frame<'t>,
+        /// Device time in seconds
+        time: Option<f64>,
+        /// `time`, or the ticks without `--tick-rate`, telling the device clock went backwards
+        ticks: Option<f64>,
+    },
+    Line(String),
+}
+
+/// Listens to each `--source` on a thread of its own until they all end, passing their frames on
+/// as they come.
+pub fn run(args: &Args) -> anyhow::Result<ExitCode> {
+    let names = args
+        .sources
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            spec.name
+                .clone()
+                .unwrap_or_else(|| format!("source{}", i + 1))
+        })
+        .collect::<Vec<_>>();
+    if let Some(name) = (1..names.len()).find_map(|i| names[..i].iter().find(|&n| *n == names[i])) {
+        return Err(anyhow!("--source {} is given more than once", name));
+    }
+    let stdin = args
+        .sources
+        .iter()
+        .filter(|spec| matches!(spec.endpoint, Endpoint::Stdin))
+        .count();
+    if stdin > 1 {
+        return Err(anyhow!("Only one --source can read stdin"));
+    }
+    let framing = args.framing.unwrap_or(Framing::Itm);
+    if framing == Framing::Auto {
+        return Err(anyhow!("--source needs a --framing other than auto"));
+    }
+    let default_elf = args.elf.as_deref().or(args.runner_elf.as_deref());
+    let elfs = args
+        .sources
+        .iter()
+        .zip(&names)
+        .map(
+            |(spec, name)| match (&spec.elf, default_elf, &args.runner_elf) {
+                (Some(_), _, Some(_)) => Err(anyhow!(
+                    "--source {} has its own ELF, which can't replace the runner ELF",
+                    name
+                )),
+                (Some(elf), _, None) => Ok(elf.as_path()),
+                (None, Some(elf), _) => Ok(elf),
+                (None, None, _) => Err(anyhow!(
+                    "--source {} needs an ELF, e.g. `{}=<endpoint>,elf=<path>`",
+                    name,
+                    name
+                )),
+            },
+        )
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let colors = label::colors(&args.names);
+    let known = (names.iter().map(String::as_str)).collect::<Vec<_>>();
+    if let Some(name) = label::unknown(&colors, &known) {
+        return Err(anyhow!("--name {} is none of the sources", name));
+    }
+    if let Some(label) = label::label(&args.names) {
+        return Err(anyhow!(
+            "--name {} would label every source, they're labeled by their names",
+            label
+        ));
+    }
+
+    let current_dir = env::current_dir()?;
+    let targets = elfs
+        .iter()
+        .map(|elf| Target::new(args.port, elf, &current_dir))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut sinks = crate::sinks(args)?;
+    sinks.add(Scope::Console, console_sink(args));
+    let mut merge = Merge {
+        args,
+        targets: &targets,
+        names: &names,
+        // NOTE the JSON objects carry the name as `source`
+        prefix: Prefix {
+            labels: (!args.json).then(|| Labels::new(&known, &colors)),
+            host_time: HostTime::new(args),
+            buffer: String::new(),
+        },
+        reboots: names
+            .iter()
+            .map(|_| Reboots::new(args.boot_banner.clone()))
+            .collect(),
+        sinks,
+        alarms: (!args.alarms.is_empty())
+            .then(|| Alarms::new(args.alarms.clone(), args.fields.clone())),
+        summaries: names.iter().map(|_| Summary::new()).collect(),
+        matched: false,
+    };
+
+    shutdown::install();
+    let deadline = args
+        .timeout
+        .map(|timeout| Instant::now() + Duration::from_secs_f64(timeout));
+    let mut timed_out = false;
+    let result = thread::scope(|scope| {
+        let (sender, events) = mpsc::channel();
+        let threads = (args.sources.iter().zip(&targets).enumerate())
+            .map(|(i, (spec, target))| {
+                let sender = sender.clone();
+                scope.spawn(move || decode(i, &spec.endpoint, target, framing, args, sender))
+            })
+            .collect::<Vec<_>>();
+        drop(sender);
+
+        let delay = (args.align_delay * 1e6) as u64;
+        let mut clocks = names.iter().map(|_| Clock::default()).collect::<Vec<_>>();
+        // by time, then in the order they arrived
+        let mut held = BTreeMap::<(u64, u64), (usize, Item)>::new();
+        let mut arrived = 0u64;
+        // the device timestamp of the last frame, reused between the frames
+        let mut rendered = String::new();
+        let mut ended = false;
+        let merged = 'merge: loop {
+            let now = now();
+            while let Some((&(time, _), _)) = held.first_key_value() {
+                if !ended && time + delay > now {
+                    break;
+                }
+                let (_, (i, item)) = held.pop_first().expect("checked");
+                if let Err(err) = merge.item(i, item) {
+                    break 'merge Err(err);
+                }
+                if merge.matched {
+                    break;
+                }
+            }
+            // NOTE the frames after the one matching `--expect` are dropped
+            if ended || merge.matched {
+                break Ok(());
+            }
+
+            let wait = match held.first_key_value() {
+                Some((&(time, _), _)) => Duration::from_micros((time + delay).saturating_sub(now)),
+                None => POLL_INTERVAL,
+            };
+            match events.recv_timeout(wait.min(POLL_INTERVAL)) {
+                Ok((i, host, event)) => {
+                    let summary = &mut merge.summaries[i];
+                    let (time, item) = match event {
+                        Event::Connected => {
+                            summary.connected();
+                            (clocks[i].line(host), Item::Line("Connected!".to_string()))
+                        }
+                        Event::ConnectionFailed(err) => {
+                            summary.connection_failed();
+                            let line = format!("Connection failed: {}", err);
+                            (clocks[i].line(host), Item::Line(line))
+                        }
+                        Event::Bytes(count) => {
+                            summary.bytes(count);
+                            continue;
+                        }
+                        Event::Frame(frame) => {
+                            summary.frame(frame.level());
+                            let location = location_info(&targets[i].locations, &frame);
+                            if let Some(module) = location.2 {
+                                summary.module(module);
+                            }
+                            let timestamp = render_timestamp(&frame, &mut rendered);
+                            let parse = |tick_rate| {
+                                timestamp.and_then(|ts| replay::parse_timestamp(ts, tick_rate))
+                            };
+                            let device = parse(args.tick_rate);
+                            let ticks = device.or_else(|| parse(Some(1.0)));
+                            let time = clocks[i].frame(host, device.map(|secs| secs * 1e6));
+                            let item = Item::Frame {
+                                frame,
+                                time: device,
+                                ticks,
+                            };
+                            (time, item)
+                        }
+                        Event::Malformed(bytes, overflowed) => {
+                            summary.malformed();
+                            merge.sinks.malformed();
+                            let line = match overflowed {
+                                true => format!(
+                                    "(HOST) WARNING: no frame ended within {} bytes, dropped them",
+                                    bytes
+                                ),
+                                false if args.show_skipped_frames || args.verbose => {
+                                    "(HOST) malformed frame skipped".to_string()
+                                }
+                                false => continue,
+                            };
+                            (clocks[i].line(host), Item::Line(line))
+                        }
+                        Event::Text(text) => (clocks[i].line(host), Item::Line(text)),
+                    };
+                    held.insert((time, arrived), (i, item));
+                    arrived += 1;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => ended = true,
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) && !timed_out {
+                timed_out = true;
+                shutdown::request();
+            }
+        };
+
+        // NOTE the threads end with the session, e.g. on `--fail-on`
+        shutdown::request();
+        let decoded = (threads.into_iter().zip(&names)).try_for_each(|(thread, name)| {
+            let result = thread.join().expect("the thread of a source panicked");
+            result.map_err(|err| anyhow!("--source {}: {}", name, err))
+        });
+        merged.and(decoded)
+    });
+    merge.finish(result, timed_out)
+}
+
+/// The state of the merged frames.
+struct Merge<'a> {
+    args: &'a Args,
+    targets: &'a [Target],
+    names: &'a [String],
+    prefix: Prefix,
+    /// Of each source
+    reboots: Vec<Reboots>,
+    sinks: Sinks,
+    alarms: Option<Alarms>,
+    /// Of each source
+    summaries: Vec<Summary>,
+    /// Whether a message matched `--expect`
+    matched: bool,
+}
+
+/// The label of each source and the host time printed in front of its frames and lines.
+struct Prefix {
+    labels: Option<Labels>,
+    host_time: Option<HostTime>,
+    /// Reused between the frames
+    buffer: String,
+}
+
+impl Prefix {
+    fn get(&mut self, i: usize) -> &str {
+        self.buffer.clear();
+        if let Some(labels) = &self.labels {
+            self.buffer.push_str(labels.get(i));
+            self.buffer.push(' ');
+        }
+        if let Some(host_time) = &self.host_time {
+            host_time.write(&mut self.buffer);
+            self.buffer.push(' ');
+        }
+        &self.buffer
+    }
+}
+
+impl Merge<'_> {
+    fn item(&mut self, i: usize, item: Item) -> anyhow::Result<()> {
+        let (frame, time, ticks) = match item {
+            Item::Frame { frame, time, ticks } => (frame, time, ticks),
+            Item::Line(line) => {
+                if !self.args.quiet {
+                    println!("{}{}", self.prefix.get(i), line);
+                }
+                return Ok(());
+            }
+        };
+
+        let target = &self.targets[i];
+        let location = location_info(&target.locations, &frame);
+        let mut record = Record::new(&frame, location, &target.name, &target.section);
+        record.label = Some(&self.names[i]);
+        record.time = time;
+        if self.reboots[i].frame(ticks, || record.message()) {
+            let boot = self.reboots[i].boots;
+            self.summaries[i].reboot();
+            let separator = format!("(HOST) ======== boot {} ========", boot);
+            println!("{}{}", self.prefix.get(i), separator.bold());
+        }
+        let alarms = match &mut self.alarms {
+            Some(alarms) => alarms.check(record.message()),
+            None => Vec::new(),
+        };
+        let build_id = target.build_id.is_build_id(frame.index());
+        if self.sinks.shows(&record) || !alarms.is_empty() || build_id {
+            record.prefix = self.prefix.get(i);
+        }
+        self.sinks.frame(&record);
+        for event in &alarms {
+            self.sinks.alarm(event, &record);
+        }
+        if build_id {
+            verify_build_id(&target.build_id, self.args, &frame, record.prefix)?;
+        }
+
+        let message = record.message();
+        if let Some(fail_on) = &self.args.fail_on {
+            if fail_on.is_match(message) {
+                return Err(anyhow!(
+                    "Message matched --fail-on `{}`: {}",
+                    fail_on,
+                    message
+                ));
+            }
+        }
+        if let Some(expect) = &self.args.expect {
+            if expect.is_match(message) {
+                if !self.args.quiet {
+                    println!("(HOST) message matched --expect `{}`", expect);
+                }
+                self.matched = true;
+                shutdown::request();
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the summary of each source and returns the result of the session.
+    fn finish(self, result: anyhow::Result<()>, timed_out: bool) -> anyhow::Result<ExitCode> {
+        self.sinks.finish();
+        for (i, (summary, name)) in self.summaries.iter().zip(self.names).enumerate() {
+            if let Some(path) = &self.args.stats_out {
+                summary.write(&capture::labeled_path(path, name), Some(name))?;
+            }
+            if !self.args.quiet {
+                match &self.prefix.labels {
+                    Some(labels) => print!("{} ", labels.get(i)),
+                    None => print!("{} ", name),
+                }
+                summary.print(true);
+            }
+        }
+        if timed_out && !self.args.quiet {
+            println!(
+                "(HOST) timeout of {}s reached",
+                self.args.timeout.unwrap_or_default()
+            );
+        }
+
+        result?;
+        let alarms = self.alarms.as_ref().map_or(0, Alarms::count);
+        if self.args.fail_on_alarm && alarms > 0 {
+            return Err(anyhow!("{} alarms tripped", alarms));
+        }
+        match (&self.args.expect, self.matched) {
+            _ if timed_out => Ok(ExitCode::from(TIMEOUT_EXIT_CODE)),
+            (Some(expect), false) => Err(anyhow!(
+                "Session ended before a message matched --expect `{}`",
+                expect
+            )),
+            _ => Ok(ExitCode::SUCCESS),
+        }
+    }
+}
+
+/// Reads source `i` from `endpoint` and passes on its frames, until it ends or the session does.
+/// Other endpoints than files and stdin are connected again whenever they end.
+fn decode<'t>(
+    i: usize,
+    endpoint: &Endpoint,
+    target: &'t Target,
+    framing: Framing,
+    args: &Args,
+    sender: Sender<(usize, u64, Event<'t>)>,
+) -> anyhow::Result<()> {
+    let mut forward = Forward {
+        i,
+        args,
+        recover: target.table.encoding().can_recover() || args.lenient,
+        sender,
+        backoff: Backoff::new(),
+        error: None,
+    };
+    while !shutdown::requested() {
+        let max_frame_bytes = args.max_frame_bytes as usize;
+        let port = target.port;
+        match listen::listen_bounded(
+            endpoint,
+            &target.table,
+            port,
+            framing,
+            max_frame_bytes,
+            &mut forward,
+        ) {
+            Ok(()) => break,
+            Err(Error::Transport(err)) if endpoint.reconnects() => {
+                if forward
+                    .send(Event::ConnectionFailed(err.to_string()))
+                    .is_break()
+                {
+                    break;
+                }
+                let until = Instant::now() + forward.backoff.failed();
+                while !shutdown::requested() && Instant::now() < until {
+                    thread::sleep(
+                        until
+                            .saturating_duration_since(Instant::now())
+                            .min(POLL_INTERVAL),
+                    );
+                }
+            }
+            Err(err) => return Err(anyhow!("Failed to open {}: {}", endpoint, err)),
+        }
+    }
+    forward.error.map_or(Ok(()), Err)
+}
+
+/// The hooks of a source, passing on what it reads to be merged.
+struct Forward<'a, 't> {
+    i: usize,
+    args: &'a Args,
+    /// Whether decoding goes on past a malformed frame
+    recover: bool,
+    sender: Sender<(usize, u64, Event<'t>)>,
+    backoff: Backoff,
+    /// Why the source stopped, if it failed
+    error: Option<anyhow::Error>,
+}
+
+impl<'t> Forward<'_, 't> {
+    /// Breaks once the merge or the session stopped, e.g. on an error.
+    fn send(&self, event: Event<'t>) -> ControlFlow<()> {
+        match self.sender.send((self.i, now(), event)).is_ok() && !shutdown::requested() {
+            true => ControlFlow::Continue(()),
+            false => ControlFlow::Break(()),
+        }
+    }
+
+    fn fail(&mut self, error: anyhow::Error) -> ControlFlow<()> {
+        self.error = Some(error);
+        ControlFlow::Break(())
+    }
+}
+
+impl<'t> Hooks<'t> for Forward<'_, 't> {
+    fn on_connect(&mut self, _endpoint: &Endpoint) -> ControlFlow<()> {
+        self.backoff.connected();
+        self.send(Event::Connected)
+    }
+
+    fn on_received(&mut self, bytes: &[u8]) -> ControlFlow<()> {
+        self.send(Event::Bytes(bytes.len()))
+    }
+
+    fn on_frame(&mut self, frame: Frame<'t>, _size: usize) -> ControlFlow<()> {
+        self.send(Event::Frame(frame))
+    }
+
+    fn on_malformed(&mut self, bytes: &[u8], overflowed: bool) -> ControlFlow<()> {
+        match (self.args.strict, self.recover) {
+            (true, _) => self.fail(anyhow!("Malformed frame, aborting because of --strict")),
+            (false, false) if overflowed => self.fail(anyhow!(
+                "No frame ended within --max-frame-bytes {} bytes",
+                self.args.max_frame_bytes
+            )),
+            (false, false) => self.fail(DecodeError::Malformed.into()),
+            (false, true) => self.send(Event::Malformed(bytes.len(), overflowed)),
+        }
+    }
+
+    fn on_text(&mut self, line: &[u8]) -> ControlFlow<()> {
+        self.send(Event::Text(String::from_utf8_lossy(line).into_owned()))
+    }
+
+    fn on_idle(&mut self) -> ControlFlow<()> {
+        match shutdown::requested() {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        }
+    }
+
+    fn on_disconnect(&mut self, err: Option<&io::Error>) -> ControlFlow<()> {
+        match err {
+            Some(err) => self.send(Event::ConnectionFailed(err.to_string())),
+            None => self.on_idle(),
+        }
+    }
+}
+
+/// µs since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
@@ -0,0 +1,45 @@
+//! Printing the session statistics on SIGUSR1 or `POST /stats` of `--control`, and the top
+//! talkers on SIGUSR2 or `t` in interactive mode.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static TOP: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGUSR1 was received since the last call, resetting it.
+pub fn take() -> bool {
+    REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Whether SIGUSR2 was received since the last call, resetting it.
+pub fn take_top() -> bool {
+    TOP.swap(false, Ordering::Relaxed)
+}
+
+/// Prints the statistics as if SIGUSR1 was received.
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handler(signal: libc::c_int) {
+        match signal {
+            libc::SIGUSR2 => TOP.store(true, Ordering::Relaxed),
+            _ => REQUESTED.store(true, Ordering::Relaxed),
+        }
+    }
+
+    for signal in [libc::SIGUSR1, libc::SIGUSR2] {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                signal,
+                handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
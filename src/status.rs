@@ -0,0 +1,171 @@
+//! The status line kept at the bottom of a terminal, instead of a line per connection attempt, or
+//! the progress through a replayed capture.
+
+use std::{
+    io::{self, IsTerminal, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the rate is computed
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+/// Characters of the progress bar
+const BAR_WIDTH: usize = 20;
+
+enum State {
+    Connecting {
+        attempts: usize,
+        error: String,
+    },
+    Connected,
+    /// Through a capture of `size` bytes, of which `read` are read
+    Replaying {
+        size: u64,
+        read: Arc<AtomicU64>,
+    },
+}
+
+pub struct StatusLine {
+    address: String,
+    state: State,
+    /// Start of the current state
+    since: Instant,
+    /// Bytes received since `sampled`
+    bytes: usize,
+    sampled: Instant,
+    /// In bytes per second
+    rate: f64,
+    /// Whether the line is on the screen
+    shown: bool,
+}
+
+impl StatusLine {
+    /// `None` if stdout isn't a terminal.
+    pub fn new(address: &str) -> Option<Self> {
+        io::stdout().is_terminal().then(|| StatusLine {
+            address: address.to_string(),
+            state: State::Connecting {
+                attempts: 0,
+                error: String::new(),
+            },
+            since: Instant::now(),
+            bytes: 0,
+            sampled: Instant::now(),
+            rate: 0.0,
+            shown: false,
+        })
+    }
+
+    /// The progress through the capture at `path`, `None` if stdout isn't a terminal.
+    pub fn replay(path: &Path, size: u64, read: Arc<AtomicU64>) -> Option<Self> {
+        let mut status = StatusLine::new(&path.display().to_string())?;
+        status.state = State::Replaying { size, read };
+        Some(status)
+    }
+
+    pub fn connection_failed(&mut self, error: String) {
+        match &mut self.state {
+            State::Connecting {
+                attempts,
+                error: last,
+            } => {
+                *attempts += 1;
+                *last = error;
+            }
+            State::Connected | State::Replaying { .. } => {
+                self.state = State::Connecting { attempts: 1, error };
+                self.since = Instant::now();
+            }
+        }
+        self.draw();
+    }
+
+    pub fn connected(&mut self) {
+        self.state = State::Connected;
+        self.since = Instant::now();
+        (self.bytes, self.sampled, self.rate) = (0, Instant::now(), 0.0);
+    }
+
+    pub fn disconnected(&mut self) {
+        self.state = State::Connecting {
+            attempts: 0,
+            error: String::new(),
+        };
+        self.since = Instant::now();
+    }
+
+    pub fn bytes(&mut self, count: usize) {
+        self.bytes += count;
+    }
+
+    /// Removes the line, to print something in its place.
+    pub fn clear(&mut self) {
+        if self.shown {
+            print!("\r\x1b[2K");
+            let _ = io::stdout().flush();
+            self.shown = false;
+        }
+    }
+
+    /// Prints the line again with the current elapsed time and rate.
+    pub fn draw(&mut self) {
+        let sampled = self.sampled.elapsed();
+        if sampled >= RATE_INTERVAL {
+            self.rate = self.bytes as f64 / sampled.as_secs_f64();
+            (self.bytes, self.sampled) = (0, Instant::now());
+        }
+
+        let elapsed = format_elapsed(self.since.elapsed());
+        let text = match &self.state {
+            State::Connecting { attempts: 0, .. } => {
+                format!("(HOST) connecting to {}", self.address)
+            }
+            State::Connecting { attempts, error } => format!(
+                "(HOST) retrying {} for {}, {} failed attempts: {}",
+                self.address, elapsed, attempts, error
+            ),
+            State::Connected => format!(
+                "(HOST) connected to {} for {}, {:.0} B/s",
+                self.address, elapsed, self.rate
+            ),
+            State::Replaying { size, read } => {
+                let read = read.load(Ordering::Relaxed).min(*size);
+                let done = match *size {
+                    0 => 1.0,
+                    size => read as f64 / size as f64,
+                };
+                let filled = (done * BAR_WIDTH as f64) as usize;
+                let left = match read {
+                    0 => "?".to_string(),
+                    _ => format_elapsed(self.since.elapsed().mul_f64((1.0 - done) / done)),
+                };
+                format!(
+                    "(HOST) replaying {} {:>3.0}% [{}{}] {:.0} B/s, {} left",
+                    self.address,
+                    done * 100.0,
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    self.rate,
+                    left
+                )
+            }
+        };
+        print!("\r\x1b[2K{}", text);
+        let _ = io::stdout().flush();
+        self.shown = true;
+    }
+}
+
+/// `1h02m03s`, `2m03s` or `3s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::anyhow;
+use defmt_parser::Level;
+use serde_json::json;
+use std::{collections::BTreeMap, fs, path::Path, time::Instant};
+
+const LEVELS: [&str; 6] = ["error", "warn", "info", "debug", "trace", "println"];
+
+/// Totals of the session, printed however it ends.
+pub struct Summary {
+    start: Instant,
+    bytes: usize,
+    /// Frames by level, in the order of `LEVELS`
+    frames: [usize; LEVELS.len()],
+    /// Frames by module, of the ELFs with locations
+    modules: BTreeMap<String, usize>,
+    malformed: usize,
+    connections: usize,
+    failed_connections: usize,
+    /// Silences longer than `--idle-warning`
+    idle: usize,
+    /// Restarts of the firmware
+    reboots: usize,
+    /// Regions of a `--replay` skipped for their checksums, and their bytes
+    corrupted: (usize, u64),
+}
+
+impl Summary {
+    pub fn new() -> Self {
+        Summary {
+            start: Instant::now(),
+            bytes: 0,
+            frames: [0; LEVELS.len()],
+            modules: BTreeMap::new(),
+            malformed: 0,
+            connections: 0,
+            failed_connections: 0,
+            idle: 0,
+            reboots: 0,
+            corrupted: (0, 0),
+        }
+    }
+
+    pub fn bytes(&mut self, count: usize) {
+        self.bytes += count;
+    }
+
+    pub fn frame(&mut self, level: Option<Level>) {
+        let slot = match level {
+            Some(Level::Error) => 0,
+            Some(Level::Warn) => 1,
+            Some(Level::Info) => 2,
+            Some(Level::Debug) => 3,
+            Some(Level::Trace) => 4,
+            None => 5,
+        };
+        self.frames[slot] += 1;
+    }
+
+    pub fn module(&mut self, module: &str) {
+        match self.modules.get_mut(module) {
+            Some(count) => *count += 1,
+            None => {
+                self.modules.insert(module.to_string(), 1);
+            }
+        }
+    }
+
+    pub fn malformed(&mut self) {
+        self.malformed += 1;
+    }
+
+    pub fn connected(&mut self) {
+        self.connections += 1;
+    }
+
+    pub fn connection_failed(&mut self) {
+        self.failed_connections += 1;
+    }
+
+    pub fn idle(&mut self) {
+        self.idle += 1;
+    }
+
+    pub fn reboot(&mut self) {
+        self.reboots += 1;
+    }
+
+    pub fn corrupted(&mut self, bytes: u64) {
+        self.corrupted.0 += 1;
+        self.corrupted.1 += bytes;
+    }
+
+    /// Writes the counters as JSON for `--stats-out`, with the `--name` label if any.
+    pub fn write(&self, path: &Path, label: Option<&str>) -> anyhow::Result<()> {
+        let mut frames = LEVELS
+            .iter()
+            .zip(self.frames)
+            .map(|(level, count)| (level.to_string(), json!(count)))
+            .collect::<serde_json::Map<_, _>>();
+        frames.insert(
+            "total".to_string(),
+            json!(self.frames.iter().sum::<usize>()),
+        );
+        let stats = json!({
+            "name": label,
+            "duration_secs": self.start.elapsed().as_secs_f64(),
+            "bytes": self.bytes,
+            "frames": frames,
+            "modules": self.modules,
+            "malformed": self.malformed,
+            "idle_periods": self.idle,
+            "reboots": self.reboots,
+            "corrupted_regions": self.corrupted.0,
+            "corrupted_bytes": self.corrupted.1,
+            "reconnects": self.connections.saturating_sub(1),
+            "failed_connections": self.failed_connections,
+        });
+        fs::write(path, format!("{:#}\n", stats))
+            .map_err(|err| anyhow!("Failed to write --stats-out {}: {}", path.display(), err))
+    }
+
+    /// Prints the summary; `live` adds the connection counts, which replays don't have.
+    pub fn print(&self, live: bool) {
+        let levels = LEVELS
+            .iter()
+            .zip(self.frames)
+            .filter(|(_, count)| *count > 0)
+            .map(|(level, count)| format!("{} {}", count, level))
+            .collect::<Vec<_>>();
+
+        println!("(HOST) session summary");
+        println!(
+            "    duration              {:.1}s",
+            self.start.elapsed().as_secs_f64()
+        );
+        println!("    bytes received        {}", self.bytes);
+        print!(
+            "    frames decoded        {}",
+            self.frames.iter().sum::<usize>()
+        );
+        match levels.is_empty() {
+            true => println!(),
+            false => println!(" ({})", levels.join(", ")),
+        }
+        println!("    malformed frames      {}", self.malformed);
+        if self.idle > 0 {
+            println!("    idle periods          {}", self.idle);
+        }
+        if self.reboots > 0 {
+            println!("    reboots               {}", self.reboots);
+        }
+        if self.corrupted.0 > 0 {
+            println!(
+                "    corrupted regions     {} ({} bytes skipped)",
+                self.corrupted.0, self.corrupted.1
+            );
+        }
+        if live {
+            println!(
+                "    reconnects            {} ({} failed attempts)",
+                self.connections.saturating_sub(1),
+                self.failed_connections
+            );
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! The `syslog` and `journald` sinks, a datagram per frame to the local syslog socket or to the
+//! native one of journald, at the syslog severity of its level. The `severity` rules of
+//! `--config` change it, e.g. for the errors of a module to alert as `crit`.
+
+use anyhow::anyhow;
+use defmt_parser::Level;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    level_map, sanitize,
+    sinks::{Record, Sink},
+};
+
+/// The socket of syslog
+pub const SYSLOG_PATH: &str = "/dev/log";
+/// The native socket of journald
+pub const JOURNALD_PATH: &str = "/run/systemd/journal/socket";
+/// `user`, the facility of the messages
+const FACILITY: u8 = 1;
+const IDENTIFIER: &str = "defmt-listener";
+
+/// The severities of syslog, from the most severe, as numbered by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl Severity {
+    /// The usual one of the frames of `level`, `None` for `println`.
+    fn of(level: Option<Level>) -> Self {
+        match level {
+            Some(Level::Error) => Severity::Err,
+            Some(Level::Warn) => Severity::Warning,
+            Some(Level::Info) | None => Severity::Info,
+            Some(Level::Debug | Level::Trace) => Severity::Debug,
+        }
+    }
+}
+
+/// Sends the frames of `level`, `None` for `println`, of the modules starting with `module`, of
+/// every module if `None`, at `severity`.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    module: Option<String>,
+    level: Option<Level>,
+    severity: Severity,
+}
+
+/// Parses `[<module>:]<level>=<severity>`, e.g. `error=crit` or `app::net:warn=err`.
+pub fn parse_rule(text: &str) -> Result<Rule, String> {
+    let (module, levels) = match text.rsplit_once(':') {
+        Some((module, levels)) if !module.is_empty() && !module.ends_with(':') => {
+            (Some(module.to_string()), levels)
+        }
+        _ => (None, text),
+    };
+    let (level, severity) = levels.split_once('=').ok_or_else(|| {
+        format!(
+            "invalid rule `{}`, expected `<level>=<severity>` or `<module>:<level>=<severity>`, e.g. `error=crit`",
+            text
+        )
+    })?;
+    Ok(Rule {
+        module,
+        level: parse_level(level)?,
+        severity: parse_severity(severity)?,
+    })
+}
+
+fn parse_level(level: &str) -> Result<Option<Level>, String> {
+    match level.to_ascii_lowercase().as_str() {
+        "trace" => Ok(Some(Level::Trace)),
+        "debug" => Ok(Some(Level::Debug)),
+        "info" => Ok(Some(Level::Info)),
+        "warn" => Ok(Some(Level::Warn)),
+        "error" => Ok(Some(Level::Error)),
+        "println" => Ok(None),
+        _ => Err(format!(
+            "invalid level `{}`, expected `trace`, `debug`, `info`, `warn`, `error` or `println`",
+            level
+        )),
+    }
+}
+
+fn parse_severity(severity: &str) -> Result<Severity, String> {
+    match severity.to_ascii_lowercase().as_str() {
+        "emerg" => Ok(Severity::Emerg),
+        "alert" => Ok(Severity::Alert),
+        "crit" => Ok(Severity::Crit),
+        "err" | "error" => Ok(Severity::Err),
+        "warning" | "warn" => Ok(Severity::Warning),
+        "notice" => Ok(Severity::Notice),
+        "info" => Ok(Severity::Info),
+        "debug" => Ok(Severity::Debug),
+        _ => Err(format!(
+            "invalid severity `{}`, expected `emerg`, `alert`, `crit`, `err`, `warning`, `notice`, `info` or `debug`",
+            severity
+        )),
+    }
+}
+
+/// The severity of the frames of `level` of `module`, by the last rule matching them.
+fn severity(rules: &[Rule], level: Option<Level>, module: Option<&str>) -> Severity {
+    let rule = rules.iter().rev().find(|rule| {
+        rule.level == level
+            && match &rule.module {
+                Some(prefix) => module.is_some_and(|module| module.starts_with(prefix.as_str())),
+                None => true,
+            }
+    });
+    rule.map_or(Severity::of(level), |rule| rule.severity)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// `<priority>tag[pid]: message`, as sent by `syslog(3)`
+    Syslog,
+    /// The fields of the native protocol of journald
+    Journald,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Syslog => "syslog",
+            Protocol::Journald => "journald",
+        }
+    }
+}
+
+pub struct Syslog {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+    protocol: Protocol,
+    path: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Syslog {
+    #[cfg(unix)]
+    pub fn connect(protocol: Protocol, path: &Path, rules: Vec<Rule>) -> anyhow::Result<Self> {
+        Ok(Syslog {
+            socket: connect(path).map_err(|err| {
+                anyhow!(
+                    "Failed to connect --sink {} to {}: {}",
+                    protocol.name(),
+                    path.display(),
+                    err
+                )
+            })?,
+            protocol,
+            path: path.to_path_buf(),
+            rules,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(protocol: Protocol, _: &Path, _: Vec<Rule>) -> anyhow::Result<Self> {
+        Err(anyhow!(
+            "--sink {} is only supported on Unix",
+            protocol.name()
+        ))
+    }
+
+    fn datagram(&self, record: &Record) -> Vec<u8> {
+        let (frame, (file, line, module)) = (record.frame, record.location);
+        let level = level_map::level(frame, module);
+        let severity = severity(&self.rules, level, module);
+        let message = sanitize::message(frame);
+        let timestamp = frame.display_timestamp().map(|ts| ts.to_string());
+
+        match self.protocol {
+            Protocol::Syslog => {
+                let mut text = String::new();
+                for prefix in [record.label, timestamp.as_deref()].into_iter().flatten() {
+                    text += prefix;
+                    text.push(' ');
+                }
+                text += &message;
+                syslog_datagram(severity, &text)
+            }
+            Protocol::Journald => {
+                let (priority, line) = ((severity as u8).to_string(), line.map(|l| l.to_string()));
+                let fields = [
+                    ("PRIORITY", Some(priority.as_str())),
+                    ("SYSLOG_FACILITY", Some("1")),
+                    ("SYSLOG_IDENTIFIER", Some(IDENTIFIER)),
+                    ("MESSAGE", Some(message.as_str())),
+                    ("CODE_FILE", file),
+                    ("CODE_LINE", line.as_deref()),
+                    ("CODE_FUNC", module),
+                    (
+                        "DEFMT_LEVEL",
+                        Some(level.map_or("println", |level| level.as_str())),
+                    ),
+                    ("DEFMT_TIMESTAMP", timestamp.as_deref()),
+                    ("DEFMT_SOURCE", record.label),
+                ];
+                let fields = fields
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((key, value?)));
+                journald_datagram(fields)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect(path: &Path) -> std::io::Result<std::os::unix::net::UnixDatagram> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    Ok(socket)
+}
+
+impl Sink for Syslog {
+    fn frame(&mut self, record: &Record) {
+        let datagram = self.datagram(record);
+        // NOTE reconnecting once for a restarted daemon, a lost frame shouldn't end the session
+        #[cfg(unix)]
+        if self.socket.send(&datagram).is_err() {
+            if let Ok(socket) = connect(&self.path) {
+                self.socket = socket;
+                let _ = self.socket.send(&datagram);
+            }
+        }
+    }
+}
+
+/// `text` as sent to syslog at `severity`.
+fn syslog_datagram(severity: Severity, text: &str) -> Vec<u8> {
+    let priority = FACILITY * 8 + severity as u8;
+    format!(
+        "<{}>{}[{}]: {}",
+        priority,
+        IDENTIFIER,
+        std::process::id(),
+        text
+    )
+    .into_bytes()
+}
+
+/// The `fields` as sent to journald, `KEY=value` lines, the values with line breaks after their
+/// length instead.
+fn journald_datagram<'f>(fields: impl IntoIterator<Item = (&'f str, &'f str)>) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (key, value) in fields {
+        datagram.extend_from_slice(key.as_bytes());
+        match value.contains('\n') {
+            true => {
+                datagram.push(b'\n');
+                datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            }
+            false => datagram.push(b'='),
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rule = parse_rule("app::net:error=crit").unwrap();
+        assert_eq!(rule.module.as_deref(), Some("app::net"));
+        assert_eq!(
+            (rule.level, rule.severity),
+            (Some(Level::Error), Severity::Crit)
+        );
+        let rule = parse_rule("PRINTLN=Notice").unwrap();
+        assert_eq!(
+            (rule.module, rule.level, rule.severity),
+            (None, None, Severity::Notice)
+        );
+        assert_eq!(parse_rule("warn=error").unwrap().severity, Severity::Err);
+
+        for text in [
+            "error",
+            "error=",
+            "=crit",
+            "error=fatal",
+            "fatal=crit",
+            ":error=crit",
+        ] {
+            assert!(parse_rule(text).is_err(), "{}", text);
+        }
+        assert!(parse_rule("app::net::error=crit").is_err());
+    }
+
+    #[test]
+    fn severities() {
+        let rules = ["error=alert", "app::net:error=crit", "app:trace=info"]
+            .map(|rule| parse_rule(rule).unwrap());
+        let severity = |level, module| severity(&rules, level, module);
+        assert_eq!(
+            severity(Some(Level::Error), Some("app::net::tcp")),
+            Severity::Crit
+        );
+        assert_eq!(
+            severity(Some(Level::Error), Some("app::usb")),
+            Severity::Alert
+        );
+        assert_eq!(severity(Some(Level::Error), None), Severity::Alert);
+        assert_eq!(severity(Some(Level::Trace), Some("app")), Severity::Info);
+        // the usual ones otherwise
+        assert_eq!(severity(Some(Level::Trace), None), Severity::Debug);
+        assert_eq!(severity(Some(Level::Warn), None), Severity::Warning);
+        assert_eq!(severity(None, Some("app")), Severity::Info);
+    }
+
+    #[test]
+    fn syslog_datagrams() {
+        let datagram = syslog_datagram(Severity::Crit, "rack1 0.5 boot");
+        let expected = format!("<10>defmt-listener[{}]: rack1 0.5 boot", std::process::id());
+        assert_eq!(String::from_utf8(datagram).unwrap(), expected);
+        assert!(syslog_datagram(Severity::Debug, "").starts_with(b"<15>"));
+    }
+
+    #[test]
+    fn journald_datagrams() {
+        let datagram = journald_datagram([("PRIORITY", "3"), ("MESSAGE", "a\nb")]);
+        let mut expected = b"PRIORITY=3\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(datagram, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connects_to_the_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path =
+            std::env::temp_dir().join(format!("defmt-listener-{}-syslog.sock", std::process::id()));
+        let err = Syslog::connect(Protocol::Journald, &path, Vec::new())
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("Failed to connect --sink journald"));
+
+        let server = UnixDatagram::bind(&path).unwrap();
+        let syslog = Syslog::connect(Protocol::Syslog, &path, Vec::new()).unwrap();
+        syslog.socket.send(b"<14>boot").unwrap();
+        let mut buffer = [0; 16];
+        let len = server.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"<14>boot");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::net::{SocketAddr, UdpSocket};
+
+use crate::sinks::{Record, Sink};
+
+/// Extracts `name=value` pairs when no `--field` is given
+const KEY_VALUE: &str = r"([A-Za-z_][A-Za-z0-9_]*)=(-?[0-9]+(?:\.[0-9]+)?(?:[eE][-+]?[0-9]+)?)";
+
+/// A numeric field extracted from messages, given as `name=regex` with one capture group.
+#[derive(Debug, Clone)]
+pub struct Field {
+    name: String,
+    regex: Regex,
+    kind: Kind,
+}
+
+/// The type of an `--extract` field, the captures that don't fit it are skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+const KINDS: [(&str, Kind); 10] = [
+    ("i8", Kind::I8),
+    ("i16", Kind::I16),
+    ("i32", Kind::I32),
+    ("i64", Kind::I64),
+    ("u8", Kind::U8),
+    ("u16", Kind::U16),
+    ("u32", Kind::U32),
+    ("u64", Kind::U64),
+    ("f32", Kind::F32),
+    ("f64", Kind::F64),
+];
+
+impl Kind {
+    /// The value of `text`, decimal or for integers also hexadecimal as `0x1f`.
+    fn parse(self, text: &str) -> Option<f64> {
+        let int = |min: i128, max: i128| {
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, text),
+            };
+            let value = match digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                Some(hex) => i128::from_str_radix(hex, 16).ok()?,
+                None => digits.parse::<i128>().ok()?,
+            };
+            let value = if negative { -value } else { value };
+            // NOTE 64-bit values lose precision past 2^53 in the f64 of the outputs
+            (min..=max).contains(&value).then_some(value as f64)
+        };
+
+        match self {
+            Kind::I8 => int(i8::MIN.into(), i8::MAX.into()),
+            Kind::I16 => int(i16::MIN.into(), i16::MAX.into()),
+            Kind::I32 => int(i32::MIN.into(), i32::MAX.into()),
+            Kind::I64 => int(i64::MIN.into(), i64::MAX.into()),
+            Kind::U8 => int(0, u8::MAX.into()),
+            Kind::U16 => int(0, u16::MAX.into()),
+            Kind::U32 => int(0, u32::MAX.into()),
+            Kind::U64 => int(0, u64::MAX.into()),
+            Kind::F32 => text.parse::<f32>().ok().map(f64::from),
+            Kind::F64 => text.parse().ok(),
+        }
+    }
+}
+
+pub fn parse_field(text: &str) -> Result<Field, String> {
+    let (name, regex) = text
+        .split_once('=')
+        .ok_or_else(|| format!("invalid field `{}`, expected `name=regex`", text))?;
+    let regex = Regex::new(regex).map_err(|err| err.to_string())?;
+    if regex.captures_len() < 2 {
+        return Err(format!(
+            "the regex of field `{}` needs a capture group for the value",
+            name
+        ));
+    }
+
+    Ok(Field {
+        name: name.to_string(),
+        regex,
+        kind: Kind::F64,
+    })
+}
+
+/// Parses an `--extract` definition, `name:type=/regex/` with the value in the first capture group,
+/// e.g. `temp:f32=/temp=(-?\d+\.\d+)/`.
+pub fn parse_extract(text: &str) -> Result<Field, String> {
+    let invalid = || {
+        format!(
+            "invalid definition `{}`, expected `name:type=/regex/`, e.g. `temp:f32=/temp=(-?\\d+\\.\\d+)/`",
+            text
+        )
+    };
+    let (name, rest) = text.split_once(':').ok_or_else(invalid)?;
+    let (kind, regex) = rest.split_once('=').ok_or_else(invalid)?;
+    let regex = regex
+        .strip_prefix('/')
+        .and_then(|regex| regex.strip_suffix('/'))
+        .ok_or_else(invalid)?;
+    if name.is_empty() || name.contains('=') {
+        return Err(invalid());
+    }
+    let kind = KINDS
+        .iter()
+        .find(|(key, _)| *key == kind)
+        .map(|&(_, kind)| kind)
+        .ok_or_else(|| {
+            format!(
+                "invalid type `{}` of `{}`, expected `i8` to `i64`, `u8` to `u64`, `f32` or `f64`",
+                kind, name
+            )
+        })?;
+
+    let mut field = parse_field(&format!("{}={}", name, regex))?;
+    field.kind = kind;
+    Ok(field)
+}
+
+/// Numeric values of a message as `(name, value)`.
+pub struct Extractor {
+    fields: Vec<Field>,
+    key_value: Regex,
+}
+
+impl Extractor {
+    pub fn new(fields: Vec<Field>) -> Self {
+        Extractor {
+            fields,
+            key_value: Regex::new(KEY_VALUE).expect("valid regex"),
+        }
+    }
+
+    pub fn extract(&self, message: &str) -> Vec<(String, f64)> {
+        if self.fields.is_empty() {
+            return self
+                .key_value
+                .captures_iter(message)
+                .filter_map(|c| Some((c[1].to_string(), c[2].parse().ok()?)))
+                .collect();
+        }
+
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let value = field
+                    .kind
+                    .parse(field.regex.captures(message)?.get(1)?.as_str())?;
+                Some((field.name.clone(), value))
+            })
+            .collect()
+    }
+}
+
+/// Streams numeric values to Teleplot and PlotJuggler over UDP.
+pub struct Telemetry {
+    socket: UdpSocket,
+    teleplot: Option<SocketAddr>,
+    plotjuggler: Option<SocketAddr>,
+    extractor: Extractor,
+}
+
+impl Telemetry {
+    pub fn new(
+        teleplot: Option<SocketAddr>,
+        plotjuggler: Option<SocketAddr>,
+        fields: Vec<Field>,
+    ) -> anyhow::Result<Self> {
+        Ok(Telemetry {
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            teleplot,
+            plotjuggler,
+            extractor: Extractor::new(fields),
+        })
+    }
+}
+
+impl Sink for Telemetry {
+    /// Sends the values in the message, at its device time if known.
+    fn frame(&mut self, record: &Record) {
+        let (values, time) = (self.extractor.extract(record.message()), record.time);
+        if values.is_empty() {
+            return;
+        }
+
+        // NOTE UDP errors, e.g. nobody listening, don't end the session
+        if let Some(address) = self.teleplot {
+            let lines = values
+                .iter()
+                .map(|(name, value)| match time {
+                    Some(time) => format!("{}:{}:{}", name, time * 1000.0, value),
+                    None => format!("{}:{}", name, value),
+                })
+                .collect::<Vec<_>>();
+            let _ = self.socket.send_to(lines.join("\n").as_bytes(), address);
+        }
+        if let Some(address) = self.plotjuggler {
+            let mut object = Map::new();
+            if let Some(time) = time {
+                object.insert("timestamp".to_string(), time.into());
+            }
+            for (name, value) in values {
+                object.insert(name, value.into());
+            }
+            let json = Value::Object(object).to_string();
+            let _ = self.socket.send_to(json.as_bytes(), address);
+        }
+    }
+}
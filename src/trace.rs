@@ -0,0 +1,122 @@
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, env, fs::File, io::BufWriter, path::PathBuf};
+
+use crate::{
+    corrupted_warning, elf, fetch, frames,
+    replay::{self, Clock},
+};
+
+/// Prefixes of messages that open and close a span with `--spans`.
+const SPAN_BEGIN: &str = "begin ";
+const SPAN_END: &str = "end ";
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TraceArgs {
+    /// Path or `http(s)://` URL of the ELF file
+    #[arg(long)]
+    elf: PathBuf,
+    /// ITM stimulus port carrying the defmt data
+    #[arg(long, default_value_t = 0)]
+    port: u8,
+    /// Frequency of the device timestamp in Hz, for timestamps without a unit
+    #[arg(long)]
+    tick_rate: Option<f64>,
+    /// Pair messages starting with `begin <name>` and `end <name>` into spans
+    #[arg(long)]
+    spans: bool,
+    /// Chrome trace JSON file to write, can be opened in Perfetto or chrome://tracing
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Capture, e.g. written with `--capture`
+    capture: PathBuf,
+}
+
+pub fn run(args: TraceArgs) -> anyhow::Result<()> {
+    let bytes = fetch::read_elf(&args.elf)?;
+    let table = elf::parse_table(&bytes)?;
+    let locs = table.get_locations(&bytes)?;
+    let current_dir = env::current_dir()?;
+
+    // one track per module
+    let mut tracks = BTreeMap::<String, usize>::new();
+    let mut events = Vec::<Value>::new();
+    let mut clock = Clock::default();
+    let mut untimed = 0;
+
+    let stats = frames::decode_capture(&args.capture, args.port, &table, |frame, _| {
+        let time = frame
+            .display_timestamp()
+            .and_then(|ts| replay::parse_timestamp(&ts.to_string(), args.tick_rate));
+        let time = match time {
+            Some(time) => clock.advance(time),
+            None => {
+                untimed += 1;
+                return;
+            }
+        };
+
+        let loc = locs.get(&frame.index());
+        let module = loc.map_or("<unknown>", |loc| loc.module.as_str());
+        let next = tracks.len() + 1;
+        let tid = *tracks.entry(module.to_string()).or_insert(next);
+
+        let message = frame.display_message().to_string();
+        let level = frame.level().map_or("println", |level| level.as_str());
+        let (phase, name) = match args.spans {
+            true if message.starts_with(SPAN_BEGIN) => ("B", &message[SPAN_BEGIN.len()..]),
+            true if message.starts_with(SPAN_END) => ("E", &message[SPAN_END.len()..]),
+            _ => ("i", message.as_str()),
+        };
+
+        let mut event = json!({
+            "name": name,
+            "cat": level,
+            "ph": phase,
+            "ts": time * 1e6,
+            "pid": 1,
+            "tid": tid,
+        });
+        if phase == "i" {
+            event["s"] = json!("t");
+        }
+        if let Some(loc) = loc {
+            event["args"] = json!({ "location": elf::display_location(loc, &current_dir) });
+        }
+        events.push(event);
+    })?;
+
+    let count = events.len();
+    for (module, tid) in &tracks {
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": tid,
+            "args": { "name": module },
+        }));
+    }
+
+    let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+    serde_json::to_writer(BufWriter::new(File::create(&args.output)?), &trace)?;
+
+    println!(
+        "(HOST) wrote {} events on {} tracks to {}",
+        count,
+        tracks.len(),
+        args.output.display()
+    );
+    if untimed > 0 {
+        println!(
+            "(HOST) {} frames without a usable timestamp left out, pass --tick-rate for timestamps in ticks",
+            untimed
+        );
+    }
+    if stats.malformed > 0 {
+        println!("(HOST) {} malformed frames skipped", stats.malformed);
+    }
+    for region in &stats.corrupted {
+        println!("{}", corrupted_warning(region));
+    }
+
+    Ok(())
+}
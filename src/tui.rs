@@ -0,0 +1,1131 @@
+//! `--tui`, the frames in a terminal UI with a pane per stimulus port or `--source`, each scrolled
+//! on its own, above the lines printed by the listener. `/` searches the frames of a pane, `m`
+//! marks them for `w` and `y` to export and `s` shows the statistics of the session.
+
+use anyhow::anyhow;
+use base64::Engine as _;
+use chrono::Local;
+use defmt_parser::Level;
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    crossterm::{
+        event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Sparkline},
+    Frame, Terminal,
+};
+use regex::Regex;
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    process::ExitCode,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    alarm, level_map, render_timestamp, sanitize, shutdown,
+    sinks::{Record, Sink},
+};
+
+/// Frames kept per pane, the oldest are dropped beyond
+const HISTORY: usize = 100_000;
+/// Lines of the listener kept
+const HOST_HISTORY: usize = 10_000;
+/// Rows of the lines of the listener, with the borders
+const HOST_HEIGHT: u16 = 6;
+/// Narrower panes are stacked rather than side by side
+const MIN_PANE_WIDTH: u16 = 60;
+const DRAW_INTERVAL: Duration = Duration::from_millis(50);
+/// Columns of the statistics beside the panes
+const STATS_WIDTH: u16 = 50;
+/// Seconds of the throughput graph
+const RATE_SECONDS: usize = 60;
+/// Log sites listed by the statistics
+const TOP_SITES: usize = 10;
+
+/// The events of `run`, for `viewer`
+static EVENTS: OnceLock<Sender<Event>> = OnceLock::new();
+
+enum Event {
+    /// A frame shown in the pane of this name, of this log site
+    Frame(String, Entry, String),
+    /// A malformed frame skipped
+    Malformed,
+    /// A line printed by the listener
+    Host(String),
+}
+
+/// A frame as shown in its pane.
+struct Entry {
+    timestamp: Option<String>,
+    level: Option<Level>,
+    message: String,
+}
+
+/// Takes the frames shown on the console, in place of it.
+pub struct Viewer {
+    tx: Sender<Event>,
+    quiet: bool,
+    timestamp: String,
+}
+
+/// The sink sending the frames to the UI of `run`.
+pub fn viewer(quiet: bool) -> Viewer {
+    let tx = EVENTS.get().expect("the listener runs in the TUI").clone();
+    Viewer {
+        tx,
+        quiet,
+        timestamp: String::new(),
+    }
+}
+
+impl Sink for Viewer {
+    fn frame(&mut self, record: &Record) {
+        let pane = match record.port {
+            Some(port) => format!("port {} {}", port, record.target),
+            None => record.label.unwrap_or(record.target).to_string(),
+        };
+        let site = match record.location {
+            (Some(file), Some(line), _) => format!("{}:{}", file, line),
+            _ => format!("index {:#06x}", record.frame.index()),
+        };
+        let entry = Entry {
+            timestamp: render_timestamp(record.frame, &mut self.timestamp).map(str::to_string),
+            level: level_map::level(record.frame, record.location.2),
+            message: plain(&sanitize::message(record.frame)),
+        };
+        // NOTE the UI may have been left already
+        let _ = self.tx.send(Event::Frame(pane, entry, site));
+    }
+
+    fn malformed(&mut self) {
+        let _ = self.tx.send(Event::Malformed);
+    }
+
+    fn alarm(&mut self, event: &alarm::Event, record: &Record) {
+        if self.quiet {
+            return;
+        }
+        let line = match event.tripped {
+            true => {
+                let message = sanitize::message(record.frame);
+                format!("{}(HOST) ALARM {}: {}", record.prefix, event, message)
+            }
+            false => format!("{}(HOST) alarm {} cleared", record.prefix, event),
+        };
+        let _ = self.tx.send(Event::Host(line));
+    }
+}
+
+/// Runs the `listener` in the background while the terminal shows its frames and what it prints,
+/// until `q` is pressed, a termination signal or the listener failing. What it printed is printed
+/// again once the terminal is back to normal.
+pub fn run(
+    listener: impl FnOnce() -> anyhow::Result<ExitCode> + Send + 'static,
+) -> anyhow::Result<ExitCode> {
+    let (tx, rx) = mpsc::channel();
+    EVENTS.set(tx.clone()).expect("run once");
+    let output = Output::capture(tx)?;
+    let mut listener = Some(thread::spawn(listener));
+
+    let mut app = App::default();
+    let screen = Screen::enter(&output.terminal)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(output.terminal.try_clone()?))?;
+    let result = app.run(&mut terminal, &rx, &mut listener);
+    drop(screen);
+
+    shutdown::request();
+    let exit_code = match listener.map(JoinHandle::join) {
+        Some(Ok(exit_code)) => exit_code,
+        Some(Err(_)) => Err(anyhow!("The listener thread panicked")),
+        None => result
+            .as_ref()
+            .map_or(Ok(ExitCode::SUCCESS), |&exit_code| Ok(exit_code)),
+    };
+    output.restore()?;
+    app.drain(&rx);
+    for line in &app.host {
+        println!("{}", line);
+    }
+    result.and(exit_code)
+}
+
+/// stdout and stderr redirected into a pipe, its lines sent to the UI drawn on the terminal.
+struct Output {
+    terminal: File,
+    stderr: File,
+    reader: JoinHandle<()>,
+}
+
+impl Output {
+    #[cfg(unix)]
+    fn capture(tx: Sender<Event>) -> anyhow::Result<Self> {
+        use std::{
+            io::{BufRead, BufReader, IsTerminal},
+            os::fd::{FromRawFd, OwnedFd},
+        };
+
+        if !io::stdout().is_terminal() {
+            return Err(anyhow!("--tui needs stdout to be a terminal"));
+        }
+        io::stdout().flush()?;
+        let mut pipe = [0; 2];
+        // SAFETY: plain libc calls, the descriptors they return are owned by the `File`s
+        let (terminal, stderr, reader) = unsafe {
+            if libc::pipe(pipe.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let terminal = File::from(OwnedFd::from_raw_fd(libc::dup(libc::STDOUT_FILENO)));
+            let stderr = File::from(OwnedFd::from_raw_fd(libc::dup(libc::STDERR_FILENO)));
+            let reader = File::from(OwnedFd::from_raw_fd(pipe[0]));
+            for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+                if libc::dup2(pipe[1], fd) < 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+            }
+            libc::close(pipe[1]);
+            (terminal, stderr, reader)
+        };
+
+        let reader = thread::spawn(move || {
+            for line in BufReader::new(reader).split(b'\n').map_while(Result::ok) {
+                let line = String::from_utf8_lossy(&line)
+                    .trim_end_matches('\r')
+                    .to_string();
+                if tx.send(Event::Host(line)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Output {
+            terminal,
+            stderr,
+            reader,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn capture(_: Sender<Event>) -> anyhow::Result<Self> {
+        Err(anyhow!("--tui is only supported on Unix"))
+    }
+
+    /// Puts stdout and stderr back, once the lines printed so far are read.
+    fn restore(self) -> io::Result<()> {
+        io::stdout().flush()?;
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            // SAFETY: both are open file descriptors, replacing the ends of the pipe
+            unsafe {
+                libc::dup2(self.terminal.as_raw_fd(), libc::STDOUT_FILENO);
+                libc::dup2(self.stderr.as_raw_fd(), libc::STDERR_FILENO);
+            }
+        }
+        // NOTE the pipe ends with its last writer
+        let _ = self.reader.join();
+        Ok(())
+    }
+}
+
+/// The raw mode and the alternate screen of the terminal, for as long as this exists.
+struct Screen {
+    terminal: File,
+}
+
+impl Screen {
+    fn enter(terminal: &File) -> io::Result<Self> {
+        let mut terminal = terminal.try_clone()?;
+        terminal::enable_raw_mode()?;
+        execute!(terminal, EnterAlternateScreen)?;
+        Ok(Screen { terminal })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.terminal, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The frames of a stimulus port or a `--source`.
+struct Pane {
+    name: String,
+    entries: VecDeque<Entry>,
+    /// Index of the first entry shown, `None` to follow the latest ones
+    top: Option<usize>,
+    /// Entries fitting in the pane when it was last drawn
+    rows: usize,
+    /// Index of the match of the search gone to with `n` and `N`
+    found: Option<usize>,
+    /// Entries dropped for `HISTORY`, for the marks to stay on theirs
+    dropped: usize,
+    /// The entries marked with `m`, counting the dropped ones
+    marks: BTreeSet<usize>,
+}
+
+impl Pane {
+    fn new(name: String) -> Self {
+        Pane {
+            name,
+            entries: VecDeque::new(),
+            top: None,
+            rows: 1,
+            found: None,
+            dropped: 0,
+            marks: BTreeSet::new(),
+        }
+    }
+
+    fn push(&mut self, entry: Entry) {
+        if self.entries.len() == HISTORY {
+            self.entries.pop_front();
+            self.top = self.top.map(|top| top.saturating_sub(1));
+            self.found = self.found.and_then(|found| found.checked_sub(1));
+            self.marks.remove(&self.dropped);
+            self.dropped += 1;
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Index of the first entry shown.
+    fn first(&self) -> usize {
+        let last_page = self.entries.len().saturating_sub(self.rows);
+        self.top.map_or(last_page, |top| top.min(last_page))
+    }
+
+    /// Indices of the entries shown.
+    fn shown(&self) -> Range<usize> {
+        let first = self.first();
+        first..(first + self.rows).min(self.entries.len())
+    }
+
+    /// Marks the match gone to if it's shown, else the bottom entry, or unmarks it.
+    fn toggle_mark(&mut self) {
+        let shown = self.shown();
+        let last = (!shown.is_empty()).then(|| shown.end - 1);
+        let Some(i) = self.found.filter(|found| shown.contains(found)).or(last) else {
+            return;
+        };
+        if !self.marks.remove(&(self.dropped + i)) {
+            self.marks.insert(self.dropped + i);
+        }
+    }
+
+    fn marked(&self, i: usize) -> bool {
+        self.marks.contains(&(self.dropped + i))
+    }
+
+    /// The entries from the first mark to the last one, or those shown if none is marked, as
+    /// lines of text.
+    fn excerpt(&self) -> (usize, String) {
+        let range = match (self.marks.first(), self.marks.last()) {
+            (Some(first), Some(last)) => first - self.dropped..last + 1 - self.dropped,
+            _ => self.shown(),
+        };
+        let mut text = String::new();
+        for entry in self.entries.range(range.clone()) {
+            let line = entry_line(entry, None, false);
+            text.extend(line.spans.iter().map(|span| span.content.as_ref()));
+            text.push('\n');
+        }
+        (range.len(), text)
+    }
+
+    fn scroll_up(&mut self, rows: usize) {
+        self.top = Some(self.first().saturating_sub(rows));
+    }
+
+    /// Scrolls down, following the latest entries again from the last page.
+    fn scroll_down(&mut self, rows: usize) {
+        let top = self.first() + rows;
+        self.top = (top + self.rows < self.entries.len()).then_some(top);
+    }
+
+    /// Goes to the match of `regex` after the one gone to, or before it unless `forward`. From the
+    /// entries shown, the first one forward and the last one backward. Returns whether it found
+    /// one.
+    fn find(&mut self, regex: &Regex, forward: bool) -> bool {
+        let first = self.first();
+        let matches = |&i: &usize| regex.is_match(&self.entries[i].message);
+        let found = match (forward, self.found) {
+            (true, Some(found)) => (found + 1..self.entries.len()).find(matches),
+            (true, None) => (first..self.entries.len()).find(matches),
+            (false, Some(found)) => (0..found).rev().find(matches),
+            (false, None) => (0..(first + self.rows).min(self.entries.len()))
+                .rev()
+                .find(matches),
+        };
+        let Some(found) = found else {
+            return false;
+        };
+        self.found = Some(found);
+        if !(first..first + self.rows).contains(&found) {
+            self.top = Some(found.saturating_sub(self.rows / 2));
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct App {
+    panes: Vec<Pane>,
+    /// The pane scrolled by the keys
+    focus: usize,
+    /// As printed, with their colors
+    host: VecDeque<String>,
+    /// Whether the listener has ended
+    ended: bool,
+    /// The regex being typed after `/`
+    input: Option<String>,
+    /// The regex searched, its matches highlighted
+    search: Option<Regex>,
+    /// Shown in place of the keys until the next one, e.g. that the search found nothing
+    status: Option<String>,
+    /// Text exported with `y`, for `run` to copy
+    clipboard: Option<String>,
+    stats: Stats,
+    /// Whether the statistics are shown, toggled with `s`
+    show_stats: bool,
+}
+
+impl App {
+    /// Draws the events until the UI is left. Takes the `listener` once it ended, ending the UI
+    /// right away if it failed.
+    fn run(
+        &mut self,
+        terminal: &mut Terminal<impl Backend + Write>,
+        rx: &Receiver<Event>,
+        listener: &mut Option<JoinHandle<anyhow::Result<ExitCode>>>,
+    ) -> anyhow::Result<ExitCode> {
+        let mut exit_code = None;
+        loop {
+            self.stats.tick(Instant::now());
+            self.drain(rx);
+            if listener.as_ref().is_some_and(JoinHandle::is_finished) {
+                let joined = listener.take().expect("finished").join();
+                let result = joined.map_err(|_| anyhow!("The listener thread panicked"))?;
+                exit_code = Some(result?);
+                self.ended = true;
+            }
+            if let (Some(exit_code), true) = (exit_code, shutdown::requested()) {
+                return Ok(exit_code);
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(DRAW_INTERVAL)? {
+                continue;
+            }
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.key(key) {
+                    return Ok(exit_code.unwrap_or(ExitCode::SUCCESS));
+                }
+            }
+            if let Some(text) = self.clipboard.take() {
+                // OSC 52, copying to the clipboard of the terminal, also over SSH
+                let text = base64::engine::general_purpose::STANDARD.encode(text);
+                write!(terminal.backend_mut(), "\x1b]52;c;{}\x07", text)?;
+                Write::flush(terminal.backend_mut())?;
+            }
+        }
+    }
+
+    fn drain(&mut self, rx: &Receiver<Event>) {
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::Frame(name, entry, site) => {
+                    self.stats.frame(entry.level, site);
+                    let i = match self.panes.iter().position(|pane| pane.name == name) {
+                        Some(i) => i,
+                        None => {
+                            self.panes.push(Pane::new(name));
+                            self.panes.len() - 1
+                        }
+                    };
+                    self.panes[i].push(entry);
+                }
+                Event::Malformed => self.stats.malformed += 1,
+                Event::Host(line) => self.host_line(line),
+            }
+        }
+    }
+
+    fn host_line(&mut self, line: String) {
+        if self.host.len() == HOST_HISTORY {
+            self.host.pop_front();
+        }
+        self.host.push_back(line);
+    }
+
+    /// Handles `key`, returns whether the UI goes on.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => self.search(),
+                KeyCode::Esc => self.input = None,
+                _ => {}
+            }
+            return true;
+        }
+        self.status = None;
+        let count = self.panes.len().max(1);
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('s') => self.show_stats = !self.show_stats,
+            KeyCode::Char('n') => self.find(true),
+            KeyCode::Char('N') => self.find(false),
+            KeyCode::Esc => {
+                self.search = None;
+                self.panes.iter_mut().for_each(|pane| pane.found = None);
+            }
+            KeyCode::Tab => self.focus = (self.focus + 1) % count,
+            KeyCode::BackTab => self.focus = (self.focus + count - 1) % count,
+            _ => {}
+        }
+        let Some(pane) = self.panes.get_mut(self.focus) else {
+            return true;
+        };
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => pane.scroll_up(1),
+            KeyCode::Down | KeyCode::Char('j') => pane.scroll_down(1),
+            KeyCode::PageUp => pane.scroll_up(pane.rows),
+            KeyCode::PageDown => pane.scroll_down(pane.rows),
+            KeyCode::Home | KeyCode::Char('g') => pane.top = Some(0),
+            KeyCode::End | KeyCode::Char('G') => pane.top = None,
+            KeyCode::Char('m') => pane.toggle_mark(),
+            KeyCode::Char('M') => pane.marks.clear(),
+            KeyCode::Char('w') => {
+                let (count, text) = pane.excerpt();
+                let path = export_path(&pane.name);
+                self.status = Some(match fs::write(&path, text) {
+                    Ok(()) => format!("wrote {} frames to {}", count, path),
+                    Err(err) => format!("can't write {}: {}", path, err),
+                });
+            }
+            KeyCode::Char('y') => {
+                let (count, text) = pane.excerpt();
+                self.clipboard = Some(text);
+                self.status = Some(format!("copied {} frames to the clipboard", count));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Searches the regex typed, from the bottom of the focused pane up. An empty one searches the
+    /// last one again.
+    fn search(&mut self) {
+        let input = self.input.take().unwrap_or_default();
+        if !input.is_empty() {
+            match Regex::new(&input) {
+                Ok(regex) => self.search = Some(regex),
+                Err(err) => {
+                    let err = err.to_string();
+                    let err = err.lines().last().unwrap_or_default().trim();
+                    self.status = Some(format!("invalid regex: {}", err));
+                    return;
+                }
+            }
+        }
+        self.panes.iter_mut().for_each(|pane| pane.found = None);
+        self.find(false);
+    }
+
+    /// Goes to the next match of the search in the focused pane, or the previous one unless
+    /// `forward`.
+    fn find(&mut self, forward: bool) {
+        let (Some(regex), Some(pane)) = (&self.search, self.panes.get_mut(self.focus)) else {
+            return;
+        };
+        if !pane.find(regex, forward) {
+            let end = match forward {
+                true => "bottom",
+                false => "top",
+            };
+            self.status = Some(format!("no match for /{} up to the {}", regex, end));
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [panes, host, help] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(HOST_HEIGHT),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let panes = match self.show_stats {
+            true => {
+                let [panes, stats] =
+                    Layout::horizontal([Constraint::Min(0), Constraint::Length(STATS_WIDTH)])
+                        .areas(panes);
+                self.stats.draw(frame, stats);
+                panes
+            }
+            false => panes,
+        };
+
+        if self.panes.is_empty() {
+            let block = Block::bordered().title(" waiting for frames ");
+            frame.render_widget(block, panes);
+        }
+        let count = self.panes.len().max(1) as u16;
+        let direction = match panes.width / count >= MIN_PANE_WIDTH {
+            true => Direction::Horizontal,
+            false => Direction::Vertical,
+        };
+        let areas = Layout::default()
+            .direction(direction)
+            .constraints(
+                self.panes
+                    .iter()
+                    .map(|_| Constraint::Ratio(1, count as u32)),
+            )
+            .split(panes);
+        for (i, (pane, &area)) in self.panes.iter_mut().zip(areas.iter()).enumerate() {
+            draw_pane(frame, pane, area, i == self.focus, self.search.as_ref());
+        }
+
+        let rows = host.height.saturating_sub(2) as usize;
+        let lines = (self.host.iter().skip(self.host.len().saturating_sub(rows)))
+            .map(|line| Line::raw(plain(line)))
+            .collect::<Vec<_>>();
+        let block = Block::bordered().title(" listener ");
+        frame.render_widget(Paragraph::new(lines).block(block), host);
+
+        let keys = match (&self.input, &self.status, self.ended) {
+            (Some(input), _, _) => format!("/{}", input),
+            (None, Some(status), _) => format!(" {}", status),
+            (None, None, true) => " the session ended, q quits".to_string(),
+            (None, None, false) => {
+                " Tab pane  ↑↓ PgUp PgDn Home End scroll  / search  n N next previous  m mark  \
+                 w file  y copy  s stats  q quit"
+                    .to_string()
+            }
+        };
+        let style = Style::default().add_modifier(Modifier::REVERSED);
+        frame.render_widget(Paragraph::new(keys).style(style), help);
+    }
+}
+
+/// The counters of the statistics, of all the panes.
+#[derive(Default)]
+struct Stats {
+    /// Frames per level from trace to error, then those of `println`
+    levels: [u64; 6],
+    malformed: u64,
+    /// Frames per log site
+    sites: HashMap<String, u64>,
+    /// Frames per second over the last `RATE_SECONDS`, the current second last
+    rate: VecDeque<u64>,
+    /// When the current second started
+    second: Option<Instant>,
+}
+
+impl Stats {
+    fn frame(&mut self, level: Option<Level>, site: String) {
+        let i = match level {
+            Some(Level::Trace) => 0,
+            Some(Level::Debug) => 1,
+            Some(Level::Info) => 2,
+            Some(Level::Warn) => 3,
+            Some(Level::Error) => 4,
+            None => 5,
+        };
+        self.levels[i] += 1;
+        *self.sites.entry(site).or_default() += 1;
+        match self.rate.back_mut() {
+            Some(count) => *count += 1,
+            None => self.rate.push_back(1),
+        }
+    }
+
+    /// Moves on to the second of `now`, the frames counted in it from now on.
+    fn tick(&mut self, now: Instant) {
+        let second = *self.second.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(second).as_secs();
+        if self.rate.is_empty() {
+            self.rate.push_back(0);
+        }
+        for _ in 0..elapsed.min(RATE_SECONDS as u64) {
+            self.rate.push_back(0);
+        }
+        while self.rate.len() > RATE_SECONDS {
+            self.rate.pop_front();
+        }
+        self.second = Some(second + Duration::from_secs(elapsed));
+    }
+
+    fn draw(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" stats ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [counters, rate, top] = Layout::vertical([
+            Constraint::Length(8),
+            Constraint::Length(4),
+            Constraint::Min(0),
+        ])
+        .areas(inner);
+
+        let levels = [
+            Level::Trace,
+            Level::Debug,
+            Level::Info,
+            Level::Warn,
+            Level::Error,
+        ];
+        let mut lines = (levels.iter().zip(self.levels))
+            .map(|(&level, count)| {
+                let name = format!("{:<10}", level.as_str().to_uppercase());
+                Line::from(vec![
+                    Span::styled(name, level_style(level)),
+                    Span::raw(format!("{:>10}", count)),
+                ])
+            })
+            .collect::<Vec<_>>();
+        lines.push(Line::raw(format!(
+            "{:<10}{:>10}",
+            "println", self.levels[5]
+        )));
+        let style = match self.malformed {
+            0 => Style::default(),
+            _ => Style::default().fg(Color::Red),
+        };
+        let malformed = format!("{:<10}{:>10}", "malformed", self.malformed);
+        lines.push(Line::styled(malformed, style));
+        frame.render_widget(Paragraph::new(lines), counters);
+
+        let data = self.rate.iter().copied().collect::<Vec<_>>();
+        let (last, max) = match self.rate.len() {
+            // NOTE the current second is still being counted
+            len @ 2.. => (self.rate[len - 2], data.iter().max().copied().unwrap_or(0)),
+            _ => (0, 0),
+        };
+        let title = format!("frames/s {} (max {}), last {}s", last, max, RATE_SECONDS);
+        let sparkline = Sparkline::default()
+            .block(Block::default().title(title))
+            .data(&data)
+            .style(Style::default().fg(Color::Cyan));
+        frame.render_widget(sparkline, rate);
+
+        let mut sites = self.sites.iter().collect::<Vec<_>>();
+        sites.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let mut lines = vec![Line::styled(
+            "top log sites",
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        lines.extend(
+            (sites.iter().take(TOP_SITES))
+                .map(|(site, count)| Line::raw(format!("{:>8} {}", count, site))),
+        );
+        frame.render_widget(Paragraph::new(lines), top);
+    }
+}
+
+fn draw_pane(
+    frame: &mut Frame,
+    pane: &mut Pane,
+    area: Rect,
+    focused: bool,
+    search: Option<&Regex>,
+) {
+    pane.rows = area.height.saturating_sub(2).max(1) as usize;
+    let first = pane.first();
+    let lines = (pane.entries.iter().enumerate().skip(first).take(pane.rows))
+        .map(|(i, entry)| {
+            let line = entry_line(entry, search, pane.found == Some(i));
+            match pane.marked(i) {
+                true => line.patch_style(Style::default().bg(Color::DarkGray)),
+                false => line,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut title = format!(" {} · {} frames ", pane.name, pane.entries.len());
+    if !pane.marks.is_empty() {
+        title += &format!("· {} marked ", pane.marks.len());
+    }
+    if pane.top.is_some() {
+        title += "· scrolled, End follows ";
+    }
+    let border = match focused {
+        true => Style::default().fg(Color::Cyan),
+        false => Style::default(),
+    };
+    let block = Block::bordered().title(title).border_style(border);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// The row of `entry`, its timestamp and level in front of the message like on the console, the
+/// matches of `search` highlighted, the whole row if it's the match `found`.
+fn entry_line<'e>(entry: &'e Entry, search: Option<&Regex>, found: bool) -> Line<'e> {
+    let mut spans = Vec::with_capacity(4);
+    if let Some(timestamp) = &entry.timestamp {
+        spans.push(Span::raw(format!("{} ", timestamp)));
+    }
+    if let Some(level) = entry.level {
+        let text = format!("{:<5} ", level.as_str().to_uppercase());
+        spans.push(Span::styled(text, level_style(level)));
+    }
+    let message = entry.message.as_str();
+    let mut end = 0;
+    for found in search
+        .into_iter()
+        .flat_map(|regex| regex.find_iter(message))
+    {
+        let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+        spans.push(Span::raw(&message[end..found.start()]));
+        spans.push(Span::styled(found.as_str(), style));
+        end = found.end();
+    }
+    spans.push(Span::raw(&message[end..]));
+
+    let line = Line::from(spans);
+    match found {
+        true => line.style(Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)),
+        false => line,
+    }
+}
+
+/// A new file in the current directory for the excerpt of the pane `name`, e.g.
+/// `defmt-port-0-app-20240102-150405.log`, numbered after the first one of the second.
+fn export_path(name: &str) -> String {
+    let name = name.replace(|c: char| !c.is_alphanumeric(), "-");
+    let stem = format!("defmt-{}-{}", name, Local::now().format("%Y%m%d-%H%M%S"));
+    (1..)
+        .map(|n| match n {
+            1 => format!("{}.log", stem),
+            n => format!("{}-{}.log", stem, n),
+        })
+        .find(|path| !Path::new(path).exists())
+        .expect("a free name")
+}
+
+/// The colors of the levels of defmt-decoder.
+fn level_style(level: Level) -> Style {
+    match level {
+        Level::Trace => Style::default().add_modifier(Modifier::DIM),
+        Level::Debug => Style::default(),
+        Level::Info => Style::default().fg(Color::Green),
+        Level::Warn => Style::default().fg(Color::Yellow),
+        Level::Error => Style::default().fg(Color::Red),
+    }
+}
+
+/// `text` without the escape sequences and control characters that would garble the UI, its line
+/// breaks shown as `⏎`.
+fn plain(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    for c in sanitize::strip(text).chars() {
+        match c {
+            '\t' => plain.push_str("    "),
+            '\n' => plain.push_str(" ⏎ "),
+            c if c.is_control() => plain.push(char::REPLACEMENT_CHARACTER),
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    fn entry(message: &str) -> Entry {
+        Entry {
+            timestamp: None,
+            level: Some(Level::Info),
+            message: message.to_string(),
+        }
+    }
+
+    fn app(panes: &[(&str, usize)]) -> App {
+        let (tx, rx) = mpsc::channel();
+        for &(name, count) in panes {
+            for i in 0..count {
+                let entry = entry(&format!("{} {}", name, i));
+                let site = format!("src/{}.rs:{}", name, i % 3);
+                tx.send(Event::Frame(name.to_string(), entry, site))
+                    .unwrap();
+            }
+        }
+        tx.send(Event::Host("(HOST) listening".to_string()))
+            .unwrap();
+        tx.send(Event::Malformed).unwrap();
+        let mut app = App::default();
+        app.drain(&rx);
+        app
+    }
+
+    /// The rows of the screen of `app`, drawn `width` by `height`.
+    fn draw(app: &mut App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    fn key(app: &mut App, code: KeyCode) -> bool {
+        app.key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn panes_per_name() {
+        let app = app(&[("port 0 app", 3), ("port 1 boot", 2)]);
+        let names = app.panes.iter().map(|pane| pane.name.as_str());
+        assert_eq!(names.collect::<Vec<_>>(), ["port 0 app", "port 1 boot"]);
+        assert_eq!(app.panes[0].entries.len(), 3);
+        assert_eq!(app.host, ["(HOST) listening"]);
+    }
+
+    #[test]
+    fn draws_the_latest_frames() {
+        let mut app = app(&[("app", 20)]);
+        let screen = draw(&mut app, 80, 20).join("\n");
+        // 11 rows in the pane, above the 6 of the listener and the help
+        assert!(screen.contains("app · 20 frames"));
+        assert!(screen.contains("INFO  app 19"));
+        assert!(screen.contains("INFO  app 9"));
+        assert!(!screen.contains("app 8"));
+        assert!(screen.contains("(HOST) listening"));
+    }
+
+    #[test]
+    fn panes_side_by_side_or_stacked() {
+        let mut app = app(&[("a", 1), ("b", 1)]);
+        let wide = draw(&mut app, 120, 20);
+        assert!(wide[0].contains(" a · 1 frames") && wide[0].contains(" b · 1 frames"));
+        let narrow = draw(&mut app, 100, 20);
+        assert!(narrow[0].contains(" a · 1 frames") && !narrow[0].contains(" b "));
+    }
+
+    #[test]
+    fn scrolls_the_focused_pane() {
+        let mut app = app(&[("a", 30), ("b", 30)]);
+        draw(&mut app, 60, 20);
+        let rows = app.panes[0].rows;
+        assert_eq!(app.panes[0].first(), 30 - rows);
+
+        key(&mut app, KeyCode::Up);
+        assert_eq!(app.panes[0].top, Some(29 - rows));
+        key(&mut app, KeyCode::Home);
+        assert_eq!(app.panes[0].first(), 0);
+        key(&mut app, KeyCode::PageDown);
+        assert_eq!(app.panes[0].first(), rows);
+        // the pane stays where it is as frames come
+        app.panes[0].push(entry("new"));
+        assert_eq!(app.panes[0].first(), rows);
+        assert_eq!(app.panes[1].top, None);
+
+        key(&mut app, KeyCode::Tab);
+        key(&mut app, KeyCode::Char('k'));
+        assert_eq!(app.panes[1].top, Some(29 - app.panes[1].rows));
+        key(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.panes[1].top, None);
+        key(&mut app, KeyCode::BackTab);
+        key(&mut app, KeyCode::End);
+        assert_eq!(app.panes[0].top, None);
+        assert!(!key(&mut app, KeyCode::Char('q')));
+    }
+
+    fn search(app: &mut App, regex: &str) {
+        key(app, KeyCode::Char('/'));
+        for c in regex.chars() {
+            key(app, KeyCode::Char(c));
+        }
+        key(app, KeyCode::Enter);
+    }
+
+    #[test]
+    fn searches_the_focused_pane() {
+        let mut app = app(&[("a", 100)]);
+        draw(&mut app, 80, 20);
+        let rows = app.panes[0].rows;
+
+        // from the bottom up, `q` typed into the regex rather than quitting
+        search(&mut app, "a [1-3]q?0$");
+        assert_eq!(app.panes[0].found, Some(30));
+        assert_eq!(app.panes[0].first(), 30 - rows / 2);
+        key(&mut app, KeyCode::Char('N'));
+        assert_eq!(app.panes[0].found, Some(20));
+        key(&mut app, KeyCode::Char('n'));
+        key(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.panes[0].found, Some(30));
+        key(&mut app, KeyCode::Char('n'));
+        assert_eq!(app.panes[0].found, Some(30));
+        assert!(app.status.as_ref().unwrap().contains("no match"));
+
+        // the last regex again
+        key(&mut app, KeyCode::End);
+        search(&mut app, "");
+        assert_eq!(app.panes[0].found, Some(30));
+
+        let screen = draw(&mut app, 80, 20).join("\n");
+        assert!(screen.contains("INFO  a 30"));
+        key(&mut app, KeyCode::Esc);
+        assert!(app.search.is_none() && app.panes[0].found.is_none());
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let mut app = app(&[("a", 1)]);
+        search(&mut app, "a(");
+        assert!(app.status.as_ref().unwrap().starts_with("invalid regex:"));
+        assert!(app.search.is_none());
+        // the status is shown until the next key
+        key(&mut app, KeyCode::Down);
+        assert!(app.status.is_none());
+    }
+
+    #[test]
+    fn highlights_the_matches() {
+        let regex = Regex::new("b+").unwrap();
+        let entry = entry("abba b");
+        let line = entry_line(&entry, Some(&regex), false);
+        let spans = line.spans.iter().map(|span| span.content.as_ref());
+        assert_eq!(
+            spans.collect::<Vec<_>>(),
+            ["INFO  ", "a", "bb", "a ", "b", ""]
+        );
+        assert_eq!(line.spans[2].style.bg, Some(Color::Yellow));
+        assert_eq!(line.spans[1].style.bg, None);
+    }
+
+    #[test]
+    fn exports_the_marked_range_or_the_frames_shown() {
+        let mut app = app(&[("a", 50)]);
+        draw(&mut app, 80, 20);
+        let rows = app.panes[0].rows;
+        let (count, text) = app.panes[0].excerpt();
+        assert_eq!(count, rows);
+        assert!(text.starts_with(&format!("INFO  a {}\n", 50 - rows)));
+        assert!(text.ends_with("INFO  a 49\n"));
+
+        // the bottom frame, then the match of the search
+        key(&mut app, KeyCode::Char('m'));
+        search(&mut app, "a 40$");
+        key(&mut app, KeyCode::Char('m'));
+        assert!(app.panes[0].marked(49) && app.panes[0].marked(40));
+        let (count, text) = app.panes[0].excerpt();
+        assert_eq!(count, 10);
+        assert_eq!(text.lines().next(), Some("INFO  a 40"));
+        key(&mut app, KeyCode::Char('m'));
+        assert!(!app.panes[0].marked(40));
+        assert_eq!(app.panes[0].excerpt().0, 1);
+
+        key(&mut app, KeyCode::Char('y'));
+        assert_eq!(app.clipboard.as_deref(), Some("INFO  a 49\n"));
+        key(&mut app, KeyCode::Char('M'));
+        assert!(app.panes[0].marks.is_empty());
+    }
+
+    #[test]
+    fn marks_stay_on_their_frames() {
+        let mut pane = Pane::new("a".to_string());
+        for i in 0..HISTORY {
+            pane.push(entry(&i.to_string()));
+        }
+        pane.marks.extend([0, 5]);
+        pane.push(entry("last"));
+        pane.push(entry("last"));
+        assert_eq!(pane.marks.len(), 1);
+        assert!(pane.marked(3));
+        assert_eq!(pane.excerpt().1, "INFO  5\n");
+    }
+
+    #[test]
+    fn export_paths() {
+        let path = export_path("port 0 app");
+        assert!(path.starts_with("defmt-port-0-app-2"));
+        assert!(path.ends_with(".log"));
+    }
+
+    #[test]
+    fn counts_the_frames() {
+        let mut app = app(&[("a", 4), ("b", 3)]);
+        app.stats
+            .frame(Some(Level::Error), "src/a.rs:0".to_string());
+        app.stats.frame(None, "src/b.rs:9".to_string());
+        assert_eq!(app.stats.levels, [0, 0, 7, 0, 1, 1]);
+        assert_eq!(app.stats.malformed, 1);
+        assert_eq!(app.stats.sites["src/a.rs:0"], 3);
+        assert_eq!(app.stats.sites["src/b.rs:1"], 1);
+
+        key(&mut app, KeyCode::Char('s'));
+        let screen = draw(&mut app, 120, 30).join("\n");
+        assert!(screen.contains("INFO               7"));
+        assert!(screen.contains("malformed          1"));
+        // by count, then by name
+        let position = |site| screen.find(site).unwrap();
+        assert!(position("       3 src/a.rs:0") < position("       1 src/a.rs:1"));
+        assert!(position("       1 src/a.rs:1") < position("       1 src/b.rs:0"));
+        key(&mut app, KeyCode::Char('s'));
+        assert!(!draw(&mut app, 120, 30).join("\n").contains("top log sites"));
+    }
+
+    #[test]
+    fn frames_per_second() {
+        let mut stats = Stats::default();
+        let start = Instant::now();
+        stats.tick(start);
+        stats.frame(None, "a".to_string());
+        stats.tick(start + Duration::from_millis(900));
+        stats.frame(None, "a".to_string());
+        stats.tick(start + Duration::from_millis(2500));
+        stats.frame(None, "a".to_string());
+        assert_eq!(stats.rate, [2, 0, 1]);
+
+        stats.tick(start + Duration::from_secs(1000));
+        assert_eq!(stats.rate.len(), RATE_SECONDS);
+        assert!(stats.rate.iter().all(|&count| count == 0));
+        // the seconds are counted from the first tick
+        stats.tick(start + Duration::from_millis(1_000_999));
+        stats.frame(None, "a".to_string());
+        assert_eq!(stats.rate.back(), Some(&1));
+        assert_eq!(stats.rate[RATE_SECONDS - 2], 0);
+    }
+
+    #[test]
+    fn history_drops_the_oldest() {
+        let mut pane = Pane::new("a".to_string());
+        for i in 0..HISTORY {
+            pane.push(entry(&i.to_string()));
+        }
+        pane.top = Some(10);
+        pane.push(entry("last"));
+        assert_eq!(pane.entries.len(), HISTORY);
+        assert_eq!(pane.entries[0].message, "1");
+        assert_eq!(pane.top, Some(9));
+    }
+
+    #[test]
+    fn plain_messages() {
+        assert_eq!(plain("a\tb"), "a    b");
+        assert_eq!(plain("a\nb"), "a ⏎ b");
+        assert_eq!(plain("\x1b[31mred\x1b[0m \x07"), "red �");
+    }
+}
@@ -0,0 +1,205 @@
+//! `--webhook` alerts, POSTed from a thread so a slow server doesn't hold up decoding.
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    alarm::Event,
+    filter::host_level,
+    level_map, sanitize,
+    sinks::{Record, Sink},
+};
+
+/// Alerts arriving this soon after the first one are sent in the same request
+const BATCH_WINDOW: Duration = Duration::from_secs(1);
+const MAX_BATCH: usize = 100;
+/// Attempts per batch, waiting twice as long after each failure
+const ATTEMPTS: u32 = 4;
+const FIRST_RETRY: Duration = Duration::from_millis(500);
+/// Frames before an alert sent along with it
+const CONTEXT_LINES: usize = 5;
+/// Longest message Discord accepts
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// Body of the `--webhook` requests.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"source": ..., "alerts": [...]}`
+    Json,
+    /// A Slack incoming webhook message
+    Slack,
+    /// A Discord webhook message
+    Discord,
+}
+
+pub struct Webhook {
+    level: log::Level,
+    /// The last `CONTEXT_LINES` frames
+    recent: VecDeque<String>,
+    sender: Sender<Value>,
+    thread: JoinHandle<()>,
+}
+
+impl Webhook {
+    /// Sends frames of `level` or more severe to `url`.
+    pub fn new(url: String, format: WebhookFormat, level: log::Level) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || post_batches(&url, format, receiver));
+        Webhook {
+            level,
+            recent: VecDeque::with_capacity(CONTEXT_LINES),
+            sender,
+            thread,
+        }
+    }
+}
+
+impl Sink for Webhook {
+    /// Takes every frame, to send the alerts along with the frames before them.
+    fn frame(&mut self, record: &Record) {
+        let frame = record.frame;
+        let level = level_map::level(frame, record.location.2);
+        if level.is_some_and(|level| host_level(level) <= self.level) {
+            let (file, line, module) = record.location;
+            let _ = self.sender.send(json!({
+                "level": level.map(|level| level.as_str()),
+                "message": sanitize::message(frame),
+                "target": record.target,
+                "label": record.label,
+                "timestamp": frame.display_timestamp().map(|timestamp| timestamp.to_string()),
+                "host_time": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+                "file": file,
+                "line": line,
+                "module": module,
+                "context": self.recent,
+            }));
+        }
+
+        let level = level
+            .map(|level| level.as_str().to_uppercase())
+            .unwrap_or_default();
+        if self.recent.len() == CONTEXT_LINES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(
+            format!("{:<5} {}", level, sanitize::message(frame))
+                .trim_start()
+                .to_string(),
+        );
+    }
+
+    /// Alerts the tripped alarms only.
+    fn alarm(&mut self, event: &Event, record: &Record) {
+        if !event.tripped {
+            return;
+        }
+        let _ = self.sender.send(json!({
+            "level": "alarm",
+            "message": format!("{}: {}", event, sanitize::message(record.frame)),
+            "alarm": event.to_string(),
+            "target": record.target,
+            "label": record.label,
+            "host_time": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            "context": self.recent,
+        }));
+    }
+
+    /// Sends the pending alerts before returning.
+    fn finish(self: Box<Self>) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
+
+fn post_batches(url: &str, format: WebhookFormat, receiver: Receiver<Value>) {
+    // ends once the sender is dropped and everything is sent
+    while let Ok(first) = receiver.recv() {
+        let mut alerts = vec![first];
+        let deadline = Instant::now() + BATCH_WINDOW;
+        while alerts.len() < MAX_BATCH {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(alert) => alerts.push(alert),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let count = alerts.len();
+        let body = body(format, alerts).to_string();
+        let mut retry = FIRST_RETRY;
+        for attempt in 1..=ATTEMPTS {
+            match ureq::post(url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+            {
+                Ok(_) => break,
+                Err(err) if attempt == ATTEMPTS => {
+                    println!("(HOST) webhook failed, {} alerts dropped: {}", count, err)
+                }
+                Err(_) => {
+                    thread::sleep(retry);
+                    retry *= 2;
+                }
+            }
+        }
+    }
+}
+
+fn body(format: WebhookFormat, alerts: Vec<Value>) -> Value {
+    match format {
+        WebhookFormat::Json => json!({ "source": env!("CARGO_PKG_NAME"), "alerts": alerts }),
+        WebhookFormat::Slack => json!({ "text": chat_message(&alerts, "*") }),
+        WebhookFormat::Discord => {
+            let mut content = chat_message(&alerts, "**");
+            if content.chars().count() > DISCORD_MAX_CHARS {
+                content = content
+                    .chars()
+                    .take(DISCORD_MAX_CHARS - 3)
+                    .collect::<String>()
+                    + "...";
+            }
+            json!({ "content": content })
+        }
+    }
+}
+
+/// The Markdown of Slack and Discord, which differ in how text is made `bold`: per alert the level
+/// and message, the location and target, and the frames before it in a code block.
+fn chat_message(alerts: &[Value], bold: &str) -> String {
+    alerts
+        .iter()
+        .map(|alert| {
+            let mut text = format!(
+                "{bold}{}{bold} {}\n",
+                alert["level"].as_str().unwrap_or_default().to_uppercase(),
+                alert["message"].as_str().unwrap_or_default()
+            );
+            if let (Some(file), Some(line)) = (alert["file"].as_str(), alert["line"].as_u64()) {
+                text += &format!("`{}:{}` on ", file, line);
+            }
+            text += alert["target"].as_str().unwrap_or_default();
+            if let Some(label) = alert["label"].as_str() {
+                text += &format!(" of {}", label);
+            }
+
+            let context = alert["context"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>();
+            if !context.is_empty() {
+                // NOTE a fence in a message would end the block early
+                let context = context.join("\n").replace("```", "'''");
+                text += &format!("\n```\n{}\n```", context);
+            }
+            text
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,189 @@
+//! `--from` and `--to`, the window of a capture a replay or `decode` is limited to.
+
+use chrono::DateTime;
+
+use crate::{capture::IndexEntry, replay};
+
+/// A bound of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bound {
+    /// Seconds since the Unix epoch, compared to the host times of timestamped captures
+    Host(f64),
+    /// A device timestamp as printed, e.g. `12.5`, `00:00:12.500000` or `12500` ticks
+    Device(String),
+}
+
+/// Parses an RFC 3339 host time like `2024-05-01T12:00:00Z`, or a device timestamp.
+pub fn parse_bound(text: &str) -> Result<Bound, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(Bound::Host(time.timestamp_micros() as f64 / 1e6));
+    }
+    match device_time(text, None) {
+        Some(_) => Ok(Bound::Device(text.to_string())),
+        None => Err(format!(
+            "invalid time `{}`, expected a host time like `2024-05-01T12:00:00Z` or a device timestamp like `12.5`",
+            text
+        )),
+    }
+}
+
+/// Device time in seconds, or in ticks for timestamps in ticks without a tick rate.
+pub fn device_time(timestamp: &str, tick_rate: Option<f64>) -> Option<f64> {
+    replay::parse_timestamp(timestamp, tick_rate)
+        .or_else(|| timestamp.parse::<u64>().ok().map(|ticks| ticks as f64))
+}
+
+enum Resolved {
+    Host(f64),
+    Device(f64),
+}
+
+impl Resolved {
+    fn new(bound: &Bound, tick_rate: Option<f64>) -> Self {
+        match bound {
+            Bound::Host(time) => Resolved::Host(*time),
+            Bound::Device(timestamp) => {
+                Resolved::Device(device_time(timestamp, tick_rate).expect("checked when parsed"))
+            }
+        }
+    }
+
+    /// The time of the frame compared to the bound, `None` if the frame has no such time.
+    fn time(&self, host: Option<f64>, device: Option<f64>) -> Option<(f64, f64)> {
+        match *self {
+            Resolved::Host(bound) => host.map(|time| (time, bound)),
+            Resolved::Device(bound) => device.map(|time| (time, bound)),
+        }
+    }
+}
+
+/// Tells the frames inside the window. It opens at the first frame at or after `--from` and closes
+/// after the last one up to `--to`, the frames without a comparable time don't move it.
+pub struct Window {
+    from: Option<Resolved>,
+    to: Option<Resolved>,
+    tick_rate: Option<f64>,
+    started: bool,
+    ended: bool,
+}
+
+impl Window {
+    pub fn new(from: Option<&Bound>, to: Option<&Bound>, tick_rate: Option<f64>) -> Option<Self> {
+        if from.is_none() && to.is_none() {
+            return None;
+        }
+        Some(Window {
+            from: from.map(|bound| Resolved::new(bound, tick_rate)),
+            to: to.map(|bound| Resolved::new(bound, tick_rate)),
+            tick_rate,
+            started: from.is_none(),
+            ended: false,
+        })
+    }
+
+    /// Whether a bound is a host time, which raw captures don't have.
+    pub fn needs_host_time(&self) -> bool {
+        [&self.from, &self.to]
+            .into_iter()
+            .flatten()
+            .any(|bound| matches!(bound, Resolved::Host(_)))
+    }
+
+    /// Whether the frame received at `host` time in seconds since the Unix epoch, with the device
+    /// `timestamp`, is inside the window.
+    pub fn frame(&mut self, host: Option<f64>, timestamp: Option<&str>) -> bool {
+        let device = timestamp.and_then(|ts| device_time(ts, self.tick_rate));
+        if !self.started {
+            let from = self.from.as_ref().and_then(|from| from.time(host, device));
+            self.started = from.is_some_and(|(time, from)| time >= from);
+        }
+        if self.started && !self.ended {
+            let to = self.to.as_ref().and_then(|to| to.time(host, device));
+            self.ended = to.is_some_and(|(time, to)| time > to);
+        }
+        self.started && !self.ended
+    }
+
+    /// The offset of the last entry of a `--capture-index` before the window opens, to start
+    /// decoding there, `None` for the start of the capture. Exact, the entries hold the latest
+    /// device time since the entry before, across reboots.
+    pub fn seek(&self, index: &[IndexEntry]) -> Option<u64> {
+        let from = self.from.as_ref()?;
+        // the window opens in the part of the capture up to the first entry past `--from`
+        let past = index
+            .iter()
+            .position(|entry| {
+                let host = Some(entry.host_time as f64 / 1e6);
+                let device =
+                    (entry.timestamp.as_deref()).and_then(|ts| device_time(ts, self.tick_rate));
+                from.time(host, device)
+                    .is_some_and(|(time, from)| time >= from)
+            })
+            .unwrap_or(index.len());
+        past.checked_sub(1).map(|entry| index[entry].offset)
+    }
+
+    /// Whether the window closed, the rest of the capture can be skipped.
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entries at offsets 0, 100, ..., each received a second after the one before and holding
+    /// the given device timestamps.
+    fn index(timestamps: &[Option<&str>]) -> Vec<IndexEntry> {
+        (timestamps.iter().enumerate())
+            .map(|(i, timestamp)| IndexEntry {
+                offset: i as u64 * 100,
+                host_time: (1_700_000_000 + i as u64) * 1_000_000,
+                timestamp: timestamp.map(str::to_string),
+            })
+            .collect()
+    }
+
+    fn from(bound: Bound) -> Window {
+        Window::new(Some(&bound), None, None).expect("a bound")
+    }
+
+    #[test]
+    fn seek_to_the_entry_before_the_window() {
+        let index = index(&[Some("1.0"), Some("2.0"), Some("3.0"), Some("4.0")]);
+        assert_eq!(from(Bound::Device("2.5".into())).seek(&index), Some(100));
+        assert_eq!(from(Bound::Device("3.0".into())).seek(&index), Some(100));
+        assert_eq!(from(Bound::Device("3.5".into())).seek(&index), Some(200));
+    }
+
+    #[test]
+    fn seek_outside_the_index() {
+        let index = index(&[Some("1.0"), Some("2.0")]);
+        assert_eq!(from(Bound::Device("0.5".into())).seek(&index), None);
+        assert_eq!(from(Bound::Device("9.0".into())).seek(&index), Some(100));
+        assert_eq!(from(Bound::Device("1.0".into())).seek(&[]), None);
+    }
+
+    #[test]
+    fn seek_skips_the_entries_without_a_timestamp() {
+        let index = index(&[Some("1.0"), None, Some("3.0"), None]);
+        assert_eq!(from(Bound::Device("2.0".into())).seek(&index), Some(100));
+        assert_eq!(from(Bound::Device("5.0".into())).seek(&index), Some(300));
+    }
+
+    #[test]
+    fn seek_by_host_time() {
+        let index = index(&[None, None, None]);
+        let window = from(Bound::Host(1_700_000_001.5));
+        assert_eq!(window.seek(&index), Some(100));
+    }
+
+    #[test]
+    fn seek_without_from() {
+        let index = index(&[Some("1.0"), Some("2.0")]);
+        let to = Bound::Device("1.5".into());
+        let window = Window::new(None, Some(&to), None).expect("a bound");
+        assert_eq!(window.seek(&index), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! `--max-line-width`, fitting long messages to the terminal.
+
+/// What to do with the rest of a line longer than `--max-line-width`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Continue on the next line, indented under the message
+    Wrap,
+    /// Cut it off with an ellipsis
+    Truncate,
+}
+
+/// Fits `message` into lines of `width` columns, the first one starting at column `indent`; the
+/// lines after it, including those of multi-line messages, are indented to line up with it.
+pub fn fit(message: &str, indent: usize, width: usize, overflow: Overflow) -> String {
+    // NOTE at least a few columns for the message, however long the prefix is
+    let columns = width.saturating_sub(indent).max(16);
+    let mut lines = Vec::new();
+
+    for line in message.lines() {
+        match overflow {
+            Overflow::Wrap => lines.extend(wrap(line, columns)),
+            Overflow::Truncate if line.chars().count() > columns => {
+                lines.push(line.chars().take(columns - 1).collect::<String>() + "…")
+            }
+            Overflow::Truncate => lines.push(line.to_string()),
+        }
+    }
+
+    lines.join(&format!("\n{:indent$}", ""))
+}
+
+/// Indents the lines of `message` after the first to column `indent`.
+pub fn indent(message: &str, indent: usize) -> String {
+    message
+        .lines()
+        .collect::<Vec<_>>()
+        .join(&format!("\n{:indent$}", ""))
+}
+
+/// Columns `text` takes in the terminal, without the ANSI escape sequences coloring it.
+pub fn columns(text: &str) -> usize {
+    let mut columns = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                chars.find(|c| c.is_ascii_alphabetic());
+            }
+            _ => columns += 1,
+        }
+    }
+    columns
+}
+
+/// Splits `line` into pieces of at most `columns` characters, at a space where possible.
+fn wrap(line: &str, columns: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut rest = line.trim_end();
+    while rest.chars().count() > columns {
+        let end = rest.char_indices().nth(columns).expect("longer").0;
+        let split = match rest[end..].starts_with(' ') {
+            true => end,
+            false => rest[..end]
+                .rfind(' ')
+                .filter(|&space| space > 0)
+                .unwrap_or(end),
+        };
+        pieces.push(rest[..split].trim_end().to_string());
+        rest = rest[split..].trim_start();
+    }
+    pieces.push(rest.to_string());
+    pieces
+}